@@ -0,0 +1,298 @@
+//! Headless commands that run without opening a window.
+//!
+//! `vimgame check [--strict] <level.json|dir>...` parses, validates and solves
+//! each level, then compares the solver's keystroke count against its par.
+//! The solver leaves out some long counts (see `Solution`), so a level can
+//! pass with a par that a cleverer run beats.
+//! A directory with a pack manifest also has the manifest checked.
+//!
+//! `vimgame generate [--difficulty easy|normal|hard] [--seed N] <command>...`
+//! prints a generated level training the given commands as JSON.
+
+use crate::generator::{self, Difficulty};
+use crate::level::Level;
+use crate::pack::{self, PackManifest};
+use crate::solver::Solver;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const USAGE: &str = "usage: vimgame check [--strict] <level.json|dir>...";
+const GENERATE_USAGE: &str =
+    "usage: vimgame generate [--difficulty easy|normal|hard] [--seed N] <command>...";
+
+/// Result of checking a single level file.
+#[derive(Debug, PartialEq)]
+enum Outcome {
+    Ok,
+    Failed,
+}
+
+/// Runs `vimgame check` and returns the process exit code.
+pub fn run_check(args: &[String]) -> i32 {
+    let mut strict = false;
+    let mut inputs = Vec::new();
+    for arg in args {
+        match arg.as_str() {
+            "--strict" => strict = true,
+            flag if flag.starts_with("--") => {
+                eprintln!("unknown option '{}'\n{}", flag, USAGE);
+                return 2;
+            }
+            path => inputs.push(PathBuf::from(path)),
+        }
+    }
+
+    let paths = match collect_paths(&inputs) {
+        Ok(paths) if !paths.is_empty() => paths,
+        Ok(_) => {
+            eprintln!("{}", USAGE);
+            return 2;
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            return 2;
+        }
+    };
+
+    let manifests: Vec<PathBuf> = inputs
+        .iter()
+        .map(|input| input.join(pack::MANIFEST_FILE))
+        .filter(|manifest| manifest.is_file())
+        .collect();
+    let failures = manifests
+        .iter()
+        .filter(|manifest| check_pack(manifest) == Outcome::Failed)
+        .count()
+        + paths
+            .iter()
+            .filter(|path| check_file(path, strict) == Outcome::Failed)
+            .count();
+
+    println!(
+        "\n{} checked, {} failed",
+        manifests.len() + paths.len(),
+        failures
+    );
+    if failures > 0 { 1 } else { 0 }
+}
+
+/// Runs `vimgame generate` and returns the process exit code. Without
+/// `--seed` the clock picks one, printed so the level can be made again.
+pub fn run_generate(args: &[String]) -> i32 {
+    let mut difficulty = Difficulty::Normal;
+    let mut seed = None;
+    let mut commands = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--difficulty" => match args.next().and_then(|d| Difficulty::from_name(d)) {
+                Some(d) => difficulty = d,
+                None => {
+                    eprintln!("{}", GENERATE_USAGE);
+                    return 2;
+                }
+            },
+            "--seed" => match args.next().and_then(|s| s.parse::<u64>().ok()) {
+                Some(s) => seed = Some(s),
+                None => {
+                    eprintln!("{}", GENERATE_USAGE);
+                    return 2;
+                }
+            },
+            flag if flag.starts_with("--") => {
+                eprintln!("unknown option '{}'\n{}", flag, GENERATE_USAGE);
+                return 2;
+            }
+            command => commands.push(command.to_string()),
+        }
+    }
+
+    let seed = seed.unwrap_or_else(|| {
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs())
+    });
+    match generator::generate(&commands, difficulty, seed) {
+        Ok(level) => match serde_json::to_string_pretty(&level) {
+            Ok(json) => {
+                eprintln!("seed {}: par {} keystrokes", seed, level.par_keystrokes);
+                println!("{}", json);
+                0
+            }
+            Err(e) => {
+                eprintln!("{}", e);
+                1
+            }
+        },
+        Err(e) => {
+            eprintln!("{}", e);
+            1
+        }
+    }
+}
+
+/// Expands directories into the sorted `.json` files they contain.
+fn collect_paths(inputs: &[PathBuf]) -> Result<Vec<PathBuf>, String> {
+    let mut paths = Vec::new();
+    for input in inputs {
+        if input.is_dir() {
+            let entries =
+                fs::read_dir(input).map_err(|e| format!("{}: {}", input.display(), e))?;
+            let mut found: Vec<PathBuf> = entries
+                .filter_map(|entry| entry.ok().map(|e| e.path()))
+                .filter(|p| p.extension().is_some_and(|ext| ext == "json"))
+                .filter(|p| p.file_name().is_some_and(|name| name != pack::MANIFEST_FILE))
+                .collect();
+            found.sort();
+            paths.extend(found);
+        } else {
+            paths.push(input.clone());
+        }
+    }
+    Ok(paths)
+}
+
+/// Checks a pack manifest, and that it lists exactly the levels beside it.
+fn check_pack(manifest_path: &Path) -> Outcome {
+    let json = match fs::read_to_string(manifest_path) {
+        Ok(json) => json,
+        Err(e) => {
+            println!("FAIL {}: {}", manifest_path.display(), e);
+            return Outcome::Failed;
+        }
+    };
+    let manifest = match PackManifest::from_json(&json) {
+        Ok(manifest) => manifest,
+        Err(errors) => {
+            println!("FAIL {}", manifest_path.display());
+            for error in errors {
+                println!("       {}", error);
+            }
+            return Outcome::Failed;
+        }
+    };
+
+    let dir = manifest_path.parent().unwrap_or(Path::new("."));
+    let mut problems: Vec<String> = manifest
+        .levels
+        .iter()
+        .filter(|file| !dir.join(file).is_file())
+        .map(|file| format!("{} is listed but missing", file))
+        .collect();
+    if let Ok(found) = collect_paths(&[dir.to_path_buf()]) {
+        for path in found {
+            let file = path.file_name().unwrap_or_default().to_string_lossy();
+            if !manifest.levels.iter().any(|listed| *listed == file) {
+                problems.push(format!("{} is not listed, so the game won't show it", file));
+            }
+        }
+    }
+
+    if problems.is_empty() {
+        println!(
+            "ok   {} ({}): {} levels",
+            manifest_path.display(),
+            manifest.name,
+            manifest.levels.len()
+        );
+        Outcome::Ok
+    } else {
+        println!("FAIL {} ({})", manifest_path.display(), manifest.name);
+        for problem in problems {
+            println!("       {}", problem);
+        }
+        Outcome::Failed
+    }
+}
+
+fn check_file(path: &Path, strict: bool) -> Outcome {
+    let json = match fs::read_to_string(path) {
+        Ok(json) => json,
+        Err(e) => {
+            println!("FAIL {}: {}", path.display(), e);
+            return Outcome::Failed;
+        }
+    };
+    let level = match Level::from_json(&json) {
+        Ok(level) => level,
+        Err(errors) => {
+            println!("FAIL {}", path.display());
+            for error in errors {
+                println!("       {}", error);
+            }
+            return Outcome::Failed;
+        }
+    };
+    check_level(&path.display().to_string(), &level, strict)
+}
+
+/// Solves an already-validated level and compares the result against par.
+fn check_level(label: &str, level: &Level, strict: bool) -> Outcome {
+    let solution = match Solver::new(level.clone()).solve() {
+        Ok(solution) => solution,
+        Err(error) => {
+            println!("FAIL {} ({}): {}", label, level.name, error);
+            return Outcome::Failed;
+        }
+    };
+
+    let par = level.par_keystrokes;
+    let (status, outcome, note) = if solution.keystrokes > par {
+        ("FAIL", Outcome::Failed, " - par is below the solver's best")
+    } else if solution.keystrokes < par && strict {
+        ("FAIL", Outcome::Failed, " - par is above the solver's best")
+    } else {
+        ("ok  ", Outcome::Ok, "")
+    };
+    let keys: Vec<String> = solution.commands.iter().map(|c| c.to_keys()).collect();
+    println!(
+        "{} {} ({}): {} keystrokes, par {}{}",
+        status, label, level.name, solution.keystrokes, par, note
+    );
+    println!("       {}", keys.join(" "));
+    outcome
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::level::{Goal, KeyPolicy, Position};
+
+    fn test_level(par_keystrokes: u32) -> Level {
+        Level {
+            id: "test".to_string(),
+            name: "Test".to_string(),
+            description: "Test".to_string(),
+            layout: vec!["#S...E#".to_string()],
+            start_pos: Position { x: 1, y: 0 },
+            target_pos: Position { x: 5, y: 0 },
+            goal: Goal::Reach,
+            allowed_keys: vec!["l".to_string(), "$".to_string()],
+            key_policy: KeyPolicy::Reject,
+            undo_penalty: 0,
+            trained_commands: vec![],
+            tutorial_text: String::new(),
+            par_time: 10.0,
+            par_keystrokes,
+        }
+    }
+
+    #[test]
+    fn test_check_level_par() {
+        assert_eq!(check_level("test", &test_level(1), false), Outcome::Ok);
+        assert_eq!(check_level("test", &test_level(0), false), Outcome::Failed);
+        assert_eq!(check_level("test", &test_level(3), false), Outcome::Ok);
+        assert_eq!(check_level("test", &test_level(3), true), Outcome::Failed);
+    }
+
+    #[test]
+    fn test_bundled_levels_pass() {
+        let manifest = Path::new(pack::BUILTIN_PACK_DIR).join(pack::MANIFEST_FILE);
+        assert_eq!(check_pack(&manifest), Outcome::Ok);
+        let paths = collect_paths(&[PathBuf::from(pack::BUILTIN_PACK_DIR)]).unwrap();
+        assert!(!paths.is_empty());
+        for path in paths {
+            assert_eq!(check_file(&path, true), Outcome::Ok, "{}", path.display());
+        }
+    }
+}
@@ -0,0 +1,519 @@
+//! Daily challenge: the date picks a bundled level and remixes it (mirrored,
+//! fresh letters in its words), and every day's result is kept for the
+//! calendar and streaks.
+
+use crate::generator::{self, Rng, TrainedCheck};
+use crate::input::{InputHandler, VimCommand};
+use crate::level::{Goal, Level, Position};
+use crate::solver::{Search, Solution, Solver};
+use crate::storage;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+const STORAGE_KEY: &str = "daily";
+
+/// Remixes tried before giving up on a day.
+const MAX_ATTEMPTS: u32 = 20;
+
+/// Trained commands that read text left to right. A mirrored sentence,
+/// section or indent is no longer one, so their levels aren't mirrored.
+const READS_FORWARD: &[&str] = &["(", ")", "[[", "]]", "[]", "][", "^", "g_", "_", "+", "-"];
+
+/// A calendar date (UTC), counted in days since 1970-01-01.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Date {
+    pub year: i32,
+    pub month: u32,
+    pub day: u32,
+}
+
+impl Date {
+    pub fn today() -> Self {
+        let seconds = macroquad::miniquad::date::now();
+        Self::from_days((seconds / 86_400.0).floor() as i64)
+    }
+
+    // Howard Hinnant's days_from_civil / civil_from_days
+    pub fn from_days(days: i64) -> Self {
+        let z = days + 719_468;
+        let era = z.div_euclid(146_097);
+        let doe = z - era * 146_097;
+        let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+        let year = (yoe + era * 400 + i64::from(month <= 2)) as i32;
+        Self { year, month, day }
+    }
+
+    pub fn days(self) -> i64 {
+        let year = i64::from(self.year) - i64::from(self.month <= 2);
+        let era = year.div_euclid(400);
+        let yoe = year - era * 400;
+        let month = i64::from(self.month);
+        let doy = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5
+            + i64::from(self.day)
+            - 1;
+        let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+        era * 146_097 + doe - 719_468
+    }
+
+    pub fn offset(self, days: i64) -> Self {
+        Self::from_days(self.days() + days)
+    }
+
+    /// 0 for Monday through 6 for Sunday.
+    pub fn weekday(self) -> u32 {
+        // 1970-01-01 was a Thursday
+        (self.days() + 3).rem_euclid(7) as u32
+    }
+
+    /// The first day of the month `months` away from this one.
+    pub fn add_months(self, months: i32) -> Self {
+        let index = self.year * 12 + self.month as i32 - 1 + months;
+        Self {
+            year: index.div_euclid(12),
+            month: index.rem_euclid(12) as u32 + 1,
+            day: 1,
+        }
+    }
+
+    pub fn days_in_month(self) -> u32 {
+        (self.add_months(1).days() - self.add_months(0).days()) as u32
+    }
+
+    /// `YYYY-MM-DD`, used as the storage key.
+    pub fn key(self) -> String {
+        format!("{:04}-{:02}-{:02}", self.year, self.month, self.day)
+    }
+}
+
+/// A day's level, remixed from a bundled one.
+#[derive(Debug, Clone)]
+pub struct DailyChallenge {
+    pub date: Date,
+    /// Its `par_keystrokes` is the day's keystroke budget.
+    pub level: Level,
+    pub source_name: String,
+    pub mirrored: bool,
+}
+
+impl DailyChallenge {
+    pub fn budget(&self) -> u32 {
+        self.level.par_keystrokes
+    }
+}
+
+/// Builds the challenge for a date from bundled levels. Every player gets
+/// the same one for the same date. It works a few solver states at a time,
+/// so the calendar keeps drawing while remixes are tried.
+pub struct ChallengeBuilder {
+    pub date: Date,
+    candidates: Vec<Level>,
+    rng: Rng,
+    attempts: u32,
+    // The remix being tried, its par still unset
+    remix: Option<(DailyChallenge, Stage)>,
+}
+
+enum Stage {
+    // Finding the remix's optimum
+    Solving(Search),
+    // Solving it again without its trained commands, which the optimum uses
+    Checking(Solution, Search),
+}
+
+impl ChallengeBuilder {
+    pub fn new(date: Date, levels: &[Level]) -> Self {
+        // Text goals spell real words, which a remix would scramble
+        let candidates = levels
+            .iter()
+            .filter(|level| level.goal == Goal::Reach)
+            .cloned()
+            .collect();
+        Self {
+            date,
+            candidates,
+            rng: Rng::new(date.days() as u64),
+            attempts: 0,
+            remix: None,
+        }
+    }
+
+    /// Explores up to `budget` solver states. None while still working;
+    /// then the challenge, or `Some(None)` if no remix could be solved.
+    pub fn step(&mut self, budget: usize) -> Option<Option<DailyChallenge>> {
+        let (challenge, stage) = match self.remix.take() {
+            Some(remix) => remix,
+            None => {
+                if self.candidates.is_empty() || self.attempts == MAX_ATTEMPTS {
+                    return Some(None);
+                }
+                self.attempts += 1;
+                let challenge = self.next_remix();
+                let search = Solver::new(challenge.level.clone()).search();
+                (challenge, Stage::Solving(search))
+            }
+        };
+        // A remix that fails is dropped, and the next call tries another
+        match stage {
+            Stage::Solving(mut search) => match search.step(budget) {
+                None => self.remix = Some((challenge, Stage::Solving(search))),
+                Some(Ok(solution)) => {
+                    // The remix must still teach what its level was made for
+                    let trained: Vec<VimCommand> = challenge
+                        .level
+                        .trained_commands
+                        .iter()
+                        .map(|name| InputHandler::from_string(name))
+                        .collect();
+                    let check = if trained.is_empty() {
+                        TrainedCheck::Needed
+                    } else {
+                        generator::check_trained(&challenge.level, &solution, &trained)
+                    };
+                    match check {
+                        TrainedCheck::Needed => {
+                            return Some(Some(with_budget(challenge, &solution)));
+                        }
+                        TrainedCheck::NotNeeded => {}
+                        TrainedCheck::Solve(without) => {
+                            let search = Solver::new(*without).search();
+                            self.remix = Some((challenge, Stage::Checking(solution, search)));
+                        }
+                    }
+                }
+                Some(Err(_)) => {}
+            },
+            Stage::Checking(solution, mut search) => match search.step(budget) {
+                None => self.remix = Some((challenge, Stage::Checking(solution, search))),
+                Some(other) => {
+                    if generator::still_needed(&solution, other) {
+                        return Some(Some(with_budget(challenge, &solution)));
+                    }
+                }
+            },
+        }
+        None
+    }
+
+    fn next_remix(&mut self) -> DailyChallenge {
+        let source = &self.candidates[self.rng.range(0, self.candidates.len() - 1)];
+        let mirrored = self.rng.range(0, 1) == 1
+            && !source
+                .trained_commands
+                .iter()
+                .any(|name| READS_FORWARD.contains(&name.as_str()));
+        let mut level = source.clone();
+        if mirrored {
+            mirror(&mut level);
+        }
+        reletter(&mut level, &mut self.rng);
+        level.id = format!("daily_{}", self.date.key());
+        level.name = format!("Daily {}: {}", self.date.key(), source.name);
+        DailyChallenge {
+            date: self.date,
+            level,
+            source_name: source.name.clone(),
+            mirrored,
+        }
+    }
+}
+
+/// Sets the day's keystroke budget from the remix's optimum, with a little
+/// slack so the budget isn't only for experts.
+fn with_budget(mut challenge: DailyChallenge, solution: &Solution) -> DailyChallenge {
+    challenge.level.par_keystrokes = solution.keystrokes + (solution.keystrokes / 5).max(1);
+    challenge
+}
+
+/// Flips the level left to right. Brackets turn around too, so pairs still
+/// open before they close.
+fn mirror(level: &mut Level) {
+    let width = level.width();
+    let turn = |c: char| match c {
+        '(' => ')',
+        ')' => '(',
+        '[' => ']',
+        ']' => '[',
+        '{' => '}',
+        '}' => '{',
+        _ => c,
+    };
+    for row in &mut level.layout {
+        *row = row.chars().rev().map(turn).collect();
+    }
+    let flip = |pos: Position| Position {
+        x: width - 1 - pos.x,
+        y: pos.y,
+    };
+    level.start_pos = flip(level.start_pos);
+    level.target_pos = flip(level.target_pos);
+}
+
+/// Swaps the lowercase letters for others, one letter for another across
+/// the whole layout, so `f` and `/` targets change while the words stay
+/// where they are and a repeated word still repeats.
+fn reletter(level: &mut Level, rng: &mut Rng) {
+    let mut letters: Vec<char> = ('a'..='z').collect();
+    rng.shuffle(&mut letters);
+    for row in &mut level.layout {
+        *row = row
+            .chars()
+            .map(|c| {
+                if c.is_ascii_lowercase() {
+                    letters[(c as u8 - b'a') as usize]
+                } else {
+                    c
+                }
+            })
+            .collect();
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DailyResult {
+    pub keystrokes: u32,
+    pub budget: u32,
+    pub time: f32,
+    pub score: i32,
+}
+
+impl DailyResult {
+    pub fn within_budget(&self) -> bool {
+        self.keystrokes <= self.budget
+    }
+}
+
+/// Each day's best result, keyed by `Date::key`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct DailyHistory {
+    #[serde(default)]
+    days: BTreeMap<String, DailyResult>,
+}
+
+impl DailyHistory {
+    /// Loads saved history. Missing or unreadable data starts afresh.
+    pub fn load() -> Self {
+        let Some(json) = storage::load(STORAGE_KEY) else {
+            return Self::default();
+        };
+        serde_json::from_str(&json).unwrap_or_else(|e| {
+            eprintln!("Ignoring unreadable daily history: {}", e);
+            Self::default()
+        })
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        let json = serde_json::to_string_pretty(self).map_err(|e| e.to_string())?;
+        storage::save(STORAGE_KEY, &json)
+    }
+
+    pub fn get(&self, date: Date) -> Option<&DailyResult> {
+        self.days.get(&date.key())
+    }
+
+    /// Records a finished run, keeping the day's fewest keystrokes.
+    /// Returns true if it is the day's new best.
+    pub fn record(&mut self, date: Date, result: DailyResult) -> bool {
+        let Some(best) = self.days.get_mut(&date.key()) else {
+            self.days.insert(date.key(), result);
+            return true;
+        };
+        let better = result.keystrokes < best.keystrokes
+            || (result.keystrokes == best.keystrokes && result.time < best.time);
+        if better {
+            *best = result;
+        }
+        better
+    }
+
+    /// Days in a row with a finished challenge, up to `today`. A streak
+    /// still counts while today's challenge is yet to be played.
+    pub fn streak(&self, today: Date) -> u32 {
+        let start = if self.get(today).is_some() {
+            today
+        } else {
+            today.offset(-1)
+        };
+        (0..)
+            .map(|i| start.offset(-i))
+            .take_while(|date| self.get(*date).is_some())
+            .count() as u32
+    }
+
+    pub fn best_streak(&self) -> u32 {
+        let mut best = 0;
+        let mut run = 0;
+        let mut previous: Option<i64> = None;
+        for key in self.days.keys() {
+            let Some(days) = parse_key(key).map(Date::days) else {
+                continue;
+            };
+            run = if previous == Some(days - 1) { run + 1 } else { 1 };
+            best = best.max(run);
+            previous = Some(days);
+        }
+        best
+    }
+}
+
+fn parse_key(key: &str) -> Option<Date> {
+    let mut parts = key.splitn(3, '-');
+    Some(Date {
+        year: parts.next()?.parse().ok()?,
+        month: parts.next()?.parse().ok()?,
+        day: parts.next()?.parse().ok()?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::level::KeyPolicy;
+
+    fn date(year: i32, month: u32, day: u32) -> Date {
+        Date { year, month, day }
+    }
+
+    fn result(keystrokes: u32) -> DailyResult {
+        DailyResult {
+            keystrokes,
+            budget: 10,
+            time: 5.0,
+            score: 900,
+        }
+    }
+
+    #[test]
+    fn test_dates() {
+        assert_eq!(Date::from_days(0), date(1970, 1, 1));
+        assert_eq!(date(2024, 2, 29).offset(1), date(2024, 3, 1));
+        assert_eq!(date(2026, 10, 17).days(), 20_743);
+        assert_eq!(Date::from_days(20_743), date(2026, 10, 17));
+        assert_eq!(date(2026, 10, 17).weekday(), 5, "a Saturday");
+        assert_eq!(date(2026, 12, 5).add_months(1), date(2027, 1, 1));
+        assert_eq!(date(2026, 1, 5).add_months(-1), date(2025, 12, 1));
+        assert_eq!(date(2024, 2, 10).days_in_month(), 29);
+        assert_eq!(date(2026, 1, 2).key(), "2026-01-02");
+        assert_eq!(parse_key("2026-01-02"), Some(date(2026, 1, 2)));
+    }
+
+    fn challenge(date: Date, levels: &[Level]) -> Option<DailyChallenge> {
+        let mut builder = ChallengeBuilder::new(date, levels);
+        loop {
+            if let Some(challenge) = builder.step(10) {
+                return challenge;
+            }
+        }
+    }
+
+    #[test]
+    fn test_challenge_is_the_same_all_day() {
+        let level = Level {
+            id: "words".to_string(),
+            name: "Words".to_string(),
+            description: String::new(),
+            layout: vec!["#S abc ~~ def E#".to_string()],
+            start_pos: Position { x: 1, y: 0 },
+            target_pos: Position { x: 14, y: 0 },
+            goal: Goal::Reach,
+            allowed_keys: vec!["w".to_string(), "b".to_string()],
+            key_policy: KeyPolicy::Reject,
+            undo_penalty: 50,
+            trained_commands: vec![],
+            tutorial_text: String::new(),
+            par_time: 10.0,
+            par_keystrokes: 4,
+        };
+        let today = date(2026, 10, 17);
+        let a = challenge(today, std::slice::from_ref(&level)).unwrap();
+        let b = challenge(today, std::slice::from_ref(&level)).unwrap();
+        assert_eq!(a.level.layout, b.level.layout);
+        assert_eq!(a.level.id, "daily_2026-10-17");
+        assert!(a.level.validate().is_empty());
+
+        // Walls, water and the S/E glyphs stay put, mirrored or not
+        let shape = |layout: &[String]| -> String {
+            layout[0]
+                .chars()
+                .map(|c| if c.is_ascii_lowercase() { 'a' } else { c })
+                .collect()
+        };
+        let expected = if a.mirrored {
+            "#E aaa ~~ aaa S#"
+        } else {
+            "#S aaa ~~ aaa E#"
+        };
+        assert_eq!(shape(&a.level.layout), expected);
+
+        // The budget is verified by the solver, with some slack
+        let optimum = Solver::new(a.level.clone()).solve().unwrap().keystrokes;
+        assert!(a.budget() > optimum);
+
+        // A remix that no longer needs the trained commands is no use
+        let mut idle = level.clone();
+        idle.layout = vec!["#S....E#".to_string()];
+        idle.target_pos = Position { x: 6, y: 0 };
+        idle.allowed_keys = ["h", "j", "l"].map(String::from).to_vec();
+        idle.trained_commands = vec!["j".to_string()];
+        assert!(challenge(today, &[idle]).is_none());
+    }
+
+    #[test]
+    fn test_remixes() {
+        let mut level = Level {
+            id: "code".to_string(),
+            name: "Code".to_string(),
+            description: String::new(),
+            layout: vec!["#S fire(x) { fire[y] } E#".to_string()],
+            start_pos: Position { x: 1, y: 0 },
+            target_pos: Position { x: 23, y: 0 },
+            goal: Goal::Reach,
+            allowed_keys: vec![],
+            key_policy: KeyPolicy::Reject,
+            undo_penalty: 50,
+            trained_commands: vec![],
+            tutorial_text: String::new(),
+            par_time: 10.0,
+            par_keystrokes: 4,
+        };
+        mirror(&mut level);
+        assert_eq!(level.layout[0], "#E { [y]erif } (x)erif S#");
+        assert_eq!(level.start_pos, Position { x: 23, y: 0 });
+
+        // One letter stands in for another everywhere, so words still repeat
+        reletter(&mut level, &mut Rng::new(7));
+        let words: Vec<&str> = level.layout[0]
+            .split(|c: char| !c.is_ascii_lowercase())
+            .filter(|word| word.len() == 4)
+            .collect();
+        assert_eq!(words.len(), 2);
+        assert_eq!(words[0], words[1]);
+        assert_ne!(words[0], "erif");
+    }
+
+    #[test]
+    fn test_history_and_streaks() {
+        let mut history = DailyHistory::default();
+        let today = date(2026, 3, 2);
+        assert!(history.record(today.offset(-2), result(12)));
+        assert!(history.record(today.offset(-1), result(9)));
+        assert!(!history.record(today.offset(-1), result(11)));
+        assert!(history.get(today.offset(-1)).unwrap().within_budget());
+        assert!(!history.get(today.offset(-2)).unwrap().within_budget());
+
+        // Today isn't played yet, so the streak carries on from yesterday
+        assert_eq!(history.streak(today), 2);
+        history.record(today, result(8));
+        assert_eq!(history.streak(today), 3);
+        assert_eq!(history.streak(today.offset(2)), 0);
+
+        history.record(date(2026, 1, 10), result(5));
+        assert_eq!(history.best_streak(), 3);
+
+        let json = serde_json::to_string(&history).unwrap();
+        assert_eq!(serde_json::from_str::<DailyHistory>(&json).unwrap(), history);
+    }
+}
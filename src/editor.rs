@@ -0,0 +1,600 @@
+//! Level editor: paint a layout with Vim-style keys, set the level's other
+//! fields with `:` commands, and see the solver's verdict as you go.
+
+use crate::level::{Goal, KeyPolicy, Level, LevelError, Position};
+use crate::pack;
+use crate::solver::{FRAME_BUDGET, Search, Solution, SolveError, Solver};
+use macroquad::input::KeyCode;
+
+/// Seconds without an edit before the solver runs again.
+const SOLVE_DELAY: f32 = 0.4;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum EditorMode {
+    Normal,
+    /// `r` was pressed; the next character paints the cursor's cell.
+    Replace,
+    /// Typed characters overwrite cells left to right, for words.
+    Insert,
+    /// A `:` command being typed.
+    Command(String),
+}
+
+/// The solver's verdict on the level as currently drawn.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Analysis {
+    /// The level changed and hasn't been solved since.
+    Pending,
+    Invalid(Vec<LevelError>),
+    Unsolved(SolveError),
+    Solved(Solution),
+}
+
+pub struct Editor {
+    pub level: Level,
+    /// Where `:w` writes; None until the level has a file.
+    pub path: Option<String>,
+    /// Directory a new level is saved into.
+    dir: String,
+    pub cursor: Position,
+    pub mode: EditorMode,
+    pub analysis: Analysis,
+    /// Feedback from the last command, shown on the status line.
+    pub message: String,
+    pub modified: bool,
+    /// Set once a save succeeds, so the menu knows to reload.
+    pub saved: bool,
+    /// Set by `:q`; the caller closes the editor.
+    pub quit: bool,
+    undo_stack: Vec<Level>,
+    idle_time: f32,
+    // The solver run under way, a few states each frame
+    search: Option<Search>,
+}
+
+impl Editor {
+    /// Opens an existing level, saved back to `path`.
+    pub fn open(level: Level, path: &str) -> Self {
+        let dir = path.rsplit_once('/').map_or(".", |(dir, _)| dir).to_string();
+        let mut editor = Self::with_level(level, dir);
+        editor.path = Some(path.to_string());
+        editor
+    }
+
+    /// Starts a small blank level, saved into `dir` as `<id>.json`.
+    pub fn create(dir: &str) -> Self {
+        let level = Level {
+            id: "custom_level".to_string(),
+            name: "Untitled".to_string(),
+            description: String::new(),
+            layout: vec![
+                "############".to_string(),
+                "#S........E#".to_string(),
+                "############".to_string(),
+            ],
+            start_pos: Position { x: 1, y: 1 },
+            target_pos: Position { x: 10, y: 1 },
+            goal: Goal::Reach,
+            allowed_keys: vec![],
+            key_policy: KeyPolicy::Reject,
+            undo_penalty: 50,
+            trained_commands: vec![],
+            tutorial_text: String::new(),
+            par_time: 30.0,
+            par_keystrokes: 0,
+        };
+        Self::with_level(level, dir.trim_end_matches('/').to_string())
+    }
+
+    fn with_level(level: Level, dir: String) -> Self {
+        Self {
+            cursor: level.start_pos,
+            level,
+            path: None,
+            dir,
+            mode: EditorMode::Normal,
+            analysis: Analysis::Pending,
+            message: String::new(),
+            modified: false,
+            saved: false,
+            quit: false,
+            undo_stack: Vec::new(),
+            idle_time: 0.0,
+            search: None,
+        }
+    }
+
+    /// Starts the solver once the level has been left alone for a moment,
+    /// then gives it a slice of each frame until it is done.
+    pub fn update(&mut self, dt: f32) {
+        if let Some(search) = &mut self.search {
+            if let Some(result) = search.step(FRAME_BUDGET) {
+                self.search = None;
+                self.analysis = match result {
+                    Ok(solution) => Analysis::Solved(solution),
+                    Err(error) => Analysis::Unsolved(error),
+                };
+            }
+        } else if self.analysis == Analysis::Pending {
+            self.idle_time += dt;
+            if self.idle_time >= SOLVE_DELAY {
+                self.analyze();
+            }
+        }
+    }
+
+    /// Validates the level and, if it is valid, starts solving it.
+    pub fn analyze(&mut self) {
+        let errors = self.level.validate();
+        if errors.is_empty() {
+            self.search = Some(Solver::new(self.level.clone()).search());
+        } else {
+            self.analysis = Analysis::Invalid(errors);
+        }
+    }
+
+    /// The solver's keystroke count, once it has found a solution.
+    pub fn suggested_par(&self) -> Option<u32> {
+        match &self.analysis {
+            Analysis::Solved(solution) => Some(solution.keystrokes),
+            _ => None,
+        }
+    }
+
+    pub fn handle_special_key(&mut self, key: KeyCode) {
+        match (&mut self.mode, key) {
+            (_, KeyCode::Escape) => {
+                self.mode = EditorMode::Normal;
+            }
+            // Backspace on an empty command line leaves it, as in Vim
+            (EditorMode::Command(line), KeyCode::Backspace) if line.is_empty() => {
+                self.mode = EditorMode::Normal;
+            }
+            (EditorMode::Command(line), KeyCode::Backspace) => {
+                line.pop();
+            }
+            (EditorMode::Command(line), KeyCode::Enter) => {
+                let line = std::mem::take(line);
+                self.mode = EditorMode::Normal;
+                self.run_command(&line);
+            }
+            (EditorMode::Insert, KeyCode::Backspace) => self.move_cursor(-1, 0),
+            (EditorMode::Normal | EditorMode::Insert, KeyCode::Left) => self.move_cursor(-1, 0),
+            (EditorMode::Normal | EditorMode::Insert, KeyCode::Right) => self.move_cursor(1, 0),
+            (EditorMode::Normal | EditorMode::Insert, KeyCode::Up) => self.move_cursor(0, -1),
+            (EditorMode::Normal | EditorMode::Insert, KeyCode::Down) => self.move_cursor(0, 1),
+            _ => {}
+        }
+    }
+
+    pub fn handle_char_input(&mut self, c: char) {
+        if c.is_control() {
+            return;
+        }
+        match &mut self.mode {
+            EditorMode::Command(line) => line.push(c),
+            EditorMode::Replace => {
+                self.mode = EditorMode::Normal;
+                self.paint(c);
+            }
+            EditorMode::Insert => {
+                self.paint(c);
+                self.move_cursor(1, 0);
+            }
+            EditorMode::Normal => self.handle_normal_char(c),
+        }
+    }
+
+    fn handle_normal_char(&mut self, c: char) {
+        match c {
+            'h' => self.move_cursor(-1, 0),
+            'j' => self.move_cursor(0, 1),
+            'k' => self.move_cursor(0, -1),
+            'l' => self.move_cursor(1, 0),
+            '0' => self.cursor.x = 0,
+            '$' => self.cursor.x = self.level.width() - 1,
+            'r' => self.mode = EditorMode::Replace,
+            'i' => self.mode = EditorMode::Insert,
+            'x' => self.paint('.'),
+            'S' | 'E' => self.paint(c),
+            'o' => self.insert_row(self.cursor.y + 1),
+            'O' => self.insert_row(self.cursor.y),
+            'D' => self.delete_row(),
+            '>' => self.widen(),
+            '<' => self.narrow(),
+            'u' => self.undo(),
+            ':' => self.mode = EditorMode::Command(String::new()),
+            _ => {}
+        }
+    }
+
+    fn move_cursor(&mut self, dx: isize, dy: isize) {
+        let x = self.cursor.x.saturating_add_signed(dx);
+        let y = self.cursor.y.saturating_add_signed(dy);
+        self.cursor = Position {
+            x: x.min(self.level.width() - 1),
+            y: y.min(self.level.height() - 1),
+        };
+    }
+
+    /// Records the level before a change, for `u`.
+    fn checkpoint(&mut self) {
+        self.undo_stack.push(self.level.clone());
+        self.modified = true;
+        self.analysis = Analysis::Pending;
+        self.idle_time = 0.0;
+        self.search = None;
+    }
+
+    fn undo(&mut self) {
+        match self.undo_stack.pop() {
+            Some(level) => {
+                self.level = level;
+                self.modified = true;
+                self.analysis = Analysis::Pending;
+                self.idle_time = 0.0;
+                self.search = None;
+                self.move_cursor(0, 0);
+            }
+            None => self.message = "Already at oldest change".to_string(),
+        }
+    }
+
+    /// Paints the cursor's cell. Painting `S` or `E` moves the start or
+    /// target there, clearing the old glyph.
+    fn paint(&mut self, c: char) {
+        if !(c.is_ascii_graphic() || c == ' ') {
+            self.message = format!("'{}' can't be drawn; use ASCII characters", c);
+            return;
+        }
+        let pos = self.cursor;
+        if cell(&self.level.layout, pos) == Some(c) {
+            return;
+        }
+        self.checkpoint();
+        match c {
+            'S' => {
+                let old = self.level.start_pos;
+                if cell(&self.level.layout, old) == Some('S') {
+                    set_cell(&mut self.level.layout, old, '.');
+                }
+                self.level.start_pos = pos;
+            }
+            'E' => {
+                let old = self.level.target_pos;
+                if cell(&self.level.layout, old) == Some('E') {
+                    set_cell(&mut self.level.layout, old, '.');
+                }
+                self.level.target_pos = pos;
+            }
+            _ => {}
+        }
+        set_cell(&mut self.level.layout, pos, c);
+    }
+
+    fn insert_row(&mut self, y: usize) {
+        self.checkpoint();
+        let width = self.level.width();
+        self.level.layout.insert(y, ".".repeat(width));
+        for pos in [&mut self.level.start_pos, &mut self.level.target_pos] {
+            if pos.y >= y {
+                pos.y += 1;
+            }
+        }
+        self.cursor.y = y;
+    }
+
+    fn delete_row(&mut self) {
+        let y = self.cursor.y;
+        if self.level.height() == 1 {
+            self.message = "Can't delete the only row".to_string();
+            return;
+        }
+        if self.level.start_pos.y == y || self.level.target_pos.y == y {
+            self.message = "Move S and E off this row first".to_string();
+            return;
+        }
+        self.checkpoint();
+        self.level.layout.remove(y);
+        for pos in [&mut self.level.start_pos, &mut self.level.target_pos] {
+            if pos.y > y {
+                pos.y -= 1;
+            }
+        }
+        self.move_cursor(0, 0);
+    }
+
+    /// Adds a column of floor on the right.
+    fn widen(&mut self) {
+        self.checkpoint();
+        for row in &mut self.level.layout {
+            row.push('.');
+        }
+    }
+
+    /// Removes the rightmost column.
+    fn narrow(&mut self) {
+        let last = self.level.width() - 1;
+        if last == 0 {
+            self.message = "Can't delete the only column".to_string();
+            return;
+        }
+        if self.level.start_pos.x == last || self.level.target_pos.x == last {
+            self.message = "Move S and E off the last column first".to_string();
+            return;
+        }
+        self.checkpoint();
+        for row in &mut self.level.layout {
+            row.truncate(last);
+        }
+        self.move_cursor(0, 0);
+    }
+
+    /// Runs a `:` command, e.g. `:name The Maze` or `:allow h j k l`.
+    fn run_command(&mut self, line: &str) {
+        let line = line.trim();
+        let (command, arg) = line.split_once(' ').unwrap_or((line, ""));
+        let arg = arg.trim();
+        let words = || arg.split_whitespace().map(str::to_string).collect::<Vec<_>>();
+        self.message.clear();
+        match command {
+            "w" => self.save((!arg.is_empty()).then_some(arg)),
+            "wq" => {
+                self.save((!arg.is_empty()).then_some(arg));
+                self.quit = !self.modified;
+            }
+            "q" if self.modified => {
+                self.message = "Unsaved changes (:w to save, :q! to discard)".to_string();
+            }
+            "q" | "q!" => self.quit = true,
+            "id" if !arg.is_empty() => {
+                self.checkpoint();
+                self.level.id = arg.to_string();
+            }
+            "name" => {
+                self.checkpoint();
+                self.level.name = arg.to_string();
+            }
+            "desc" => {
+                self.checkpoint();
+                self.level.description = arg.to_string();
+            }
+            "tutorial" => {
+                self.checkpoint();
+                self.level.tutorial_text = arg.replace("\\n", "\n");
+            }
+            "allow" => {
+                self.checkpoint();
+                self.level.allowed_keys = words();
+            }
+            "train" => {
+                self.checkpoint();
+                self.level.trained_commands = words();
+            }
+            "policy" => {
+                let policy = match arg {
+                    "reject" => KeyPolicy::Reject,
+                    "penalize" => KeyPolicy::Penalize,
+                    _ => {
+                        self.message = "Usage: :policy reject|penalize".to_string();
+                        return;
+                    }
+                };
+                self.checkpoint();
+                self.level.key_policy = policy;
+            }
+            "par" => {
+                let Some(par) = arg.parse().ok().or_else(|| self.suggested_par()) else {
+                    self.message = "No solution yet to take par from".to_string();
+                    return;
+                };
+                self.checkpoint();
+                self.level.par_keystrokes = par;
+            }
+            "partime" => match arg.parse::<f32>() {
+                Ok(seconds) if seconds > 0.0 => {
+                    self.checkpoint();
+                    self.level.par_time = seconds;
+                }
+                _ => self.message = "Usage: :partime <seconds>".to_string(),
+            },
+            "goal" => {
+                // A text goal is the layout as it stands; edit the start afterwards
+                let layout = self.level.layout.clone();
+                let goal = match arg {
+                    "reach" => Goal::Reach,
+                    "text" => Goal::Text { layout },
+                    "text_then_reach" => Goal::TextThenReach { layout },
+                    _ => {
+                        self.message = "Usage: :goal reach|text|text_then_reach".to_string();
+                        return;
+                    }
+                };
+                self.checkpoint();
+                self.level.goal = goal;
+            }
+            "" => {}
+            _ => self.message = format!("Not an editor command: {}", line),
+        }
+    }
+
+    pub fn to_json(&self) -> Result<String, String> {
+        serde_json::to_string_pretty(&self.level)
+            .map(|json| json + "\n")
+            .map_err(|e| e.to_string())
+    }
+
+    /// Saves to `path`, or to the level's own file. Refuses a level that
+    /// wouldn't load.
+    fn save(&mut self, path: Option<&str>) {
+        if let Some(error) = self.level.validate().first() {
+            self.message = format!("Not saved: {}", error);
+            return;
+        }
+        let path = path
+            .map(str::to_string)
+            .or_else(|| self.path.clone())
+            .unwrap_or_else(|| format!("{}/{}.json", self.dir, self.level.id));
+        let new_file = self.path.as_deref() != Some(path.as_str());
+        let result = self.to_json().and_then(|json| write_level(&path, &json));
+        match result {
+            Ok(()) => {
+                self.message = format!("Saved {}", path);
+                if let Analysis::Unsolved(_) = self.analysis {
+                    self.message.push_str(" (no solution yet)");
+                }
+                if new_file {
+                    self.message
+                        .push_str(&format!(" - list it in {} to play it", pack::MANIFEST_FILE));
+                }
+                self.path = Some(path);
+                self.modified = false;
+                self.saved = true;
+            }
+            Err(e) => self.message = format!("Not saved: {}", e),
+        }
+    }
+}
+
+fn cell(layout: &[String], pos: Position) -> Option<char> {
+    layout.get(pos.y)?.chars().nth(pos.x)
+}
+
+fn set_cell(layout: &mut [String], pos: Position, c: char) {
+    let row = &mut layout[pos.y];
+    *row = row
+        .chars()
+        .enumerate()
+        .map(|(x, old)| if x == pos.x { c } else { old })
+        .collect();
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn write_level(path: &str, json: &str) -> Result<(), String> {
+    std::fs::write(path, json).map_err(|e| format!("{}: {}", path, e))
+}
+
+// The browser can't write files, so levels go to storage keyed by file name
+#[cfg(target_arch = "wasm32")]
+fn write_level(path: &str, json: &str) -> Result<(), String> {
+    let name = path.rsplit('/').next().unwrap_or(path);
+    crate::storage::save(&format!("levels/{}", name.trim_end_matches(".json")), json)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn type_keys(editor: &mut Editor, keys: &str) {
+        for c in keys.chars() {
+            editor.handle_char_input(c);
+        }
+    }
+
+    fn command(editor: &mut Editor, line: &str) {
+        type_keys(editor, &format!(":{}", line));
+        editor.handle_special_key(KeyCode::Enter);
+    }
+
+    /// Lets frames go by until the solver has given its verdict.
+    fn settle(editor: &mut Editor) {
+        editor.update(SOLVE_DELAY);
+        while editor.search.is_some() {
+            editor.update(0.0);
+        }
+    }
+
+    #[test]
+    fn test_painting() {
+        let mut editor = Editor::create("levels");
+        // Cursor starts on S at (1, 1)
+        type_keys(&mut editor, "lr~lix_y");
+        editor.handle_special_key(KeyCode::Escape);
+        assert_eq!(editor.level.layout[1], "#S~x_y....E#");
+        assert_eq!(editor.cursor, Position { x: 6, y: 1 });
+
+        // Painting S moves the start and clears the old glyph
+        type_keys(&mut editor, "S");
+        assert_eq!(editor.level.layout[1], "#.~x_yS...E#");
+        assert_eq!(editor.level.start_pos, Position { x: 6, y: 1 });
+
+        type_keys(&mut editor, "u");
+        assert_eq!(editor.level.layout[1], "#S~x_y....E#");
+        assert_eq!(editor.level.start_pos, Position { x: 1, y: 1 });
+        type_keys(&mut editor, "hx");
+        assert_eq!(editor.level.layout[1], "#S~x_.....E#");
+    }
+
+    #[test]
+    fn test_resizing() {
+        let mut editor = Editor::create("levels");
+        type_keys(&mut editor, "O");
+        assert_eq!(editor.level.height(), 4);
+        assert_eq!(editor.level.start_pos, Position { x: 1, y: 2 });
+        assert_eq!(editor.level.target_pos, Position { x: 10, y: 2 });
+
+        type_keys(&mut editor, "jD");
+        assert_eq!(editor.level.height(), 4, "S and E's row is kept");
+        type_keys(&mut editor, "kD");
+        assert_eq!(editor.level.height(), 3);
+        assert_eq!(editor.level.start_pos, Position { x: 1, y: 1 });
+
+        type_keys(&mut editor, ">>");
+        assert_eq!(editor.level.width(), 14);
+        type_keys(&mut editor, "<");
+        assert_eq!(editor.level.width(), 13);
+        assert!(editor.level.validate().is_empty());
+    }
+
+    #[test]
+    fn test_commands_and_solving() {
+        let mut editor = Editor::create("levels");
+        command(&mut editor, "name Long Hall");
+        command(&mut editor, "allow l");
+        command(&mut editor, "tutorial Hold l.\\nOr don't.");
+        assert_eq!(editor.level.name, "Long Hall");
+        assert_eq!(editor.level.tutorial_text, "Hold l.\nOr don't.");
+
+        settle(&mut editor);
+        // 9l
+        assert_eq!(editor.suggested_par(), Some(2));
+        command(&mut editor, "par");
+        assert_eq!(editor.level.par_keystrokes, 2);
+
+        command(&mut editor, "allow h");
+        settle(&mut editor);
+        assert_eq!(editor.analysis, Analysis::Unsolved(SolveError::Unsolvable));
+
+        command(&mut editor, "allow jump");
+        settle(&mut editor);
+        assert!(matches!(editor.analysis, Analysis::Invalid(_)));
+
+        command(&mut editor, "q");
+        assert!(!editor.quit, "unsaved changes");
+        command(&mut editor, "frobnicate");
+        assert_eq!(editor.message, "Not an editor command: frobnicate");
+    }
+
+    #[test]
+    fn test_save_writes_a_loadable_level() {
+        let dir = std::env::temp_dir().join(format!("vimgame-editor-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut editor = Editor::create(dir.to_str().unwrap());
+        command(&mut editor, "id hall");
+
+        // A level that wouldn't load isn't written
+        type_keys(&mut editor, "r#");
+        command(&mut editor, "w");
+        assert!(editor.message.starts_with("Not saved"));
+        type_keys(&mut editor, "u");
+
+        command(&mut editor, "wq");
+        assert!(editor.quit);
+        let path = dir.join("hall.json");
+        assert_eq!(editor.path.as_deref(), path.to_str());
+        let level = Level::from_json(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(level.id, "hall");
+        assert_eq!(level.layout, editor.level.layout);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
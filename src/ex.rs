@@ -0,0 +1,325 @@
+//! Ex commands, typed after `:`: the registry behind parsing, completion
+//! and `:help`, and the history browsed with Up/Down.
+
+use std::fmt;
+
+/// Entries kept in the command-line history.
+const HISTORY_SIZE: usize = 50;
+
+pub struct ExSpec {
+    pub name: &'static str,
+    /// Shortest accepted abbreviation, as in Vim (`:q` for `:quit`).
+    pub short: &'static str,
+    pub usage: &'static str,
+    pub help: &'static str,
+}
+
+pub const COMMANDS: &[ExSpec] = &[
+    ExSpec {
+        name: "quit",
+        short: "q",
+        usage: ":quit",
+        help: "leave the level for the menu",
+    },
+    ExSpec {
+        name: "restart",
+        short: "res",
+        usage: ":restart",
+        help: "start the level over",
+    },
+    ExSpec {
+        name: "level",
+        short: "lev",
+        usage: ":level {N}",
+        help: "play level N of the pack, once it is unlocked",
+    },
+    ExSpec {
+        name: "set",
+        short: "se",
+        usage: ":set {option}",
+        help: "number, relativenumber, ignorecase or smartcase; no in front turns it off",
+    },
+    ExSpec {
+        name: "nohlsearch",
+        short: "noh",
+        usage: ":nohlsearch",
+        help: "hide the search highlighting until the next search",
+    },
+    ExSpec {
+        name: "help",
+        short: "h",
+        usage: ":help [command]",
+        help: "explain a command, or show the level's help",
+    },
+];
+
+/// Options for `:set`, with their short names.
+pub const OPTIONS: &[(&str, &str)] = &[
+    ("number", "nu"),
+    ("relativenumber", "rnu"),
+    ("ignorecase", "ic"),
+    ("smartcase", "scs"),
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExCommand {
+    /// `:{N}`
+    Goto(u32),
+    Quit,
+    Restart,
+    Level(usize),
+    Set(SetOption),
+    NoHighlight,
+    Help(Option<String>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SetOption {
+    Number(bool),
+    RelativeNumber(bool),
+    IgnoreCase(bool),
+    SmartCase(bool),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExError {
+    NotACommand(String),
+    ArgumentRequired,
+    InvalidArgument(String),
+    UnknownOption(String),
+    TrailingCharacters(String),
+}
+
+impl fmt::Display for ExError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Vim's own messages and numbers
+        match self {
+            ExError::NotACommand(line) => write!(f, "E492: Not an editor command: {}", line),
+            ExError::ArgumentRequired => write!(f, "E471: Argument required"),
+            ExError::InvalidArgument(arg) => write!(f, "E474: Invalid argument: {}", arg),
+            ExError::UnknownOption(option) => write!(f, "E518: Unknown option: {}", option),
+            ExError::TrailingCharacters(rest) => write!(f, "E488: Trailing characters: {}", rest),
+        }
+    }
+}
+
+/// The command `name` abbreviates, if any.
+pub fn lookup(name: &str) -> Option<&'static ExSpec> {
+    COMMANDS
+        .iter()
+        .find(|spec| spec.name.starts_with(name) && name.len() >= spec.short.len())
+}
+
+/// Parses a command line (without the `:`).
+pub fn parse(line: &str) -> Result<ExCommand, ExError> {
+    let line = line.trim().trim_start_matches(':').trim();
+    if let Ok(number) = line.parse() {
+        return Ok(ExCommand::Goto(number));
+    }
+    let (name, arg) = match line.split_once(char::is_whitespace) {
+        Some((name, arg)) => (name, arg.trim()),
+        None => (line, ""),
+    };
+    let spec = lookup(name).ok_or_else(|| ExError::NotACommand(line.to_string()))?;
+    let no_arg = |command: ExCommand| {
+        if arg.is_empty() {
+            Ok(command)
+        } else {
+            Err(ExError::TrailingCharacters(arg.to_string()))
+        }
+    };
+    match spec.name {
+        "quit" => no_arg(ExCommand::Quit),
+        "restart" => no_arg(ExCommand::Restart),
+        "level" => match arg.parse() {
+            Ok(number) if number > 0 => Ok(ExCommand::Level(number)),
+            _ if arg.is_empty() => Err(ExError::ArgumentRequired),
+            _ => Err(ExError::InvalidArgument(arg.to_string())),
+        },
+        "set" => parse_option(arg).map(ExCommand::Set),
+        "nohlsearch" => no_arg(ExCommand::NoHighlight),
+        _ => Ok(ExCommand::Help((!arg.is_empty()).then(|| arg.to_string()))),
+    }
+}
+
+fn parse_option(arg: &str) -> Result<SetOption, ExError> {
+    if arg.is_empty() {
+        return Err(ExError::ArgumentRequired);
+    }
+    let (on, name) = match arg.strip_prefix("no") {
+        Some(name) => (false, name),
+        None => (true, arg),
+    };
+    let option = OPTIONS
+        .iter()
+        .find(|(long, short)| name == *long || name == *short)
+        .map(|(long, _)| *long);
+    match option {
+        Some("number") => Ok(SetOption::Number(on)),
+        Some("relativenumber") => Ok(SetOption::RelativeNumber(on)),
+        Some("ignorecase") => Ok(SetOption::IgnoreCase(on)),
+        Some("smartcase") => Ok(SetOption::SmartCase(on)),
+        _ => Err(ExError::UnknownOption(arg.to_string())),
+    }
+}
+
+/// Completes a command line: the line extended as far as every match
+/// agrees, and the matches to show.
+pub fn complete(line: &str) -> (String, Vec<String>) {
+    let (head, word, choices): (String, &str, Vec<String>) = match line.split_once(' ') {
+        None => (
+            String::new(),
+            line,
+            COMMANDS.iter().map(|spec| spec.name.to_string()).collect(),
+        ),
+        Some((name, arg)) => {
+            let choices = match lookup(name).map(|spec| spec.name) {
+                Some("set") => OPTIONS
+                    .iter()
+                    .flat_map(|(option, _)| [option.to_string(), format!("no{}", option)])
+                    .collect(),
+                Some("help") => COMMANDS.iter().map(|spec| spec.name.to_string()).collect(),
+                _ => Vec::new(),
+            };
+            (format!("{} ", name), arg.trim_start(), choices)
+        }
+    };
+    let matches: Vec<String> = choices
+        .into_iter()
+        .filter(|choice| choice.starts_with(word))
+        .collect();
+    let Some(first) = matches.first() else {
+        return (line.to_string(), matches);
+    };
+    let common = matches.iter().fold(first.as_str(), |common, choice| {
+        let len = common
+            .chars()
+            .zip(choice.chars())
+            .take_while(|(a, b)| a == b)
+            .count();
+        &common[..len]
+    });
+    (format!("{}{}", head, common), matches)
+}
+
+/// Lines entered before, newest last. Browsing only visits lines that start
+/// with what was typed before the first Up, as in Vim.
+#[derive(Debug, Default)]
+pub struct History {
+    entries: Vec<String>,
+    // The entry shown, and the text typed before browsing started
+    browsing: Option<(usize, String)>,
+}
+
+impl History {
+    pub fn push(&mut self, line: &str) {
+        self.browsing = None;
+        let line = line.trim();
+        if line.is_empty() {
+            return;
+        }
+        self.entries.retain(|entry| entry != line);
+        self.entries.push(line.to_string());
+        if self.entries.len() > HISTORY_SIZE {
+            self.entries.remove(0);
+        }
+    }
+
+    /// Up: the previous matching line, if any.
+    pub fn older(&mut self, typed: &str) -> Option<String> {
+        let (index, prefix) = self
+            .browsing
+            .get_or_insert_with(|| (self.entries.len(), typed.to_string()));
+        let found = (0..*index)
+            .rev()
+            .find(|&i| self.entries[i].starts_with(prefix.as_str()))?;
+        *index = found;
+        Some(self.entries[found].clone())
+    }
+
+    /// Down: the next matching line, or what was typed once past the newest.
+    pub fn newer(&mut self) -> Option<String> {
+        let (index, prefix) = self.browsing.as_mut()?;
+        match (*index + 1..self.entries.len())
+            .find(|&i| self.entries[i].starts_with(prefix.as_str()))
+        {
+            Some(found) => {
+                *index = found;
+                Some(self.entries[found].clone())
+            }
+            None => self.browsing.take().map(|(_, typed)| typed),
+        }
+    }
+
+    /// Stops browsing, e.g. when the command line closes.
+    pub fn reset(&mut self) {
+        self.browsing = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(parse("12"), Ok(ExCommand::Goto(12)));
+        assert_eq!(parse(" q "), Ok(ExCommand::Quit));
+        assert_eq!(parse("res"), Ok(ExCommand::Restart));
+        assert_eq!(parse("lev 3"), Ok(ExCommand::Level(3)));
+        assert_eq!(parse("set nornu"), Ok(ExCommand::Set(SetOption::RelativeNumber(false))));
+        assert_eq!(parse("se number"), Ok(ExCommand::Set(SetOption::Number(true))));
+        assert_eq!(parse("set noic"), Ok(ExCommand::Set(SetOption::IgnoreCase(false))));
+        assert_eq!(parse("noh"), Ok(ExCommand::NoHighlight));
+        assert_eq!(parse("h"), Ok(ExCommand::Help(None)));
+        assert_eq!(parse("help w"), Ok(ExCommand::Help(Some("w".to_string()))));
+
+        assert_eq!(parse("re"), Err(ExError::NotACommand("re".to_string())));
+        assert_eq!(parse("level"), Err(ExError::ArgumentRequired));
+        assert_eq!(parse("level x"), Err(ExError::InvalidArgument("x".to_string())));
+        assert_eq!(parse("set list"), Err(ExError::UnknownOption("list".to_string())));
+        assert_eq!(parse("q now"), Err(ExError::TrailingCharacters("now".to_string())));
+        assert_eq!(
+            parse("wq").unwrap_err().to_string(),
+            "E492: Not an editor command: wq"
+        );
+    }
+
+    #[test]
+    fn test_complete() {
+        assert_eq!(complete("re"), ("restart".to_string(), vec!["restart".to_string()]));
+        assert_eq!(complete("set rel").0, "set relativenumber");
+        // Several matches: as far as they agree
+        let (line, matches) = complete("set no");
+        assert_eq!(line, "set no");
+        assert_eq!(
+            matches,
+            vec!["nonumber", "norelativenumber", "noignorecase", "nosmartcase"]
+        );
+        assert_eq!(complete("zz"), ("zz".to_string(), vec![]));
+        assert_eq!(complete("lev 1"), ("lev 1".to_string(), vec![]));
+    }
+
+    #[test]
+    fn test_history() {
+        let mut history = History::default();
+        history.push("set nu");
+        history.push("12");
+        history.push("set rnu");
+        history.push("12");
+        assert_eq!(history.older(""), Some("12".to_string()));
+        assert_eq!(history.older(""), Some("set rnu".to_string()));
+        assert_eq!(history.older(""), Some("set nu".to_string()));
+        assert_eq!(history.older(""), None);
+        assert_eq!(history.newer(), Some("set rnu".to_string()));
+        assert_eq!(history.newer(), Some("12".to_string()));
+        assert_eq!(history.newer(), Some(String::new()));
+        assert_eq!(history.newer(), None);
+
+        // Only lines starting with the typed text
+        assert_eq!(history.older("se"), Some("set rnu".to_string()));
+        assert_eq!(history.older("se"), Some("set nu".to_string()));
+        history.reset();
+        assert_eq!(history.older("1"), Some("12".to_string()));
+    }
+}
@@ -1,31 +1,115 @@
-use crate::input::{InputHandler, VimCommand};
-use crate::level::{Level, Position};
+use crate::ex::{self, ExCommand};
+use crate::input::{InputHandler, Operator, TextObject, VimCommand};
+use crate::level::{KeyPolicy, Level, Position};
+use crate::marks::{JumpList, Marks};
+use crate::pattern::{self, CaseOptions, Pattern};
+use crate::replay::{Replay, ReplayStep};
+use crate::viewport::Viewport;
 use std::collections::VecDeque;
 
+/// How long (in seconds) a blocked/penalized command stays visible in the UI.
+const BLOCKED_MESSAGE_DURATION: f32 = 1.5;
+
 #[derive(Debug, PartialEq, Clone)]
 pub enum InputMode {
     Normal,
     WaitingForChar(VimCommand), // Stores the pending command (e.g. StartFindNext)
     CommandLine(String, VimCommand), // Stores current input and type (StartSearchForward/Backward)
+    OperatorPending,                 // An operator (see `pending_operator`) waits for its motion
+    Insert(Position),                // Stores where Escape lands: the last cell typed over
+}
+
+impl InputMode {
+    /// Modes where keys are text (a char, a query, inserted text) rather than commands.
+    pub fn takes_text(&self) -> bool {
+        !matches!(self, InputMode::Normal | InputMode::OperatorPending)
+    }
+}
+
+/// An operator waiting for its motion, e.g. the `2d` in `2dw`.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct PendingOperator {
+    pub operator: Operator,
+    pub count: Option<u32>,
+    // 'i' or 'a' once a text object has been started (the `i` in `diw`)
+    pub object: Option<char>,
+}
+
+/// Text saved by `d`, `c`, `y` and `x`, written back by `p` and `P`.
+#[derive(Debug, PartialEq, Clone, Eq, Hash)]
+pub struct Register {
+    pub text: Vec<String>,
+    pub linewise: bool,
+}
+
+/// How a motion shapes the text an operator acts on.
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum MotionKind {
+    Exclusive,
+    Inclusive,
+    Linewise,
+}
+
+/// Cells an operator acts on, from `start` to `end` inclusive in reading order.
+#[derive(Debug, PartialEq, Clone, Copy)]
+struct TextRange {
+    start: Position,
+    end: Position,
+    linewise: bool,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum CharClass {
+    Blank,
+    Punctuation,
+    Word,
+}
+
+/// A line shown under the level, such as an ex command's reply.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Message {
+    Info(String),
+    Error(String),
 }
 
+#[derive(Debug, PartialEq, Clone, Copy)]
 pub enum GameStatus {
     Playing,
     LevelComplete,
     GameOver,
 }
 
+/// Everything a command can change, saved so `u` and `Ctrl-r` can restore it.
+#[derive(Debug, PartialEq, Clone)]
+struct Snapshot {
+    player_pos: Position,
+    layout: Vec<String>,
+    status: GameStatus,
+    last_find_command: Option<VimCommand>,
+    last_search_query: Option<String>,
+    search_direction_forward: bool,
+}
+
 pub struct GameState {
     pub player_pos: Position,
     pub current_level: Level,
+    /// The part of the level on screen. Covers the whole level until
+    /// `resize_viewport` is told the real screen size.
+    pub viewport: Viewport,
     pub status: GameStatus,
     pub time_elapsed: f32,
+    // Real seconds played, without terrain costs; recorded steps are timed by it
+    pub play_time: f32,
     pub keystrokes: u32,
     pub score: i32,
     pub show_help: bool,
     pub trained_commands: Vec<VimCommand>,
+    pub allowed_commands: Vec<VimCommand>,
+    pub blocked_command: Option<VimCommand>,
+    pub blocked_timer: f32,
     pub penalties: u32,
-    pub replay_queue: VecDeque<VimCommand>,
+    pub replay_queue: VecDeque<ReplayStep>,
+    // Seconds since auto-play started; each step runs once this reaches its time
     pub replay_timer: f32,
     pub is_auto_playing: bool,
     pub last_auto_command: Option<VimCommand>,
@@ -34,25 +118,72 @@ pub struct GameState {
     pub last_find_command: Option<VimCommand>,
     pub last_search_query: Option<String>,
     pub search_direction_forward: bool,
+    // hlsearch: the last search's matches are shown until `:noh`
+    pub highlight_search: bool,
+    // ignorecase/smartcase, set by the game loop, which keeps the options
+    pub case_options: CaseOptions,
+    // Count typed before a command that still waits for its argument (e.g. 2f)
+    pub pending_count: Option<u32>,
+    undo_stack: Vec<Snapshot>,
+    redo_stack: Vec<Snapshot>,
+    pub undos: u32,
+    // Practice mode lets the player undo out of GameOver
+    pub practice_mode: bool,
+    pub pending_operator: Option<PendingOperator>,
+    pub register: Option<Register>,
+    // Like Vim's, marks and the jumplist aren't rolled back by undo
+    pub marks: Marks,
+    pub jumps: JumpList,
+    // The player's commands so far, and the finished run once the level is won
+    pub recording: Vec<ReplayStep>,
+    pub finished_replay: Option<Replay>,
+    // Shown until the next command
+    pub message: Option<Message>,
+    // An ex command for the game loop to carry out (`:q`, `:level 3`, ...)
+    pub ex_request: Option<ExCommand>,
+    // A `*` or `#` search for the game loop to add to the search history
+    pub word_search: Option<String>,
+    level_hash: u64,
 }
 
 impl GameState {
     pub fn new(level: Level) -> Self {
+        let level_hash = level.fingerprint();
+        Self::with_level_hash(level, level_hash)
+    }
+
+    /// Like `new`, for callers that build many states of one level and
+    /// have already taken its `Level::fingerprint`.
+    pub fn with_level_hash(level: Level, level_hash: u64) -> Self {
         let trained_commands = level
             .trained_commands
             .iter()
             .map(|s| InputHandler::from_string(s))
             .collect();
 
+        // Keys that don't map to a command (e.g. "Enter") are ignored.
+        let allowed_commands = level
+            .allowed_keys
+            .iter()
+            .map(|s| InputHandler::from_string(s))
+            .filter(|cmd| *cmd != VimCommand::None)
+            .collect();
+
         Self {
             player_pos: level.start_pos,
+            viewport: Viewport::new(level.height(), level.width()),
+            level_hash,
             current_level: level,
             status: GameStatus::Playing,
             time_elapsed: 0.0,
+            play_time: 0.0,
             keystrokes: 0,
             score: 0,
             show_help: false,
             trained_commands,
+            allowed_commands,
+            blocked_command: None,
+            blocked_timer: 0.0,
             penalties: 0,
             replay_queue: VecDeque::new(),
             replay_timer: 0.0,
@@ -63,37 +194,77 @@ impl GameState {
             last_find_command: None,
             last_search_query: None,
             search_direction_forward: true,
+            highlight_search: true,
+            case_options: CaseOptions::default(),
+            pending_count: None,
+            undo_stack: Vec::new(),
+            redo_stack: Vec::new(),
+            undos: 0,
+            practice_mode: false,
+            pending_operator: None,
+            register: None,
+            marks: Marks::default(),
+            jumps: JumpList::default(),
+            recording: Vec::new(),
+            finished_replay: None,
+            message: None,
+            ex_request: None,
+            word_search: None,
+        }
+    }
+
+    /// Fits the viewport to a screen showing `rows` x `cols` cells.
+    pub fn resize_viewport(&mut self, rows: usize, cols: usize) {
+        if (self.viewport.rows, self.viewport.cols) == (rows.max(1), cols.max(1)) {
+            return;
         }
+        self.viewport.rows = rows.max(1);
+        self.viewport.cols = cols.max(1);
+        self.viewport
+            .clamp(self.current_level.height(), self.current_level.width());
+        self.viewport.follow(self.player_pos);
     }
 
     pub fn toggle_help(&mut self) {
         self.show_help = !self.show_help;
     }
 
-    pub fn start_auto_play(&mut self, commands: Vec<VimCommand>) {
-        self.replay_queue = VecDeque::from(commands);
+    /// Plays `steps` back, each at its recorded time. Solver solutions are
+    /// spaced out with `replay::paced`.
+    pub fn start_auto_play(&mut self, steps: Vec<ReplayStep>) {
+        self.replay_queue = VecDeque::from(steps);
         self.is_auto_playing = true;
         self.replay_timer = 0.0;
         self.last_auto_command = None;
     }
 
     pub fn update(&mut self, dt: f32) {
+        if self.blocked_timer > 0.0 {
+            self.blocked_timer -= dt;
+            if self.blocked_timer <= 0.0 {
+                self.blocked_command = None;
+            }
+        }
+
         match self.status {
             GameStatus::Playing => {
                 self.time_elapsed += dt;
+                self.play_time += dt;
 
                 if self.is_auto_playing {
                     self.replay_timer += dt;
-                    if self.replay_timer >= 0.8 {
-                        // Move every 0.8 seconds
-                        self.replay_timer = 0.0;
-                        if let Some(cmd) = self.replay_queue.pop_front() {
-                            self.last_auto_command = Some(cmd.clone());
-                            self.handle_command(cmd);
-                        } else {
-                            self.is_auto_playing = false;
-                            self.last_auto_command = None;
-                        }
+                    // Several steps can fall in one frame (e.g. fast typing)
+                    while self.status == GameStatus::Playing
+                        && let Some(step) = self
+                            .replay_queue
+                            .pop_front_if(|step| step.time <= self.replay_timer)
+                    {
+                        self.last_auto_command = Some(step.command.clone());
+                        self.handle_command(step.command);
+                    }
+                    if self.replay_queue.is_empty() && self.status == GameStatus::Playing {
+                        self.is_auto_playing = false;
+                        self.last_auto_command = None;
                     }
                 }
             }
@@ -104,72 +275,554 @@ impl GameState {
         }
     }
 
+    /// Returns true if the level's `allowed_keys` permit this command.
+    /// An empty allow list means every command is available.
+    /// An operator also needs its motion to be allowed; text objects and
+    /// `dd` only need the operator.
+    pub fn is_command_allowed(&self, command: &VimCommand) -> bool {
+        if self.allowed_commands.is_empty() || Self::is_meta_command(command) {
+            return true;
+        }
+        let motion_allowed = match command {
+            VimCommand::Counted(_, inner) => return self.is_command_allowed(inner),
+            VimCommand::Operate(_, motion) => match motion.as_ref() {
+                VimCommand::TextObject(_) | VimCommand::StartOperator(_) => true,
+                motion => self.is_command_allowed(motion),
+            },
+            _ => true,
+        };
+        motion_allowed && self.allowed_commands.contains(&command.base())
+    }
+
+    /// Adds a resolved command typed by the player to the recording.
+    /// Half-typed ones (`f`, `d`, `/`) are recorded once complete.
+    fn record(&mut self, command: &VimCommand) {
+        if !self.is_auto_playing && command.keystrokes() > 0 {
+            self.recording.push(ReplayStep {
+                time: self.play_time,
+                command: command.clone(),
+            });
+        }
+    }
+
+    /// Commands every level allows and never counts against training:
+    /// leaving a mode, undo/redo, text typed in insert mode, `zt`/`zz`/`zb`,
+    /// which scroll without moving the cursor, and `:` itself. A jump typed
+    /// after `:` is still a command the level must allow.
+    fn is_meta_command(command: &VimCommand) -> bool {
+        *command == VimCommand::StartCommandLine
+            || matches!(
+                command.base(),
+                VimCommand::None
+                    | VimCommand::Escape
+                    | VimCommand::Undo
+                    | VimCommand::Redo
+                    | VimCommand::InsertChar(_)
+                    | VimCommand::ScrollCursorTop
+                    | VimCommand::ScrollCursorCenter
+                    | VimCommand::ScrollCursorBottom
+            )
+    }
+
     pub fn handle_command(&mut self, command: VimCommand) {
+        self.message = None;
+
+        // In operator-pending mode the next command is the operator's motion
+        let command = if self.input_mode == InputMode::OperatorPending {
+            match self.compose_operator(command) {
+                Some(command) => command,
+                None => return,
+            }
+        } else {
+            command
+        };
+
+        if matches!(command.base(), VimCommand::Undo | VimCommand::Redo) {
+            self.record(&command);
+            self.handle_history(&command);
+            self.viewport.follow(self.player_pos);
+            return;
+        }
+
         if let GameStatus::Playing = self.status {
-            match &command {
-                VimCommand::MoveLeft => self.move_player(-1, 0),
-                VimCommand::MoveRight => self.move_player(1, 0),
-                VimCommand::MoveUp => self.move_player(0, -1),
-                VimCommand::MoveDown => self.move_player(0, 1),
-                VimCommand::MoveWordForward => self.move_word_forward(),
-                VimCommand::MoveWordBack => self.move_word_back(),
-                VimCommand::MoveWordEnd => self.move_word_end(),
-                VimCommand::MoveLineStart => self.move_line_start(),
-                VimCommand::MoveLineEnd => self.move_line_end(),
-                VimCommand::MoveScreenTop => self.move_screen_top(),
-                VimCommand::MoveScreenMiddle => self.move_screen_middle(),
-                VimCommand::MoveScreenBottom => self.move_screen_bottom(),
-                VimCommand::MoveParagraphForward => self.move_paragraph_forward(),
-                VimCommand::MoveParagraphBack => self.move_paragraph_back(),
-                VimCommand::StartFindNext
-                | VimCommand::StartFindPrev
-                | VimCommand::StartTillNext
-                | VimCommand::StartTillPrev => {
-                    self.input_mode = InputMode::WaitingForChar(command.clone());
-                }
-                VimCommand::StartSearchForward | VimCommand::StartSearchBackward => {
-                    self.input_mode = InputMode::CommandLine(String::new(), command.clone());
+            let allowed = self.is_command_allowed(&command);
+            if !allowed && self.current_level.key_policy == KeyPolicy::Reject {
+                self.blocked_command = Some(command);
+                self.blocked_timer = BLOCKED_MESSAGE_DURATION;
+                self.pending_operator = None;
+                return;
+            }
+
+            let before = self.snapshot();
+            self.record(&command);
+            self.remember_command(&command);
+            self.execute_command(&command);
+            if command.is_jump() && self.player_pos != before.player_pos {
+                self.jumps.record(before.player_pos);
+            }
+            self.viewport.follow(self.player_pos);
+
+            let cost = command.keystrokes();
+            if cost > 0 {
+                self.keystrokes += cost;
+
+                // Disallowed commands only get here under KeyPolicy::Penalize
+                if !allowed {
+                    self.penalties += 1;
+                    self.blocked_command = Some(command.clone());
+                    self.blocked_timer = BLOCKED_MESSAGE_DURATION;
                 }
-                VimCommand::FindNextChar(c) => self.find_char_forward(*c),
-                VimCommand::FindPrevChar(c) => self.find_char_backward(*c),
-                VimCommand::TillNextChar(c) => self.till_char_forward(*c),
-                VimCommand::TillPrevChar(c) => self.till_char_backward(*c),
-                VimCommand::RepeatFind => self.repeat_find(false),
-                VimCommand::RepeatFindReverse => self.repeat_find(true),
-                VimCommand::SearchForward(s) => self.search_forward(s),
-                VimCommand::SearchBackward(s) => self.search_backward(s),
-                VimCommand::NextMatch => self.next_match(),
-                VimCommand::PrevMatch => self.prev_match(),
-                VimCommand::DeleteChar => self.delete_char(),
-                _ => {} // Implement other commands later
-            }
-
-            if command != VimCommand::None
-                && !matches!(
-                    command,
-                    VimCommand::StartFindNext
-                        | VimCommand::StartFindPrev
-                        | VimCommand::StartTillNext
-                        | VimCommand::StartTillPrev
-                        | VimCommand::StartSearchForward
-                        | VimCommand::StartSearchBackward
-                )
-            {
-                self.keystrokes += 1;
 
                 // Check penalty
                 // Only penalize movement commands, not Escape/Insert etc if we had them
                 // For now, all handled commands are movement.
                 // If trained_commands is empty, no penalties (allow all).
-                if !self.trained_commands.is_empty() && !self.trained_commands.contains(&command) {
+                if !self.trained_commands.is_empty()
+                    && !Self::is_meta_command(&command)
+                    && !self.trained_commands.contains(&command.base())
+                {
                     self.penalties += 1;
                 }
             }
 
             self.check_win_condition();
+
+            // Commands that change nothing (e.g. bumping a wall) aren't worth undoing
+            if self.snapshot() != before {
+                self.undo_stack.push(before);
+                self.redo_stack.clear();
+            }
+        }
+    }
+
+    /// Combines a pending operator with the command typed after it. Returns
+    /// the command to run, or None while the operator is still waiting (or
+    /// was cancelled by a command that isn't a motion).
+    fn compose_operator(&mut self, command: VimCommand) -> Option<VimCommand> {
+        let mut pending = self.pending_operator.take()?;
+        self.input_mode = InputMode::Normal;
+
+        let (count, motion) = match command {
+            VimCommand::Counted(count, inner) => (Some(count), *inner),
+            other => (None, other),
+        };
+        // A count typed before `i`/`a` of a text object (d2aw)
+        let count = count.or(self.pending_count.take());
+
+        let motion = match (pending.object, &motion) {
+            (Some(scope), VimCommand::MoveWordForward) => VimCommand::TextObject(match scope {
+                'i' => TextObject::InnerWord,
+                _ => TextObject::AWord,
+            }),
+            (Some(scope), VimCommand::MoveBigWordForward) => {
+                VimCommand::TextObject(match scope {
+                    'i' => TextObject::InnerBigWord,
+                    _ => TextObject::ABigWord,
+                })
+            }
+            (Some(_), _) => return None,
+            (None, VimCommand::InsertMode | VimCommand::Append) => {
+                pending.object = Some(if motion == VimCommand::InsertMode { 'i' } else { 'a' });
+                self.pending_operator = Some(pending);
+                self.pending_count = count;
+                self.input_mode = InputMode::OperatorPending;
+                return None;
+            }
+            (None, VimCommand::StartOperator(op)) if *op == pending.operator => motion,
+            (
+                None,
+                VimCommand::StartFindNext
+                | VimCommand::StartFindPrev
+                | VimCommand::StartTillNext
+                | VimCommand::StartTillPrev
+                | VimCommand::StartGotoMarkLine
+                | VimCommand::StartGotoMark
+                | VimCommand::StartSearchForward
+                | VimCommand::StartSearchBackward,
+            ) => {
+                // Wait for the char or query; `apply_pending_count` finishes the job
+                self.pending_operator = Some(pending);
+                return Some(match count {
+                    Some(count) => VimCommand::Counted(count, Box::new(motion)),
+                    None => motion,
+                });
+            }
+            (None, other) if Self::is_motion(other) => motion,
+            // Escape, or anything that isn't a motion, cancels the operator
+            _ => return None,
+        };
+
+        let motion = match count {
+            Some(count) => VimCommand::Counted(count, Box::new(motion)),
+            None => motion,
+        };
+        Some(Self::wrap_operator(pending, motion))
+    }
+
+    fn wrap_operator(pending: PendingOperator, motion: VimCommand) -> VimCommand {
+        let command = VimCommand::Operate(pending.operator, Box::new(motion));
+        match pending.count {
+            Some(count) => VimCommand::Counted(count, Box::new(command)),
+            None => command,
+        }
+    }
+
+    fn is_motion(command: &VimCommand) -> bool {
+        matches!(
+            command,
+            VimCommand::MoveLeft
+                | VimCommand::MoveDown
+                | VimCommand::MoveUp
+                | VimCommand::MoveRight
+                | VimCommand::MoveWordForward
+                | VimCommand::MoveWordBack
+                | VimCommand::MoveWordEnd
+                | VimCommand::MoveWordEndBack
+                | VimCommand::MoveBigWordForward
+                | VimCommand::MoveBigWordBack
+                | VimCommand::MoveBigWordEnd
+                | VimCommand::MoveBigWordEndBack
+                | VimCommand::MoveLineStart
+                | VimCommand::MoveLineEnd
+                | VimCommand::MoveFirstNonBlank
+                | VimCommand::MoveLastNonBlank
+                | VimCommand::MoveLineFirstNonBlank
+                | VimCommand::MoveNextLineStart
+                | VimCommand::MovePrevLineStart
+                | VimCommand::MoveScreenTop
+                | VimCommand::MoveScreenMiddle
+                | VimCommand::MoveScreenBottom
+                | VimCommand::MoveParagraphForward
+                | VimCommand::MoveParagraphBack
+                | VimCommand::MoveSentenceForward
+                | VimCommand::MoveSentenceBack
+                | VimCommand::MoveSectionForward
+                | VimCommand::MoveSectionBack
+                | VimCommand::MoveSectionEndForward
+                | VimCommand::MoveSectionEndBack
+                | VimCommand::MatchPair
+                | VimCommand::PrevUnmatchedParen
+                | VimCommand::PrevUnmatchedBrace
+                | VimCommand::NextUnmatchedParen
+                | VimCommand::NextUnmatchedBrace
+                | VimCommand::GotoFirstLine
+                | VimCommand::GotoLastLine
+                | VimCommand::RepeatFind
+                | VimCommand::RepeatFindReverse
+                | VimCommand::NextMatch
+                | VimCommand::PrevMatch
+                | VimCommand::SearchWordForward
+                | VimCommand::SearchWordBackward
+                | VimCommand::SearchTextForward
+                | VimCommand::SearchTextBackward
+                | VimCommand::FindNextChar(_)
+                | VimCommand::FindPrevChar(_)
+                | VimCommand::TillNextChar(_)
+                | VimCommand::TillPrevChar(_)
+                | VimCommand::SearchForward(_)
+                | VimCommand::SearchBackward(_)
+                | VimCommand::GotoMarkLine(_)
+                | VimCommand::GotoMark(_)
+        )
+    }
+
+    fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            player_pos: self.player_pos,
+            layout: self.current_level.layout.clone(),
+            status: self.status,
+            last_find_command: self.last_find_command.clone(),
+            last_search_query: self.last_search_query.clone(),
+            search_direction_forward: self.search_direction_forward,
+        }
+    }
+
+    fn restore(&mut self, snapshot: Snapshot) {
+        self.player_pos = snapshot.player_pos;
+        self.current_level.layout = snapshot.layout;
+        self.status = snapshot.status;
+        self.last_find_command = snapshot.last_find_command;
+        self.last_search_query = snapshot.last_search_query;
+        self.search_direction_forward = snapshot.search_direction_forward;
+        self.cancel_pending();
+    }
+
+    /// Runs `u` or `Ctrl-r`, repeated by any count. Undo works from
+    /// `GameOver` only in practice mode, and never after the level is won.
+    fn handle_history(&mut self, command: &VimCommand) {
+        let can_rewind = match self.status {
+            GameStatus::Playing => true,
+            GameStatus::GameOver => self.practice_mode,
+            GameStatus::LevelComplete => false,
+        };
+        if !can_rewind {
+            return;
+        }
+
+        let (count, base) = match command {
+            VimCommand::Counted(count, inner) => (*count, inner.as_ref()),
+            other => (1, other),
+        };
+        let undoing = *base == VimCommand::Undo;
+        self.keystrokes += command.keystrokes();
+        for _ in 0..count {
+            let popped = if undoing {
+                self.undo_stack.pop()
+            } else {
+                self.redo_stack.pop()
+            };
+            let Some(snapshot) = popped else {
+                break;
+            };
+            let current = self.snapshot();
+            if undoing {
+                self.redo_stack.push(current);
+                self.undos += 1;
+            } else {
+                self.undo_stack.push(current);
+            }
+            self.restore(snapshot);
+        }
+    }
+
+    /// Updates the find (`;`) and search (`n`) registers for a command the
+    /// player issued. Repeats like `;` and `,` don't go through here, so they
+    /// never overwrite what they repeat.
+    fn remember_command(&mut self, command: &VimCommand) {
+        match command {
+            // ; and , repeat the find without its count, as in Vim
+            VimCommand::Counted(_, inner) => self.remember_command(inner),
+            // dfx remembers fx too
+            VimCommand::Operate(_, motion) => self.remember_command(motion),
+            VimCommand::FindNextChar(_)
+            | VimCommand::FindPrevChar(_)
+            | VimCommand::TillNextChar(_)
+            | VimCommand::TillPrevChar(_) => {
+                self.last_find_command = Some(command.clone());
+            }
+            VimCommand::SearchForward(query) => {
+                self.last_search_query = Some(query.clone());
+                self.search_direction_forward = true;
+                self.highlight_search = true;
+            }
+            VimCommand::SearchBackward(query) => {
+                self.last_search_query = Some(query.clone());
+                self.search_direction_forward = false;
+                self.highlight_search = true;
+            }
+            // As in Vim, n and N bring back the highlighting `:noh` hid
+            VimCommand::NextMatch | VimCommand::PrevMatch => self.highlight_search = true,
+            _ => {}
+        }
+    }
+
+    fn execute_command(&mut self, command: &VimCommand) {
+        match command {
+            VimCommand::MoveLeft => self.move_player(-1, 0),
+            VimCommand::MoveRight => self.move_player(1, 0),
+            VimCommand::MoveUp => self.move_player(0, -1),
+            VimCommand::MoveDown => self.move_player(0, 1),
+            VimCommand::MoveWordForward => self.move_word_forward(false),
+            VimCommand::MoveWordBack => self.move_word_back(false),
+            VimCommand::MoveWordEnd => self.move_word_end(false),
+            VimCommand::MoveWordEndBack => self.move_word_end_back(false),
+            VimCommand::MoveBigWordForward => self.move_word_forward(true),
+            VimCommand::MoveBigWordBack => self.move_word_back(true),
+            VimCommand::MoveBigWordEnd => self.move_word_end(true),
+            VimCommand::MoveBigWordEndBack => self.move_word_end_back(true),
+            VimCommand::MoveLineStart => self.move_line_start(),
+            VimCommand::MoveLineEnd => {
+                self.move_line_end();
+            }
+            VimCommand::MoveFirstNonBlank | VimCommand::MoveLineFirstNonBlank => {
+                self.move_to_line_start(0, true)
+            }
+            VimCommand::MoveLastNonBlank => self.move_last_non_blank(0),
+            VimCommand::MoveNextLineStart => self.move_to_line_start(1, true),
+            VimCommand::MovePrevLineStart => self.move_to_line_start(1, false),
+            VimCommand::MoveScreenTop => self.move_screen_top(1),
+            VimCommand::MoveScreenMiddle => self.move_screen_middle(),
+            VimCommand::MoveScreenBottom => self.move_screen_bottom(1),
+            VimCommand::MoveParagraphForward => self.move_paragraph_forward(),
+            VimCommand::MoveParagraphBack => self.move_paragraph_back(),
+            VimCommand::MoveSentenceForward => self.move_sentence(true),
+            VimCommand::MoveSentenceBack => self.move_sentence(false),
+            VimCommand::MoveSectionForward => self.move_section('{', true),
+            VimCommand::MoveSectionBack => self.move_section('{', false),
+            VimCommand::MoveSectionEndForward => self.move_section('}', true),
+            VimCommand::MoveSectionEndBack => self.move_section('}', false),
+            VimCommand::MatchPair => self.match_pair(),
+            VimCommand::PrevUnmatchedParen => self.goto_unmatched('(', ')', false, 1),
+            VimCommand::PrevUnmatchedBrace => self.goto_unmatched('{', '}', false, 1),
+            VimCommand::NextUnmatchedParen => self.goto_unmatched('(', ')', true, 1),
+            VimCommand::NextUnmatchedBrace => self.goto_unmatched('{', '}', true, 1),
+            VimCommand::ScrollLineDown => self.scroll_lines(1),
+            VimCommand::ScrollLineUp => self.scroll_lines(-1),
+            VimCommand::ScrollHalfPageDown => self.scroll_half_page(true, None),
+            VimCommand::ScrollHalfPageUp => self.scroll_half_page(false, None),
+            VimCommand::ScrollPageDown => self.scroll_page(true),
+            VimCommand::ScrollPageUp => self.scroll_page(false),
+            VimCommand::ScrollCursorTop => {
+                let height = self.current_level.height();
+                self.viewport.place_top(self.player_pos.y, height);
+            }
+            VimCommand::ScrollCursorCenter => {
+                let height = self.current_level.height();
+                self.viewport.place_center(self.player_pos.y, height);
+            }
+            VimCommand::ScrollCursorBottom => {
+                let height = self.current_level.height();
+                self.viewport.place_bottom(self.player_pos.y, height);
+            }
+            VimCommand::StartFindNext
+            | VimCommand::StartFindPrev
+            | VimCommand::StartTillNext
+            | VimCommand::StartTillPrev
+            | VimCommand::StartSetMark
+            | VimCommand::StartGotoMarkLine
+            | VimCommand::StartGotoMark => {
+                self.input_mode = InputMode::WaitingForChar(command.clone());
+            }
+            VimCommand::StartSearchForward
+            | VimCommand::StartSearchBackward
+            | VimCommand::StartCommandLine => {
+                self.input_mode = InputMode::CommandLine(String::new(), command.clone());
+            }
+            VimCommand::GotoFirstLine => self.goto_end_line(false),
+            VimCommand::GotoLastLine => self.goto_end_line(true),
+            VimCommand::GotoLine(line) => self.goto_line(*line as usize),
+            VimCommand::FindNextChar(c) => self.find_char_forward(*c, 1),
+            VimCommand::FindPrevChar(c) => self.find_char_backward(*c, 1),
+            VimCommand::TillNextChar(c) => self.till_char_forward(*c, 1),
+            VimCommand::TillPrevChar(c) => self.till_char_backward(*c, 1),
+            VimCommand::RepeatFind => self.repeat_find(false, 1),
+            VimCommand::RepeatFindReverse => self.repeat_find(true, 1),
+            VimCommand::SearchForward(s) => self.search(s, true),
+            VimCommand::SearchBackward(s) => self.search(s, false),
+            VimCommand::NextMatch => self.next_match(),
+            VimCommand::SearchWordForward => self.search_word(true, true),
+            VimCommand::SearchWordBackward => self.search_word(false, true),
+            VimCommand::SearchTextForward => self.search_word(true, false),
+            VimCommand::SearchTextBackward => self.search_word(false, false),
+            VimCommand::PrevMatch => self.prev_match(),
+            VimCommand::SetMark(name) => {
+                self.marks.set(*name, self.player_pos);
+            }
+            VimCommand::GotoMarkLine(name) => self.goto_mark(*name, true),
+            VimCommand::GotoMark(name) => self.goto_mark(*name, false),
+            VimCommand::JumpOlder => self.jump_older(1),
+            VimCommand::JumpNewer => self.jump_newer(1),
+            VimCommand::DeleteChar => self.delete_char(1),
+            VimCommand::StartOperator(op) => self.start_operator(*op, None),
+            VimCommand::Operate(op, motion) => self.operate(*op, motion, 1),
+            VimCommand::PutAfter => self.put(true, 1),
+            VimCommand::PutBefore => self.put(false, 1),
+            VimCommand::InsertMode => self.input_mode = InputMode::Insert(self.player_pos),
+            VimCommand::Append => self.append(),
+            VimCommand::InsertChar(c) => self.insert_char(*c),
+            VimCommand::Escape => self.leave_insert_mode(),
+            VimCommand::Counted(count, inner) => self.execute_counted(*count, inner),
+            _ => {} // Implement other commands later
+        }
+    }
+
+    fn execute_counted(&mut self, count: u32, command: &VimCommand) {
+        match command {
+            // The count waits until the argument (char or search text) arrives
+            VimCommand::StartFindNext
+            | VimCommand::StartFindPrev
+            | VimCommand::StartTillNext
+            | VimCommand::StartTillPrev
+            | VimCommand::StartGotoMarkLine
+            | VimCommand::StartGotoMark
+            | VimCommand::StartSearchForward
+            | VimCommand::StartSearchBackward => {
+                self.pending_count = Some(count);
+                self.execute_command(command);
+            }
+            VimCommand::FindNextChar(c) => self.find_char_forward(*c, count),
+            VimCommand::FindPrevChar(c) => self.find_char_backward(*c, count),
+            VimCommand::TillNextChar(c) => self.till_char_forward(*c, count),
+            VimCommand::TillPrevChar(c) => self.till_char_backward(*c, count),
+            VimCommand::RepeatFind => self.repeat_find(false, count),
+            VimCommand::RepeatFindReverse => self.repeat_find(true, count),
+            // {count}% goes to that percentage of the level's lines
+            VimCommand::MatchPair => {
+                if count <= 100 {
+                    let height = self.current_level.height() as u32;
+                    self.goto_line((count * height).div_ceil(100) as usize);
+                }
+            }
+            VimCommand::PrevUnmatchedParen => self.goto_unmatched('(', ')', false, count),
+            VimCommand::PrevUnmatchedBrace => self.goto_unmatched('{', '}', false, count),
+            VimCommand::NextUnmatchedParen => self.goto_unmatched('(', ')', true, count),
+            VimCommand::NextUnmatchedBrace => self.goto_unmatched('{', '}', true, count),
+            VimCommand::JumpOlder => self.jump_older(count as usize),
+            VimCommand::JumpNewer => self.jump_newer(count as usize),
+            VimCommand::MoveLineEnd => {
+                // {count}$ goes to the end of the line count-1 lines down
+                let last_y = self.current_level.height().saturating_sub(1);
+                let target_y = (self.player_pos.y + count as usize - 1).min(last_y);
+                let start = self.player_pos;
+                self.player_pos.y = target_y;
+                if !self.move_line_end() {
+                    self.player_pos = start;
+                }
+            }
+            // {count}_ and {count}g_ go count-1 lines down; {count}+ and
+            // {count}- go count lines
+            VimCommand::MoveLineFirstNonBlank => self.move_to_line_start(count as usize - 1, true),
+            VimCommand::MoveLastNonBlank => self.move_last_non_blank(count as usize - 1),
+            VimCommand::MoveNextLineStart => self.move_to_line_start(count as usize, true),
+            VimCommand::MovePrevLineStart => self.move_to_line_start(count as usize, false),
+            VimCommand::MoveScreenTop => self.move_screen_top(count as usize),
+            // As in Vim, M has no use for a count
+            VimCommand::MoveScreenMiddle => self.move_screen_middle(),
+            VimCommand::MoveScreenBottom => self.move_screen_bottom(count as usize),
+            // {count}Ctrl-d scrolls that many lines instead of half a screen
+            VimCommand::ScrollHalfPageDown => self.scroll_half_page(true, Some(count as usize)),
+            VimCommand::ScrollHalfPageUp => self.scroll_half_page(false, Some(count as usize)),
+            // {N}G and {N}gg both go to line N
+            VimCommand::GotoFirstLine | VimCommand::GotoLastLine => self.goto_line(count as usize),
+            VimCommand::DeleteChar => self.delete_char(count as usize),
+            VimCommand::StartOperator(op) => self.start_operator(*op, Some(count)),
+            VimCommand::Operate(op, motion) => self.operate(*op, motion, count),
+            VimCommand::PutAfter => self.put(true, count),
+            VimCommand::PutBefore => self.put(false, count),
+            _ => {
+                // Plain repetition, e.g. 3w is w three times.
+                // Stop as soon as the player dies or reaches the goal.
+                for _ in 0..count {
+                    self.execute_command(command);
+                    self.check_win_condition();
+                    if !matches!(self.status, GameStatus::Playing) {
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Wraps a completed command in the count typed before its start key,
+    /// and in the operator waiting for it (the `d` in `dfx`).
+    fn apply_pending_count(&mut self, command: VimCommand) -> VimCommand {
+        let command = match self.pending_count.take() {
+            Some(count) => VimCommand::Counted(count, Box::new(command)),
+            None => command,
+        };
+        match self.pending_operator.take() {
+            Some(pending) => Self::wrap_operator(pending, command),
+            None => command,
         }
     }
 
+    /// Drops a half-typed command (count, operator) when its argument is cancelled.
+    fn cancel_pending(&mut self) {
+        self.input_mode = InputMode::Normal;
+        self.pending_count = None;
+        self.pending_operator = None;
+    }
+
     pub fn handle_char_input(&mut self, c: char) {
         match &self.input_mode {
             InputMode::WaitingForChar(cmd) => {
@@ -178,12 +831,17 @@ impl GameState {
                     VimCommand::StartFindPrev => VimCommand::FindPrevChar(c),
                     VimCommand::StartTillNext => VimCommand::TillNextChar(c),
                     VimCommand::StartTillPrev => VimCommand::TillPrevChar(c),
+                    VimCommand::StartSetMark => VimCommand::SetMark(c),
+                    VimCommand::StartGotoMarkLine => VimCommand::GotoMarkLine(c),
+                    VimCommand::StartGotoMark => VimCommand::GotoMark(c),
                     _ => VimCommand::None,
                 };
                 self.input_mode = InputMode::Normal;
                 if next_cmd != VimCommand::None {
-                    self.last_find_command = Some(next_cmd.clone());
+                    let next_cmd = self.apply_pending_count(next_cmd);
                     self.handle_command(next_cmd);
+                } else {
+                    self.cancel_pending();
                 }
             }
             InputMode::CommandLine(current_text, cmd_type) => {
@@ -191,120 +849,193 @@ impl GameState {
                 let mut new_text = current_text.clone();
                 new_text.push(c);
                 self.input_mode = InputMode::CommandLine(new_text, cmd_type.clone());
+                self.message = None;
             }
+            InputMode::Insert(_) => self.handle_command(VimCommand::InsertChar(c)),
             _ => {}
         }
     }
 
     pub fn handle_special_key(&mut self, key: macroquad::input::KeyCode) {
+        if let InputMode::WaitingForChar(_) = self.input_mode
+            && key == macroquad::input::KeyCode::Escape
+        {
+            self.cancel_pending();
+            return;
+        }
+
+        if let InputMode::Insert(_) = self.input_mode
+            && key == macroquad::input::KeyCode::Escape
+        {
+            self.handle_command(VimCommand::Escape);
+            return;
+        }
+
         if let InputMode::CommandLine(ref mut text, ref cmd_type) = self.input_mode.clone() {
             match key {
                 macroquad::input::KeyCode::Enter => {
+                    let is_search = matches!(
+                        cmd_type,
+                        VimCommand::StartSearchForward | VimCommand::StartSearchBackward
+                    );
+                    // As in Vim, an empty search repeats the last pattern
+                    let query = match &self.last_search_query {
+                        Some(last) if is_search && text.is_empty() => last.clone(),
+                        _ => text.clone(),
+                    };
+                    if is_search && query.is_empty() {
+                        self.cancel_pending();
+                        self.message = Some(Message::Error(
+                            "E35: No previous regular expression".to_string(),
+                        ));
+                        return;
+                    }
                     let final_cmd = match cmd_type {
-                        VimCommand::StartSearchForward => VimCommand::SearchForward(text.clone()),
-                        VimCommand::StartSearchBackward => VimCommand::SearchBackward(text.clone()),
+                        VimCommand::StartSearchForward => VimCommand::SearchForward(query),
+                        VimCommand::StartSearchBackward => VimCommand::SearchBackward(query),
                         _ => VimCommand::None,
                     };
                     self.input_mode = InputMode::Normal;
                     if final_cmd != VimCommand::None {
-                        self.last_search_query = Some(text.clone());
-                        self.search_direction_forward =
-                            matches!(cmd_type, VimCommand::StartSearchForward);
+                        let final_cmd = self.apply_pending_count(final_cmd);
                         self.handle_command(final_cmd);
+                    } else {
+                        self.cancel_pending();
+                        if *cmd_type == VimCommand::StartCommandLine {
+                            self.execute_ex(text);
+                        }
                     }
                 }
+                macroquad::input::KeyCode::Tab if *cmd_type == VimCommand::StartCommandLine => {
+                    let (line, matches) = ex::complete(text);
+                    // One match is filled in; several are listed
+                    self.message = (matches.len() > 1).then(|| Message::Info(matches.join("  ")));
+                    self.input_mode = InputMode::CommandLine(line, cmd_type.clone());
+                }
                 macroquad::input::KeyCode::Backspace => {
                     let mut new_text = text.clone();
                     new_text.pop();
                     self.input_mode = InputMode::CommandLine(new_text, cmd_type.clone());
                 }
-                macroquad::input::KeyCode::Escape => {
-                    self.input_mode = InputMode::Normal;
-                }
+                macroquad::input::KeyCode::Escape => self.cancel_pending(),
                 _ => {}
             }
         }
     }
 
-    fn find_char_forward(&mut self, target: char) {
-        let y = self.player_pos.y;
-        let start_x = self.player_pos.x + 1;
-        let width = self.current_level.width();
+    /// What is being typed after `:`, `/` or `?`, and which of them it follows.
+    pub fn command_line(&self) -> Option<(&VimCommand, &str)> {
+        match &self.input_mode {
+            InputMode::CommandLine(text, kind) => Some((kind, text)),
+            _ => None,
+        }
+    }
 
-        for x in start_x..width {
-            if self.get_char_at(x, y) == target {
-                if !self.current_level.is_wall(x, y) {
-                    self.player_pos.x = x;
-                    if self.get_char_at(x, y) == '~' {
-                        self.status = GameStatus::GameOver;
-                    }
-                }
-                return;
-            }
+    /// Replaces what is being typed, e.g. with a line from the history.
+    pub fn set_command_line(&mut self, line: String) {
+        if let InputMode::CommandLine(text, _) = &mut self.input_mode {
+            *text = line;
         }
     }
 
-    fn find_char_backward(&mut self, target: char) {
-        let y = self.player_pos.y;
-        let start_x = self.player_pos.x;
-        if start_x == 0 {
+    /// Runs a line typed after `:`. Jumps and `:help` are handled here; the
+    /// rest (leaving, restarting, options) goes to `ex_request` for the game
+    /// loop.
+    fn execute_ex(&mut self, line: &str) {
+        if line.trim().is_empty() {
             return;
         }
+        match ex::parse(line) {
+            Ok(ExCommand::Goto(line)) => self.handle_command(VimCommand::GotoLine(line)),
+            Ok(ExCommand::NoHighlight) => self.highlight_search = false,
+            Ok(ExCommand::Help(None)) => self.show_help = true,
+            Ok(ExCommand::Help(Some(topic))) => self.message = Some(self.help_for(&topic)),
+            Ok(command) => self.ex_request = Some(command),
+            Err(e) => self.message = Some(Message::Error(e.to_string())),
+        }
+    }
 
-        for x in (0..start_x).rev() {
-            if self.get_char_at(x, y) == target {
-                if !self.current_level.is_wall(x, y) {
-                    self.player_pos.x = x;
-                    if self.get_char_at(x, y) == '~' {
-                        self.status = GameStatus::GameOver;
-                    }
-                }
-                return;
-            }
+    /// `:help {topic}`: a key command (and whether this level allows it),
+    /// or an ex command, which `:` in front picks out, as in Vim.
+    fn help_for(&self, topic: &str) -> Message {
+        let ex_topic = topic.strip_prefix(':').filter(|name| !name.is_empty());
+        let command = InputHandler::from_string(topic);
+        if command != VimCommand::None && ex_topic.is_none() {
+            let note = if self.is_command_allowed(&command) {
+                ""
+            } else {
+                " - not allowed in this level"
+            };
+            return Message::Info(format!("{}{}", command.to_display_string(), note));
+        }
+        match ex::lookup(ex_topic.unwrap_or(topic)) {
+            Some(spec) => Message::Info(format!("{}  {}", spec.usage, spec.help)),
+            None => Message::Error(format!("E149: Sorry, no help for {}", topic)),
         }
     }
 
-    fn till_char_forward(&mut self, target: char) {
+    /// Column of the `count`-th occurrence of `target` right of the player.
+    fn nth_char_forward(&self, target: char, count: u32) -> Option<usize> {
         let y = self.player_pos.y;
-        let start_x = self.player_pos.x + 1;
         let width = self.current_level.width();
+        (self.player_pos.x + 1..width)
+            .filter(|&x| self.get_char_at(x, y) == target)
+            .nth(count.max(1) as usize - 1)
+    }
 
-        for x in start_x..width {
-            if self.get_char_at(x, y) == target {
-                let target_x = x - 1;
-                if target_x > self.player_pos.x && !self.current_level.is_wall(target_x, y) {
-                    self.player_pos.x = target_x;
-                    if self.get_char_at(target_x, y) == '~' {
-                        self.status = GameStatus::GameOver;
-                    }
-                }
-                return;
+    /// Column of the `count`-th occurrence of `target` left of the player.
+    fn nth_char_backward(&self, target: char, count: u32) -> Option<usize> {
+        let y = self.player_pos.y;
+        (0..self.player_pos.x)
+            .rev()
+            .filter(|&x| self.get_char_at(x, y) == target)
+            .nth(count.max(1) as usize - 1)
+    }
+
+    /// Lands on column `x` of the current row unless it is a wall.
+    fn land_on_column(&mut self, x: usize) {
+        let y = self.player_pos.y;
+        if !self.current_level.is_wall(x, y) {
+            self.player_pos.x = x;
+            if self.get_char_at(x, y) == '~' {
+                self.status = GameStatus::GameOver;
             }
         }
     }
 
-    fn till_char_backward(&mut self, target: char) {
-        let y = self.player_pos.y;
-        let start_x = self.player_pos.x;
-        if start_x == 0 {
-            return;
+    // With a count, Vim doesn't move at all if there are fewer than
+    // `count` occurrences on the line.
+    fn find_char_forward(&mut self, target: char, count: u32) {
+        if let Some(x) = self.nth_char_forward(target, count) {
+            self.land_on_column(x);
         }
+    }
 
-        for x in (0..start_x).rev() {
-            if self.get_char_at(x, y) == target {
-                let target_x = x + 1;
-                if target_x < self.player_pos.x && !self.current_level.is_wall(target_x, y) {
-                    self.player_pos.x = target_x;
-                    if self.get_char_at(target_x, y) == '~' {
-                        self.status = GameStatus::GameOver;
-                    }
-                }
-                return;
+    fn find_char_backward(&mut self, target: char, count: u32) {
+        if let Some(x) = self.nth_char_backward(target, count) {
+            self.land_on_column(x);
+        }
+    }
+
+    fn till_char_forward(&mut self, target: char, count: u32) {
+        if let Some(x) = self.nth_char_forward(target, count) {
+            let target_x = x - 1;
+            if target_x > self.player_pos.x {
+                self.land_on_column(target_x);
+            }
+        }
+    }
+
+    fn till_char_backward(&mut self, target: char, count: u32) {
+        if let Some(x) = self.nth_char_backward(target, count) {
+            let target_x = x + 1;
+            if target_x < self.player_pos.x {
+                self.land_on_column(target_x);
             }
         }
     }
 
-    fn repeat_find(&mut self, reverse: bool) {
+    fn repeat_find(&mut self, reverse: bool, count: u32) {
         if let Some(cmd) = self.last_find_command.clone() {
             let cmd_to_run = if reverse {
                 match cmd {
@@ -317,121 +1048,223 @@ impl GameState {
             } else {
                 cmd
             };
-            self.handle_command(cmd_to_run);
+            // As in Vim, an uncounted repeat of t or T skips the char right
+            // next to the cursor, where the last one stopped, instead of
+            // getting stuck
+            let Position { x, y } = self.player_pos;
+            let count = match cmd_to_run {
+                VimCommand::TillNextChar(c) if count == 1 && self.get_char_at(x + 1, y) == c => 2,
+                VimCommand::TillPrevChar(c)
+                    if count == 1 && x > 0 && self.get_char_at(x - 1, y) == c =>
+                {
+                    2
+                }
+                _ => count,
+            };
+            // Run directly so `;` is counted (and gated) once, as itself
+            self.execute_counted(count, &cmd_to_run);
         }
     }
 
-    fn search_forward(&mut self, query: &str) {
-        // Simple linear search from current pos
-        // Flatten coordinates? Or just loop y then x
-        let width = self.current_level.width();
-        let height = self.current_level.height();
-
-        let mut x = self.player_pos.x;
-        let mut y = self.player_pos.y;
-
-        // Start from next char
-        loop {
-            x += 1;
-            if x >= width {
-                x = 0;
-                y += 1;
-            }
-            if y >= height {
-                y = 0; // Wrap around? Vim wraps.
-            }
+    /// `/` and `?`: jumps to the next match of `query`. A match on a wall
+    /// stops the search without moving.
+    fn search(&mut self, query: &str, forward: bool) {
+        if let Some(pos) = self.find_match(query, self.player_pos, forward) {
+            self.jump_to(pos);
+        }
+    }
 
-            if x == self.player_pos.x && y == self.player_pos.y {
-                break; // Full loop
+    /// Lands on `pos` unless it is a wall, skipping whatever lies between.
+    fn jump_to(&mut self, pos: Position) {
+        if !self.current_level.is_wall(pos.x, pos.y) {
+            self.player_pos = pos;
+            if self.get_char_at(pos.x, pos.y) == '~' {
+                self.status = GameStatus::GameOver;
             }
+        }
+    }
 
-            // Check match
-            if self.check_match_at(x, y, query) {
-                if !self.current_level.is_wall(x, y) {
-                    self.player_pos.x = x;
-                    self.player_pos.y = y;
-                    if self.get_char_at(x, y) == '~' {
-                        self.status = GameStatus::GameOver;
-                    }
-                }
-                return;
-            }
+    /// `` `a `` goes to mark `a`; `'a` to the first non-blank cell of its line.
+    fn goto_mark(&mut self, name: char, linewise: bool) {
+        let Some(mut pos) = self.marks.get(name) else {
+            // Vim's messages for an unset mark and a name that can't be one
+            let error = if Marks::is_valid_name(name) {
+                "E20: Mark not set"
+            } else {
+                "E78: Unknown mark"
+            };
+            self.message = Some(Message::Error(error.to_string()));
+            return;
+        };
+        if linewise && let Some(x) = self.first_non_blank(pos.y) {
+            pos.x = x;
         }
+        self.jump_to(pos);
     }
 
-    fn search_backward(&mut self, query: &str) {
-        let width = self.current_level.width();
-        let height = self.current_level.height();
+    /// Ctrl-o: back to where the `count`-th jump before this one left from.
+    fn jump_older(&mut self, count: usize) {
+        if let Some(pos) = self.jumps.older(count, self.player_pos) {
+            self.jump_to(pos);
+        }
+    }
 
-        let mut x = self.player_pos.x;
-        let mut y = self.player_pos.y;
+    /// Ctrl-i: forward again through the jumps Ctrl-o went back over.
+    fn jump_newer(&mut self, count: usize) {
+        if let Some(pos) = self.jumps.newer(count) {
+            self.jump_to(pos);
+        }
+    }
 
-        loop {
-            if x == 0 {
-                x = width - 1;
-                if y == 0 {
-                    y = height - 1;
+    /// Start of the first match of `query` after `from` (before it when
+    /// searching backward), wrapping around the level as Vim does.
+    fn find_match(&self, query: &str, from: Position, forward: bool) -> Option<Position> {
+        let pattern = Pattern::new(query, self.case_options);
+        let rows = self.rows();
+        let width = self.current_level.width();
+        let cells = width * self.current_level.height();
+        let start = from.y * width + from.x;
+        (1..cells)
+            .map(|step| {
+                if forward {
+                    (start + step) % cells
                 } else {
-                    y -= 1;
+                    (start + cells - step) % cells
                 }
-            } else {
-                x -= 1;
-            }
-
-            if x == self.player_pos.x && y == self.player_pos.y {
-                break;
-            }
+            })
+            .map(|i| Position {
+                x: i % width,
+                y: i / width,
+            })
+            .find(|pos| {
+                // An empty match at the end of a row (`$`) lands on its last cell
+                let row = &rows[pos.y];
+                let at = |x| pattern.match_at(row, x).is_some();
+                pos.x < row.len() && (at(pos.x) || (pos.x + 1 == row.len() && at(row.len())))
+            })
+    }
 
-            if self.check_match_at(x, y, query) {
-                if !self.current_level.is_wall(x, y) {
-                    self.player_pos.x = x;
-                    self.player_pos.y = y;
-                    if self.get_char_at(x, y) == '~' {
-                        self.status = GameStatus::GameOver;
-                    }
-                }
-                return;
-            }
-        }
+    /// The layout's rows as chars, for matching patterns against.
+    fn rows(&self) -> Vec<Vec<char>> {
+        self.current_level
+            .layout
+            .iter()
+            .map(|row| row.chars().collect())
+            .collect()
     }
 
-    fn check_match_at(&self, x: usize, y: usize, query: &str) -> bool {
-        // Check if query matches starting at x, y
-        // Only horizontal matches for now
-        let width = self.current_level.width();
-        if x + query.len() > width {
-            return false;
+    /// Incremental search: where the search being typed would land, or
+    /// None if it has no match.
+    pub fn search_preview(&self) -> Option<Position> {
+        let (query, forward) = self.search_line()?;
+        if query.is_empty() {
+            return None;
         }
-
-        for (i, c) in query.chars().enumerate() {
-            if self.get_char_at(x + i, y) != c {
-                return false;
+        let mut pos = self.player_pos;
+        for _ in 0..self.pending_count.unwrap_or(1) {
+            let found = self.find_match(query, pos, forward)?;
+            // A counted search stops at a wall, and ends in the water
+            if self.current_level.is_wall(found.x, found.y) {
+                break;
+            }
+            pos = found;
+            if self.get_char_at(pos.x, pos.y) == '~' {
+                break;
             }
         }
-        true
+        Some(pos)
     }
 
-    fn next_match(&mut self) {
-        if let Some(query) = self.last_search_query.clone() {
-            if self.search_direction_forward {
-                self.search_forward(&query);
-            } else {
-                self.search_backward(&query);
+    /// Matches to highlight, as (start, length in cells): those of the
+    /// search being typed, or else of the last search until `:noh`.
+    pub fn search_highlights(&self) -> Vec<(Position, usize)> {
+        let query = match self.search_line() {
+            Some((query, _)) => query,
+            None if self.highlight_search => match &self.last_search_query {
+                Some(query) => query,
+                None => return Vec::new(),
+            },
+            None => return Vec::new(),
+        };
+        if query.is_empty() {
+            return Vec::new();
+        }
+        let pattern = Pattern::new(query, self.case_options);
+        let mut matches = Vec::new();
+        for (y, row) in self.rows().iter().enumerate() {
+            for x in 0..row.len() {
+                if let Some(len) = pattern.match_at(row, x) {
+                    matches.push((Position { x, y }, len));
+                }
             }
         }
+        matches
     }
 
-    fn prev_match(&mut self) {
-        if let Some(query) = self.last_search_query.clone() {
-            if self.search_direction_forward {
-                self.search_backward(&query);
-            } else {
-                self.search_forward(&query);
-            }
+    /// The search being typed after `/` or `?`, and whether it goes forward.
+    fn search_line(&self) -> Option<(&str, bool)> {
+        match &self.input_mode {
+            InputMode::CommandLine(query, VimCommand::StartSearchForward) => Some((query, true)),
+            InputMode::CommandLine(query, VimCommand::StartSearchBackward) => Some((query, false)),
+            _ => None,
         }
     }
 
-    fn delete_char(&mut self) {
+    /// `*` and `#` (`g*` and `g#` without `whole`): searches for the
+    /// keyword under or after the cursor on its row, as a whole word.
+    fn search_word(&mut self, forward: bool, whole: bool) {
+        let row = &self.rows()[self.player_pos.y];
+        let Some(start) = (self.player_pos.x..row.len()).find(|&x| self.is_word_char(row[x])) else {
+            self.message = Some(Message::Error("E348: No string under cursor".to_string()));
+            return;
+        };
+        // Back to the start of the keyword the cursor is in
+        let start = (0..start)
+            .rev()
+            .take_while(|&x| self.is_word_char(row[x]))
+            .last()
+            .unwrap_or(start);
+        let word: String = row[start..]
+            .iter()
+            .take_while(|&&c| self.is_word_char(c))
+            .collect();
+        let query = if whole {
+            format!("\\<{}\\>", pattern::escape(&word))
+        } else {
+            pattern::escape(&word)
+        };
+        self.word_search = Some(query.clone());
+        // As in Vim, `*` honours 'ignorecase' but not 'smartcase'
+        let query = if self.case_options.ignore_case {
+            format!("\\c{}", query)
+        } else {
+            query
+        };
+        self.last_search_query = Some(query.clone());
+        self.search_direction_forward = forward;
+        self.highlight_search = true;
+        // From the keyword's start, so it is found last, after wrapping around
+        let from = Position {
+            x: start,
+            y: self.player_pos.y,
+        };
+        let pos = self.find_match(&query, from, forward).unwrap_or(from);
+        self.jump_to(pos);
+    }
+
+    fn next_match(&mut self) {
+        if let Some(query) = self.last_search_query.clone() {
+            self.search(&query, self.search_direction_forward);
+        }
+    }
+
+    fn prev_match(&mut self) {
+        if let Some(query) = self.last_search_query.clone() {
+            self.search(&query, !self.search_direction_forward);
+        }
+    }
+
+    fn delete_char(&mut self, count: usize) {
         let x = self.player_pos.x;
         let y = self.player_pos.y;
 
@@ -463,16 +1296,451 @@ impl GameState {
         // And maybe specific chars like 'X' trigger something.
 
         // For now: 'x' turns current tile to '.'
+        // With a count, 3x clears the tile under the cursor and the two to its right,
+        // stopping at walls.
 
         if y < self.current_level.layout.len() {
-            let row = &mut self.current_level.layout[y];
-            if x < row.len() {
-                let mut chars: Vec<char> = row.chars().collect();
-                if chars[x] != 'S' && chars[x] != 'E' {
-                    chars[x] = '.';
-                    self.current_level.layout[y] = chars.into_iter().collect();
+            let mut chars: Vec<char> = self.current_level.layout[y].chars().collect();
+            // Like dl, x saves what it deletes for p
+            let mut deleted = String::new();
+            for cx in (x..chars.len()).take(count.max(1)) {
+                if chars[cx] == '#' {
+                    break;
+                }
+                deleted.push(chars[cx]);
+                if chars[cx] != 'S' && chars[cx] != 'E' {
+                    chars[cx] = '.';
+                }
+            }
+            self.current_level.layout[y] = chars.into_iter().collect();
+            if !deleted.is_empty() {
+                self.register = Some(Register {
+                    text: vec![deleted],
+                    linewise: false,
+                });
+            }
+        }
+    }
+
+    fn row_len(&self, y: usize) -> usize {
+        self.current_level
+            .layout
+            .get(y)
+            .map_or(0, |row| row.chars().count())
+    }
+
+    fn set_char_at(&mut self, x: usize, y: usize, c: char) {
+        if let Some(row) = self.current_level.layout.get_mut(y) {
+            *row = row
+                .chars()
+                .enumerate()
+                .map(|(i, old)| if i == x { c } else { old })
+                .collect();
+        }
+    }
+
+    /// Edits never touch walls, start or goal, and never create them:
+    /// deleted cells become floor ('.') and pasted walls/S/E become floor too.
+    fn write_cell(&mut self, x: usize, y: usize, c: char) {
+        if matches!(self.get_char_at(x, y), '#' | 'S' | 'E') {
+            return;
+        }
+        let c = if matches!(c, '#' | 'S' | 'E') { '.' } else { c };
+        self.set_char_at(x, y, c);
+    }
+
+    fn start_operator(&mut self, operator: Operator, count: Option<u32>) {
+        self.pending_operator = Some(PendingOperator {
+            operator,
+            count,
+            object: None,
+        });
+        self.input_mode = InputMode::OperatorPending;
+    }
+
+    /// Runs an operator over the text from the cursor to where `motion`
+    /// (repeated `count` times) would go. Deleted text becomes floor, so
+    /// `d` can drain water as well as clear rubble.
+    fn operate(&mut self, operator: Operator, motion: &VimCommand, count: u32) {
+        let Some(range) = self.operator_range(operator, motion, count) else {
+            return;
+        };
+        self.register = Some(self.read_range(range));
+        if operator != Operator::Yank {
+            for (y, x0, x1) in self.range_rows(range) {
+                for x in x0..=x1 {
+                    self.write_cell(x, y, '.');
+                }
+            }
+        }
+
+        // The cursor moves to the start of the text; linewise only the row changes
+        let land = if range.linewise {
+            Position {
+                x: self.player_pos.x,
+                y: range.start.y,
+            }
+        } else {
+            range.start
+        };
+        if !self.current_level.is_wall(land.x, land.y) {
+            self.player_pos = land;
+        }
+        if operator == Operator::Change {
+            self.input_mode = InputMode::Insert(self.player_pos);
+        } else if self.get_char_at(self.player_pos.x, self.player_pos.y) == '~' {
+            self.status = GameStatus::GameOver;
+        }
+    }
+
+    /// Cells an operator acts on, or None if the motion fails.
+    fn operator_range(
+        &mut self,
+        operator: Operator,
+        motion: &VimCommand,
+        count: u32,
+    ) -> Option<TextRange> {
+        let (motion_count, motion) = match motion {
+            VimCommand::Counted(n, inner) => (*n, inner.as_ref()),
+            other => (1, other),
+        };
+        let count = count.max(1).saturating_mul(motion_count.max(1));
+        let here = self.player_pos;
+
+        match motion {
+            // dd, cc, yy: `count` lines from the cursor down
+            VimCommand::StartOperator(_) => {
+                let last = self.current_level.height().saturating_sub(1);
+                let end_y = (here.y + count as usize - 1).min(last);
+                return Some(self.line_range(here.y, end_y));
+            }
+            VimCommand::TextObject(object) => return Some(self.text_object_range(*object, count)),
+            _ => {}
+        }
+
+        // cw on a word works like ce, and on a word's last char changes just that char
+        let big = *motion == VimCommand::MoveBigWordForward;
+        if operator == Operator::Change
+            && matches!(
+                motion,
+                VimCommand::MoveWordForward | VimCommand::MoveBigWordForward
+            )
+            && self.class_at(here.x, here.y, big) != CharClass::Blank
+        {
+            let word_end = if big {
+                VimCommand::MoveBigWordEnd
+            } else {
+                VimCommand::MoveWordEnd
+            };
+            let at_end =
+                self.class_at(here.x + 1, here.y, big) != self.class_at(here.x, here.y, big);
+            let target = match (at_end, count) {
+                (true, 1) => here,
+                (true, _) => self.motion_target(&word_end, count - 1),
+                (false, _) => self.motion_target(&word_end, count),
+            };
+            return self.ordered_range(here, target, MotionKind::Inclusive);
+        }
+
+        let target = self.motion_target(motion, count);
+        let kind = self.motion_kind(motion);
+        // A motion that goes nowhere (no match, a wall) cancels the operator,
+        // except $ which covers the cursor's own cell
+        if target == here && kind != MotionKind::Linewise && *motion != VimCommand::MoveLineEnd {
+            return None;
+        }
+        self.ordered_range(here, target, kind)
+    }
+
+    /// Where a motion would take the cursor, without moving it or letting
+    /// it fall or win on the way.
+    fn motion_target(&mut self, motion: &VimCommand, count: u32) -> Position {
+        let saved = (self.player_pos, self.status, self.time_elapsed, self.score);
+        // The motion may pass the exit, but only measuring it wins nothing
+        let finished_replay = self.finished_replay.take();
+        if count > 1 {
+            self.execute_counted(count, motion);
+        } else {
+            self.execute_command(motion);
+        }
+        let target = self.player_pos;
+        (self.player_pos, self.status, self.time_elapsed, self.score) = saved;
+        self.finished_replay = finished_replay;
+        target
+    }
+
+    fn motion_kind(&self, motion: &VimCommand) -> MotionKind {
+        match motion {
+            VimCommand::MoveUp
+            | VimCommand::MoveDown
+            | VimCommand::MoveScreenTop
+            | VimCommand::MoveScreenMiddle
+            | VimCommand::MoveScreenBottom
+            | VimCommand::GotoFirstLine
+            | VimCommand::GotoLastLine
+            | VimCommand::MoveLineFirstNonBlank
+            | VimCommand::MoveNextLineStart
+            | VimCommand::MovePrevLineStart
+            | VimCommand::GotoMarkLine(_) => MotionKind::Linewise,
+            VimCommand::MoveWordEnd
+            | VimCommand::MoveWordEndBack
+            | VimCommand::MoveBigWordEnd
+            | VimCommand::MoveBigWordEndBack
+            | VimCommand::MoveLineEnd
+            | VimCommand::MoveLastNonBlank
+            | VimCommand::MatchPair
+            | VimCommand::FindNextChar(_)
+            | VimCommand::TillNextChar(_) => MotionKind::Inclusive,
+            VimCommand::RepeatFind => self
+                .last_find_command
+                .as_ref()
+                .map_or(MotionKind::Exclusive, |find| self.motion_kind(find)),
+            // , runs the find the other way: f becomes F (exclusive) and so on
+            VimCommand::RepeatFindReverse => match self.last_find_command {
+                Some(VimCommand::FindPrevChar(_) | VimCommand::TillPrevChar(_)) => {
+                    MotionKind::Inclusive
+                }
+                _ => MotionKind::Exclusive,
+            },
+            _ => MotionKind::Exclusive,
+        }
+    }
+
+    /// The range between two cells in reading order, shaped by the motion kind.
+    fn ordered_range(&self, a: Position, b: Position, kind: MotionKind) -> Option<TextRange> {
+        let (start, end) = if (b.y, b.x) < (a.y, a.x) { (b, a) } else { (a, b) };
+        match kind {
+            MotionKind::Linewise => Some(self.line_range(start.y, end.y)),
+            MotionKind::Inclusive => Some(TextRange {
+                start,
+                end,
+                linewise: false,
+            }),
+            MotionKind::Exclusive => {
+                // The far cell isn't included. Ending at the start of a later
+                // row (as dw on a line's last word does) stops at the end of
+                // the previous row instead.
+                let first_open = (0..self.row_len(end.y))
+                    .find(|&x| !self.current_level.is_wall(x, end.y))
+                    .unwrap_or(0);
+                let end = if end.y > start.y && end.x <= first_open {
+                    Position {
+                        x: self.row_len(end.y - 1).saturating_sub(1),
+                        y: end.y - 1,
+                    }
+                } else if end.x > 0 {
+                    Position {
+                        x: end.x - 1,
+                        y: end.y,
+                    }
+                } else {
+                    return None;
+                };
+                ((end.y, end.x) >= (start.y, start.x)).then_some(TextRange {
+                    start,
+                    end,
+                    linewise: false,
+                })
+            }
+        }
+    }
+
+    fn line_range(&self, first: usize, last: usize) -> TextRange {
+        TextRange {
+            start: Position { x: 0, y: first },
+            end: Position {
+                x: self.row_len(last).saturating_sub(1),
+                y: last,
+            },
+            linewise: true,
+        }
+    }
+
+    /// `iw`/`aw` (and `iW`/`aW`) on the cursor's row. Each count takes one
+    /// more run of word or blank cells; `aw` also takes the blanks around a word.
+    fn text_object_range(&self, object: TextObject, count: u32) -> TextRange {
+        let (inner, big) = match object {
+            TextObject::InnerWord => (true, false),
+            TextObject::AWord => (false, false),
+            TextObject::InnerBigWord => (true, true),
+            TextObject::ABigWord => (false, true),
+        };
+        let y = self.player_pos.y;
+        let len = self.row_len(y);
+        let class = |x: usize| self.class_at(x, y, big);
+        let run_start = |mut x: usize| {
+            let c = class(x);
+            while x > 0 && class(x - 1) == c {
+                x -= 1;
+            }
+            x
+        };
+        let run_end = |mut x: usize| {
+            let c = class(x);
+            while x + 1 < len && class(x + 1) == c {
+                x += 1;
+            }
+            x
+        };
+
+        let mut start = run_start(self.player_pos.x);
+        let mut end = self.player_pos.x;
+        let mut next = start;
+        for _ in 0..count.max(1) {
+            if next >= len {
+                break;
+            }
+            let on_blank = class(next) == CharClass::Blank;
+            end = run_end(next);
+            // A word takes the blanks after it, blanks take the word after them
+            if !inner && end + 1 < len && on_blank != (class(end + 1) == CharClass::Blank) {
+                end = run_end(end + 1);
+            }
+            next = end + 1;
+        }
+        // With no blanks after the word, aw takes the blanks before it
+        if !inner
+            && class(start) != CharClass::Blank
+            && class(end) != CharClass::Blank
+            && start > 0
+            && class(start - 1) == CharClass::Blank
+        {
+            start = run_start(start - 1);
+        }
+
+        TextRange {
+            start: Position { x: start, y },
+            end: Position { x: end, y },
+            linewise: false,
+        }
+    }
+
+    /// The (row, first column, last column) spans a range covers.
+    fn range_rows(&self, range: TextRange) -> Vec<(usize, usize, usize)> {
+        (range.start.y..=range.end.y)
+            .filter_map(|y| {
+                let len = self.row_len(y);
+                let first = if y == range.start.y && !range.linewise {
+                    range.start.x
+                } else {
+                    0
+                };
+                let last = if y == range.end.y && !range.linewise {
+                    range.end.x.min(len.saturating_sub(1))
+                } else {
+                    len.saturating_sub(1)
+                };
+                (len > 0 && first <= last).then_some((y, first, last))
+            })
+            .collect()
+    }
+
+    fn read_range(&self, range: TextRange) -> Register {
+        let text = self
+            .range_rows(range)
+            .into_iter()
+            .map(|(y, first, last)| (first..=last).map(|x| self.get_char_at(x, y)).collect())
+            .collect();
+        Register {
+            text,
+            linewise: range.linewise,
+        }
+    }
+
+    /// `p` / `P`. Text can't shift in a grid, so the register is written
+    /// over the layout: charwise after (or at) the cursor, stopping at
+    /// walls; linewise over the row below (or the cursor's row).
+    fn put(&mut self, after: bool, count: u32) {
+        let Some(register) = self.register.clone() else {
+            return;
+        };
+        let count = count.max(1) as usize;
+        let here = self.player_pos;
+
+        if register.linewise {
+            let first = if after { here.y + 1 } else { here.y };
+            let lines = register.text.iter().cycle().take(register.text.len() * count);
+            for (i, line) in lines.enumerate() {
+                let y = first + i;
+                if y >= self.current_level.height() {
+                    break;
+                }
+                for (x, c) in line.chars().enumerate() {
+                    self.write_cell(x, y, c);
+                }
+            }
+            if first < self.current_level.height() && !self.current_level.is_wall(here.x, first) {
+                self.player_pos.y = first;
+            }
+        } else {
+            let mut pos = Position {
+                x: if after { here.x + 1 } else { here.x },
+                y: here.y,
+            };
+            let mut last = None;
+            for (i, segment) in register.text.iter().enumerate() {
+                let text = if register.text.len() == 1 {
+                    segment.repeat(count)
+                } else {
+                    segment.clone()
+                };
+                if i > 0 {
+                    pos = Position { x: 0, y: pos.y + 1 };
+                }
+                for c in text.chars() {
+                    if pos.x >= self.row_len(pos.y) || self.current_level.is_wall(pos.x, pos.y) {
+                        break;
+                    }
+                    self.write_cell(pos.x, pos.y, c);
+                    last = Some(pos);
+                    pos.x += 1;
                 }
             }
+            // The cursor ends on the last character put
+            if let Some(last) = last {
+                self.player_pos = last;
+            }
+        }
+
+        if self.get_char_at(self.player_pos.x, self.player_pos.y) == '~' {
+            self.status = GameStatus::GameOver;
+        }
+    }
+
+    /// `a`: insert after the cursor. Escape without typing returns here.
+    fn append(&mut self) {
+        let here = self.player_pos;
+        let next = here.x + 1;
+        if next < self.row_len(here.y) && !self.current_level.is_wall(next, here.y) {
+            self.player_pos.x = next;
+        }
+        self.input_mode = InputMode::Insert(here);
+    }
+
+    /// Types over the cell under the cursor, then moves right. The cursor
+    /// hovers in insert mode, so it can cross water while typing a bridge.
+    fn insert_char(&mut self, c: char) {
+        let InputMode::Insert(_) = self.input_mode else {
+            return;
+        };
+        let here = self.player_pos;
+        self.write_cell(here.x, here.y, c);
+        self.input_mode = InputMode::Insert(here);
+        let next = here.x + 1;
+        if next < self.row_len(here.y) && !self.current_level.is_wall(next, here.y) {
+            self.player_pos.x = next;
+        }
+    }
+
+    /// Escape from insert mode lands on the last cell typed over, as Vim
+    /// steps back onto the last inserted char.
+    fn leave_insert_mode(&mut self) {
+        if let InputMode::Insert(land) = self.input_mode {
+            self.input_mode = InputMode::Normal;
+            self.player_pos = land;
+            if self.get_char_at(land.x, land.y) == '~' {
+                self.status = GameStatus::GameOver;
+            }
         }
     }
 
@@ -518,173 +1786,215 @@ impl GameState {
         c.is_alphanumeric() || c == '_'
     }
 
-    fn move_word_forward(&mut self) {
-        let mut x = self.player_pos.x;
-        let mut y = self.player_pos.y;
-        let width = self.current_level.width();
-        let height = self.current_level.height();
+    /// Vim's character classes for word motions. Terrain (floor, water,
+    /// walls) counts as blank, so `w` leaps over it like whitespace.
+    /// With `big` (WORD motions) every non-blank char is in the same class.
+    fn char_class(&self, c: char, big: bool) -> CharClass {
+        if c.is_whitespace() || matches!(c, '.' | '~' | '#') {
+            CharClass::Blank
+        } else if big || self.is_word_char(c) {
+            CharClass::Word
+        } else {
+            CharClass::Punctuation
+        }
+    }
 
-        // 1. If we are on a word, skip to the end of it
-        // But VIM 'w' logic is: move to start of next word.
-        // If we are on a word, we consume it.
-        // If we are on whitespace, we consume it.
+    /// Class of a cell for word motions. Column `width` is a virtual
+    /// cell between rows, so a line break always separates words.
+    fn class_at(&self, x: usize, y: usize, big: bool) -> CharClass {
+        self.char_class(self.get_char_at(x, y), big)
+    }
 
-        // Simple state machine approach:
-        // State 0: Consume current word (if on one)
-        // State 1: Consume whitespace
-        // State 2: Stop at start of next word
+    /// One step forward in reading order, through the virtual line-break cell.
+    fn step_forward(&self, x: usize, y: usize) -> Option<(usize, usize)> {
+        let width = self.current_level.width();
+        if x < width {
+            Some((x + 1, y))
+        } else if y + 1 < self.current_level.height() {
+            Some((0, y + 1))
+        } else {
+            None
+        }
+    }
 
-        let mut passed_current_word = false;
+    fn step_back(&self, x: usize, y: usize) -> Option<(usize, usize)> {
+        if x > 0 {
+            Some((x - 1, y))
+        } else if y > 0 {
+            Some((self.current_level.width(), y - 1))
+        } else {
+            None
+        }
+    }
 
-        // Loop to find next position
-        loop {
-            x += 1;
-            if x >= width {
-                x = 0;
-                y += 1;
-                if y >= height {
-                    // End of document
-                    return;
+    // w / W: skip the rest of the current word, then any blanks.
+    // Stays put if there is no next word.
+    fn move_word_forward(&mut self, big: bool) {
+        let (mut x, mut y) = (self.player_pos.x, self.player_pos.y);
+        let start_class = self.class_at(x, y, big);
+
+        // 1. Consume the current word (if on one)
+        if start_class != CharClass::Blank {
+            while self.class_at(x, y, big) == start_class {
+                match self.step_forward(x, y) {
+                    Some(next) => (x, y) = next,
+                    None => return,
                 }
             }
+        }
 
-            let c = self.get_char_at(x, y);
-            // println!("Checking ({}, {}): '{}', passed_word: {}", x, y, c, passed_current_word);
-
-            if self.current_level.is_wall(x, y) {
-                continue; // Treat walls as invisible/skip? Or stop? Let's skip.
-            }
-
-            if self.is_word_char(c) {
-                if passed_current_word {
-                    // Found start of next word
-                    self.player_pos.x = x;
-                    self.player_pos.y = y;
-                    return;
-                }
-            } else {
-                // We hit non-word char (whitespace or punctuation)
-                passed_current_word = true;
+        // 2. Consume blanks, stopping at the start of the next word
+        while self.class_at(x, y, big) == CharClass::Blank {
+            match self.step_forward(x, y) {
+                Some(next) => (x, y) = next,
+                None => return,
             }
         }
-    }
 
-    fn move_word_back(&mut self) {
-        let mut x = self.player_pos.x;
-        let mut y = self.player_pos.y;
-        let width = self.current_level.width();
+        self.player_pos.x = x;
+        self.player_pos.y = y;
+    }
 
-        // Helper to move back one step
-        let move_back_one = |x: &mut usize, y: &mut usize| -> bool {
-            if *x > 0 {
-                *x -= 1;
-                true
-            } else if *y > 0 {
-                *y -= 1;
-                *x = width - 1;
-                true
-            } else {
-                false
-            }
-        };
+    // b / B: back to the start of the current or previous word.
+    fn move_word_back(&mut self, big: bool) {
+        let (mut x, mut y) = (self.player_pos.x, self.player_pos.y);
 
         // 1. Move back one step initially
-        if !move_back_one(&mut x, &mut y) {
-            return;
+        match self.step_back(x, y) {
+            Some(prev) => (x, y) = prev,
+            None => return,
         }
 
-        // 2. Skip any non-word characters (whitespace/punctuation) going backwards
-        while !self.is_word_char(self.get_char_at(x, y)) {
-            if !move_back_one(&mut x, &mut y) {
-                self.player_pos.x = x;
-                self.player_pos.y = y;
-                return;
+        // 2. Skip any blanks going backwards
+        while self.class_at(x, y, big) == CharClass::Blank {
+            match self.step_back(x, y) {
+                Some(prev) => (x, y) = prev,
+                None => return,
             }
         }
 
-        // 3. We are now on the last character of a word (or middle).
-        // Move back until we find the start of this word.
-
-        loop {
-            // Check previous position
-            let mut prev_x = x;
-            let mut prev_y = y;
-
-            if !move_back_one(&mut prev_x, &mut prev_y) {
-                // We reached start of file, so current pos (x,y) is start of word
-                break;
-            }
-
-            let c = self.get_char_at(prev_x, prev_y);
-
-            if !self.is_word_char(c) {
-                // Previous char is not a word char, so current pos (x,y) is start of word
+        // 3. Move back to the first char of this word
+        let class = self.class_at(x, y, big);
+        while let Some((px, py)) = self.step_back(x, y) {
+            if self.class_at(px, py, big) != class {
                 break;
             }
-
-            // Move to previous
-            x = prev_x;
-            y = prev_y;
+            (x, y) = (px, py);
         }
 
         self.player_pos.x = x;
         self.player_pos.y = y;
     }
 
-    fn move_word_end(&mut self) {
-        let mut x = self.player_pos.x;
-        let mut y = self.player_pos.y;
-        let width = self.current_level.width();
-        let height = self.current_level.height();
-
-        // Helper to move forward one step
-        let move_fwd_one = |x: &mut usize, y: &mut usize| -> bool {
-            *x += 1;
-            if *x >= width {
-                *x = 0;
-                *y += 1;
-            }
-            if *y >= height {
-                return false;
-            }
-            true
-        };
+    // e / E: forward to the end of the current or next word.
+    fn move_word_end(&mut self, big: bool) {
+        let (mut x, mut y) = (self.player_pos.x, self.player_pos.y);
 
         // 1. Move forward one step initially
-        if !move_fwd_one(&mut x, &mut y) {
-            return;
+        match self.step_forward(x, y) {
+            Some(next) => (x, y) = next,
+            None => return,
         }
 
-        // 2. Skip any non-word characters (whitespace) going forward
-        while !self.is_word_char(self.get_char_at(x, y)) {
-            if !move_fwd_one(&mut x, &mut y) {
-                return;
+        // 2. Skip any blanks going forward
+        while self.class_at(x, y, big) == CharClass::Blank {
+            match self.step_forward(x, y) {
+                Some(next) => (x, y) = next,
+                None => return,
             }
         }
 
-        // 3. We are now on a word char. Move forward until the NEXT char is non-word.
-        loop {
-            let mut next_x = x;
-            let mut next_y = y;
-
-            if !move_fwd_one(&mut next_x, &mut next_y) {
-                // End of file, so current is end of word
+        // 3. Move forward until the NEXT char is in another class
+        let class = self.class_at(x, y, big);
+        while let Some((nx, ny)) = self.step_forward(x, y) {
+            if self.class_at(nx, ny, big) != class {
                 break;
             }
+            (x, y) = (nx, ny);
+        }
 
-            if !self.is_word_char(self.get_char_at(next_x, next_y)) {
-                // Next char is not a word char, so current is end of word
-                break;
+        self.player_pos.x = x;
+        self.player_pos.y = y;
+    }
+
+    // ge / gE: back to the end of the previous word.
+    fn move_word_end_back(&mut self, big: bool) {
+        let (mut x, mut y) = (self.player_pos.x, self.player_pos.y);
+        let start_class = self.class_at(x, y, big);
+
+        // 1. Leave the current word (if on one)
+        if start_class != CharClass::Blank {
+            while self.class_at(x, y, big) == start_class {
+                match self.step_back(x, y) {
+                    Some(prev) => (x, y) = prev,
+                    None => return,
+                }
             }
+        }
 
-            x = next_x;
-            y = next_y;
+        // 2. Skip blanks; the first non-blank is the previous word's end
+        while self.class_at(x, y, big) == CharClass::Blank {
+            match self.step_back(x, y) {
+                Some(prev) => (x, y) = prev,
+                None => return,
+            }
         }
 
         self.player_pos.x = x;
         self.player_pos.y = y;
     }
 
+    /// Column of the first non-blank cell of row `y`. If every one is
+    /// blank, Vim stops at the end of the line: the last open cell.
+    fn first_non_blank(&self, y: usize) -> Option<usize> {
+        let width = self.row_len(y);
+        (0..width)
+            .find(|&x| self.class_at(x, y, false) != CharClass::Blank)
+            .or_else(|| (0..width).rev().find(|&x| !self.current_level.is_wall(x, y)))
+    }
+
+    /// Column of the last non-blank cell of row `y`, or of its first open
+    /// cell if every one is blank.
+    fn last_non_blank(&self, y: usize) -> Option<usize> {
+        let width = self.row_len(y);
+        (0..width)
+            .rev()
+            .find(|&x| self.class_at(x, y, false) != CharClass::Blank)
+            .or_else(|| (0..width).find(|&x| !self.current_level.is_wall(x, y)))
+    }
+
+    /// `^`, `_`, `+`, `-` and Enter: the first non-blank cell of the row
+    /// `lines` down (or up). Rows in between are skipped; without such a
+    /// row the cursor stays put.
+    fn move_to_line_start(&mut self, lines: usize, down: bool) {
+        let here = self.player_pos.y;
+        let y = if down {
+            here + lines
+        } else {
+            match here.checked_sub(lines) {
+                Some(y) => y,
+                None => return,
+            }
+        };
+        if y < self.current_level.height()
+            && let Some(x) = self.first_non_blank(y)
+        {
+            self.jump_to(Position { x, y });
+        }
+    }
+
+    /// `g_`: the last non-blank cell of the row `lines` down, or of the
+    /// last row if there are fewer.
+    fn move_last_non_blank(&mut self, lines: usize) {
+        let last_y = self.current_level.height().saturating_sub(1);
+        let y = (self.player_pos.y + lines).min(last_y);
+        if let Some(x) = self.last_non_blank(y) {
+            self.jump_to(Position { x, y });
+        }
+    }
+
+    /// `0`: the first cell of the row's text. Unlike `^` it doesn't skip
+    /// leading blanks, so it can land in water.
     fn move_line_start(&mut self) {
         let y = self.player_pos.y;
         // Find first non-wall from left
@@ -700,7 +2010,8 @@ impl GameState {
         }
     }
 
-    fn move_line_end(&mut self) {
+    /// Returns false if the line has no walkable cell.
+    fn move_line_end(&mut self) -> bool {
         let y = self.player_pos.y;
         // Find first non-wall from right
         for x in (0..self.current_level.width()).rev() {
@@ -710,87 +2021,171 @@ impl GameState {
                 if self.get_char_at(x, y) == '~' {
                     self.status = GameStatus::GameOver;
                 }
-                return;
+                return true;
             }
         }
+        false
+    }
+
+    /// Rows of the level that are on screen.
+    fn visible_rows(&self) -> std::ops::RangeInclusive<usize> {
+        let last = self.current_level.height().saturating_sub(1);
+        self.viewport.top.min(last)..=self.viewport.bottom().min(last)
+    }
+
+    /// Moves to row `y` of the current column, like a jump: whatever lies
+    /// in between is skipped.
+    fn land_on_row(&mut self, y: usize) {
+        self.player_pos.y = y;
+        if self.get_char_at(self.player_pos.x, y) == '~' {
+            self.status = GameStatus::GameOver;
+        }
     }
 
-    fn move_screen_top(&mut self) {
+    fn move_screen_top(&mut self, count: usize) {
         let x = self.player_pos.x;
-        // Find first non-wall from top
-        for y in 0..self.current_level.height() {
-            if !self.current_level.is_wall(x, y) {
-                self.player_pos.y = y;
-                // Check hazard
-                if self.get_char_at(x, y) == '~' {
-                    self.status = GameStatus::GameOver;
-                }
-                return;
-            }
+        // Find the count-th non-wall from the top of the screen (or the last one if there are fewer)
+        let rows: Vec<usize> = self
+            .visible_rows()
+            .filter(|&y| !self.current_level.is_wall(x, y))
+            .collect();
+        if let Some(&y) = rows.get(count.max(1) - 1).or(rows.last()) {
+            self.land_on_row(y);
         }
     }
 
     fn move_screen_middle(&mut self) {
         let x = self.player_pos.x;
-        let mid_y = self.current_level.height() / 2;
-
-        let mut target_y = None;
-
-        // Try mid, then search out
-        if !self.current_level.is_wall(x, mid_y) {
-            target_y = Some(mid_y);
-        } else {
-            // Search up/down for closest valid
-            for offset in 1..self.current_level.height() {
-                if mid_y >= offset && !self.current_level.is_wall(x, mid_y - offset) {
-                    target_y = Some(mid_y - offset);
-                    break;
-                }
-                if mid_y + offset < self.current_level.height()
-                    && !self.current_level.is_wall(x, mid_y + offset)
-                {
-                    target_y = Some(mid_y + offset);
-                    break;
-                }
-            }
-        }
+        let visible = self.visible_rows();
+        let (first, last) = (*visible.start(), *visible.end());
+        let mid_y = first + (last + 1 - first) / 2;
+
+        // Try mid, then search out without leaving the screen
+        let target_y = (0..=last - first).find_map(|offset| {
+            [mid_y.checked_sub(offset), Some(mid_y + offset)]
+                .into_iter()
+                .flatten()
+                .find(|&y| visible.contains(&y) && !self.current_level.is_wall(x, y))
+        });
 
         if let Some(y) = target_y {
-            self.player_pos.y = y;
-            if self.get_char_at(x, y) == '~' {
-                self.status = GameStatus::GameOver;
-            }
+            self.land_on_row(y);
         }
     }
 
-    fn move_screen_bottom(&mut self) {
+    fn move_screen_bottom(&mut self, count: usize) {
         let x = self.player_pos.x;
-        // Find first non-wall from bottom
-        for y in (0..self.current_level.height()).rev() {
-            if !self.current_level.is_wall(x, y) {
-                self.player_pos.y = y;
-                // Check hazard
-                if self.get_char_at(x, y) == '~' {
-                    self.status = GameStatus::GameOver;
-                }
-                return;
-            }
+        // Find the count-th non-wall from the bottom of the screen (or the topmost one if there are fewer)
+        let rows: Vec<usize> = self
+            .visible_rows()
+            .rev()
+            .filter(|&y| !self.current_level.is_wall(x, y))
+            .collect();
+        if let Some(&y) = rows.get(count.max(1) - 1).or(rows.last()) {
+            self.land_on_row(y);
         }
     }
 
-    fn move_paragraph_forward(&mut self) {
+    /// `gg` and `G`: the first or last open row in the cursor's column.
+    fn goto_end_line(&mut self, last: bool) {
         let x = self.player_pos.x;
-        let mut y = self.player_pos.y + 1;
+        let mut rows = 0..self.current_level.height();
+        let open = |y: &usize| !self.current_level.is_wall(x, *y);
+        let target = if last { rows.rev().find(open) } else { rows.find(open) };
+        if let Some(y) = target {
+            self.land_on_row(y);
+        }
+    }
+
+    /// `{N}G`, `{N}gg` and `:{N}`: line N, counted from 1 as in the gutter.
+    /// The column is kept, and nothing happens if that cell is a wall.
+    fn goto_line(&mut self, line: usize) {
+        let y = line.clamp(1, self.current_level.height().max(1)) - 1;
+        if !self.current_level.is_wall(self.player_pos.x, y) {
+            self.land_on_row(y);
+        }
+    }
+
+    /// Ctrl-e / Ctrl-y: scrolls by lines. The cursor only moves when it
+    /// would go off screen.
+    fn scroll_lines(&mut self, delta: isize) {
+        let before = self.viewport;
+        if self.viewport.scroll(delta, self.current_level.height()) != 0
+            && !self.keep_cursor_on_screen()
+        {
+            self.viewport = before;
+        }
+    }
+
+    /// Ctrl-d / Ctrl-u: scrolls half a screen (or `lines`), and the cursor
+    /// moves down or up as many lines, even when the view can't scroll.
+    fn scroll_half_page(&mut self, down: bool, lines: Option<usize>) {
+        let height = self.current_level.height();
+        let amount = lines.unwrap_or(self.viewport.rows / 2).max(1) as isize;
+        let delta = if down { amount } else { -amount };
+        self.viewport.scroll(delta, height);
+
+        // Walls stop the cursor short of its target
+        let x = self.player_pos.x;
+        let from = self.player_pos.y;
+        let target = from.saturating_add_signed(delta).min(height.saturating_sub(1));
+        let landing = if down {
+            (from + 1..=target).rev().find(|&y| !self.current_level.is_wall(x, y))
+        } else {
+            (target..from).find(|&y| !self.current_level.is_wall(x, y))
+        };
+        if let Some(y) = landing {
+            self.land_on_row(y);
+        }
+    }
+
+    /// Ctrl-f / Ctrl-b: scrolls a screen, keeping two lines of context.
+    fn scroll_page(&mut self, down: bool) {
+        let before = self.viewport;
+        let amount = self.viewport.rows.saturating_sub(2).max(1) as isize;
+        let delta = if down { amount } else { -amount };
+        if self.viewport.scroll(delta, self.current_level.height()) != 0
+            && !self.keep_cursor_on_screen()
+        {
+            self.viewport = before;
+        }
+    }
+
+    /// After a scroll, moves the cursor onto the nearest open row on screen.
+    /// Returns false if its column has no open row there.
+    fn keep_cursor_on_screen(&mut self) -> bool {
+        let x = self.player_pos.x;
+        let y = self.player_pos.y;
+        let visible = self.visible_rows();
+        if visible.contains(&y) {
+            return true;
+        }
+        let open = |y: &usize| !self.current_level.is_wall(x, *y);
+        let landing = if y < *visible.start() {
+            visible.clone().find(open)
+        } else {
+            visible.clone().rev().find(open)
+        };
+        match landing {
+            Some(y) => {
+                self.land_on_row(y);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn move_paragraph_forward(&mut self) {
+        let x = self.player_pos.x;
+        let mut y = self.player_pos.y + 1;
 
         while y < self.current_level.height() {
-            if self.is_line_empty(y) {
-                if !self.current_level.is_wall(x, y) {
-                    self.player_pos.y = y;
-                    if self.get_char_at(x, y) == '~' {
-                        self.status = GameStatus::GameOver;
-                    }
-                    return;
+            if self.is_line_empty(y) && !self.current_level.is_wall(x, y) {
+                self.player_pos.y = y;
+                if self.get_char_at(x, y) == '~' {
+                    self.status = GameStatus::GameOver;
                 }
+                return;
             }
             y += 1;
         }
@@ -812,14 +2207,12 @@ impl GameState {
         let mut y = self.player_pos.y - 1;
 
         loop {
-            if self.is_line_empty(y) {
-                if !self.current_level.is_wall(x, y) {
-                    self.player_pos.y = y;
-                    if self.get_char_at(x, y) == '~' {
-                        self.status = GameStatus::GameOver;
-                    }
-                    return;
+            if self.is_line_empty(y) && !self.current_level.is_wall(x, y) {
+                self.player_pos.y = y;
+                if self.get_char_at(x, y) == '~' {
+                    self.status = GameStatus::GameOver;
                 }
+                return;
             }
             if y == 0 {
                 break;
@@ -828,6 +2221,96 @@ impl GameState {
         }
     }
 
+    /// `)` and `(`: the next sentence start after the cursor, or the last
+    /// one before it. With none, the cursor stays put.
+    fn move_sentence(&mut self, forward: bool) {
+        let here = (self.player_pos.y, self.player_pos.x);
+        let starts = self.sentence_starts();
+        let found = if forward {
+            starts.into_iter().find(|pos| (pos.y, pos.x) > here)
+        } else {
+            starts.into_iter().rev().find(|pos| (pos.y, pos.x) < here)
+        };
+        if let Some(pos) = found {
+            self.jump_to(pos);
+        }
+    }
+
+    /// Where sentences start, in reading order. As in Vim, a sentence ends
+    /// at a `.`, `!` or `?` followed by the end of the row or a space
+    /// (water counts as one), with any `)`, `]`, `"` or `'` in between; the
+    /// next non-blank cell starts a new one. An all-blank row is a boundary
+    /// too, and so a start, on its first open cell.
+    fn sentence_starts(&self) -> Vec<Position> {
+        let rows = self.rows();
+        let is_blank = |c: char| self.char_class(c, false) == CharClass::Blank;
+        let mut starts = Vec::new();
+        // The next non-blank cell starts a sentence
+        let mut pending = true;
+        let mut after_blank_row = false;
+        for (y, row) in rows.iter().enumerate() {
+            if row.iter().all(|&c| is_blank(c)) {
+                if !after_blank_row
+                    && let Some(x) = (0..row.len()).find(|&x| !self.current_level.is_wall(x, y))
+                {
+                    starts.push(Position { x, y });
+                }
+                after_blank_row = true;
+                pending = true;
+                continue;
+            }
+            after_blank_row = false;
+            let mut x = 0;
+            while x < row.len() {
+                let c = row[x];
+                if pending && !is_blank(c) {
+                    starts.push(Position { x, y });
+                    pending = false;
+                }
+                x += 1;
+                if matches!(c, '.' | '!' | '?') {
+                    let end = (x..row.len())
+                        .find(|&i| !matches!(row[i], ')' | ']' | '"' | '\''))
+                        .unwrap_or(row.len());
+                    if end == row.len() || matches!(row[end], ' ' | '\t' | '~') {
+                        pending = true;
+                        x = end;
+                    }
+                }
+            }
+        }
+        starts
+    }
+
+    /// `]]` and `[[` (`][` and `[]` with `}`): the next row, or the one
+    /// before, whose first open cell is `brace`. Without one, the last or
+    /// the first row with an open cell. Walls stand in for column 0.
+    fn move_section(&mut self, brace: char, forward: bool) {
+        let y = self.player_pos.y;
+        let height = self.current_level.height();
+        let first_open =
+            |y: usize| (0..self.row_len(y)).find(|&x| !self.current_level.is_wall(x, y));
+        let starts_section =
+            |y: &usize| first_open(*y).is_some_and(|x| self.get_char_at(x, *y) == brace);
+        let found = if forward {
+            (y + 1..height).find(starts_section)
+        } else {
+            (0..y).rev().find(starts_section)
+        };
+        let found = found.or_else(|| {
+            if forward {
+                (y + 1..height).rev().find(|&y| first_open(y).is_some())
+            } else {
+                (0..y).find(|&y| first_open(y).is_some())
+            }
+        });
+        let Some(y) = found else {
+            return;
+        };
+        let x = first_open(y).unwrap_or(0);
+        self.jump_to(Position { x, y });
+    }
+
     fn is_line_empty(&self, y: usize) -> bool {
         if y >= self.current_level.height() {
             return false;
@@ -843,10 +2326,93 @@ impl GameState {
         true
     }
 
+    /// `%`: from the first bracket at or after the cursor on its row to the
+    /// one that pairs with it, counting nested pairs across rows.
+    fn match_pair(&mut self) {
+        let y = self.player_pos.y;
+        let Some((x, (open, close))) = (self.player_pos.x..self.row_len(y))
+            .find_map(|x| bracket_pair(self.get_char_at(x, y)).map(|pair| (x, pair)))
+        else {
+            return;
+        };
+        let from = Position { x, y };
+        let forward = self.get_char_at(x, y) == open;
+        if let Some(pos) = self.find_unmatched(from, open, close, forward) {
+            self.jump_to(pos);
+        }
+    }
+
+    /// `[(`, `[{`, `])` and `]}`: the `count`-th enclosing bracket that is
+    /// still open before the cursor (closed after it, going forward).
+    fn goto_unmatched(&mut self, open: char, close: char, forward: bool, count: u32) {
+        let mut pos = self.player_pos;
+        for _ in 0..count.max(1) {
+            match self.find_unmatched(pos, open, close, forward) {
+                Some(found) => pos = found,
+                None => break,
+            }
+        }
+        if pos != self.player_pos {
+            self.jump_to(pos);
+        }
+    }
+
+    /// The first `close` after `from` (or `open` before it, going back)
+    /// that isn't paired with a bracket in between.
+    fn find_unmatched(
+        &self,
+        from: Position,
+        open: char,
+        close: char,
+        forward: bool,
+    ) -> Option<Position> {
+        let width = self.current_level.width();
+        let start = from.y * width + from.x;
+        let cells: Box<dyn Iterator<Item = usize>> = if forward {
+            Box::new(start + 1..width * self.current_level.height())
+        } else {
+            Box::new((0..start).rev())
+        };
+        let (wanted, nested) = if forward { (close, open) } else { (open, close) };
+        let mut depth = 0;
+        for i in cells {
+            let pos = Position {
+                x: i % width,
+                y: i / width,
+            };
+            let c = self.get_char_at(pos.x, pos.y);
+            if c == nested {
+                depth += 1;
+            } else if c == wanted {
+                if depth == 0 {
+                    return Some(pos);
+                }
+                depth -= 1;
+            }
+        }
+        None
+    }
+
     fn check_win_condition(&mut self) {
-        if self.player_pos == self.current_level.target_pos {
+        // In insert mode the cursor hovers; it only counts once Escape lands it
+        let level = &self.current_level;
+        let at_target = self.player_pos == level.target_pos;
+        if level.goal.is_met(at_target, &level.layout)
+            && !matches!(self.input_mode, InputMode::Insert(_))
+        {
             self.status = GameStatus::LevelComplete;
             self.calculate_score();
+            if !self.is_auto_playing {
+                self.finished_replay = Some(Replay {
+                    level_id: self.current_level.id.clone(),
+                    level_hash: self.level_hash,
+                    steps: self.recording.clone(),
+                    keystrokes: self.keystrokes,
+                    time: self.time_elapsed,
+                    score: self.score,
+                    case_options: self.case_options,
+                });
+            }
         }
     }
 
@@ -856,14 +2422,27 @@ impl GameState {
         let keystroke_penalty =
             (self.keystrokes as i32 - self.current_level.par_keystrokes as i32).max(0) * 50;
         let penalty_score = self.penalties as i32 * 100;
+        let undo_score = self.undos as i32 * self.current_level.undo_penalty as i32;
 
-        self.score = base_score - time_penalty as i32 - keystroke_penalty - penalty_score;
+        self.score =
+            base_score - time_penalty as i32 - keystroke_penalty - penalty_score - undo_score;
+    }
+}
+
+/// The pair `c` belongs to, if it is a bracket `%` jumps between.
+fn bracket_pair(c: char) -> Option<(char, char)> {
+    match c {
+        '(' | ')' => Some(('(', ')')),
+        '[' | ']' => Some(('[', ']')),
+        '{' | '}' => Some(('{', '}')),
+        _ => None,
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::level::{Goal, KeyPolicy};
 
     fn create_test_level() -> Level {
         Level {
@@ -873,12 +2452,15 @@ mod tests {
             layout: vec!["...".to_string(), "...".to_string(), "...".to_string()],
             start_pos: Position { x: 0, y: 0 },
             target_pos: Position { x: 2, y: 2 },
+            goal: Goal::Reach,
             allowed_keys: vec![
                 "h".to_string(),
                 "j".to_string(),
                 "k".to_string(),
                 "l".to_string(),
             ],
+            key_policy: KeyPolicy::Reject,
+            undo_penalty: 0,
             trained_commands: vec![],
             tutorial_text: String::new(),
             par_time: 10.0,
@@ -911,11 +2493,10 @@ mod tests {
         game.handle_command(VimCommand::MoveDown); // 2, 1
         game.handle_command(VimCommand::MoveDown); // 2, 2
 
-        if let GameStatus::LevelComplete = game.status {
-            assert!(true);
-        } else {
-            assert!(false, "Game should be complete");
-        }
+        assert!(
+            matches!(game.status, GameStatus::LevelComplete),
+            "Game should be complete"
+        );
     }
 
     #[test]
@@ -929,7 +2510,10 @@ mod tests {
             ],
             start_pos: Position { x: 0, y: 0 },
             target_pos: Position { x: 0, y: 1 }, // Unreachable in this 1-line level
+            goal: Goal::Reach,
             allowed_keys: vec![],
+            key_policy: KeyPolicy::Reject,
+            undo_penalty: 0,
             trained_commands: vec![],
             tutorial_text: String::new(),
             par_time: 10.0,
@@ -980,7 +2564,10 @@ mod tests {
             ],
             start_pos: Position { x: 1, y: 1 },
             target_pos: Position { x: 18, y: 8 },
+            goal: Goal::Reach,
             allowed_keys: vec![],
+            key_policy: KeyPolicy::Reject,
+            undo_penalty: 0,
             trained_commands: vec![],
             tutorial_text: String::new(),
             par_time: 10.0,
@@ -1016,7 +2603,10 @@ mod tests {
             ],
             start_pos: Position { x: 0, y: 0 },
             target_pos: Position { x: 6, y: 0 },
+            goal: Goal::Reach,
             allowed_keys: vec![],
+            key_policy: KeyPolicy::Reject,
+            undo_penalty: 0,
             trained_commands: vec![],
             tutorial_text: String::new(),
             par_time: 10.0,
@@ -1042,4 +2632,1076 @@ mod tests {
         game.handle_char_input('a');
         assert_eq!(game.player_pos.x, 0);
     }
+
+    #[test]
+    fn test_repeat_till_skips_adjacent_char() {
+        let mut level = create_test_level();
+        level.layout = vec!["x.x.x.x".to_string()];
+        level.start_pos = Position { x: 0, y: 0 };
+        level.target_pos = Position { x: 6, y: 0 };
+        level.allowed_keys = vec![];
+        let mut game = GameState::new(level);
+
+        game.handle_command(VimCommand::TillNextChar('x'));
+        assert_eq!(game.player_pos.x, 1);
+        // ; doesn't stick in front of the x the t stopped at
+        game.handle_command(VimCommand::RepeatFind);
+        assert_eq!(game.player_pos.x, 3);
+        // A count counts from the cursor, as for t itself
+        game.handle_command(VimCommand::Counted(2, Box::new(VimCommand::RepeatFind)));
+        assert_eq!(game.player_pos.x, 5);
+        game.handle_command(VimCommand::RepeatFindReverse);
+        assert_eq!(game.player_pos.x, 3);
+    }
+
+    #[test]
+    fn test_disallowed_command_is_rejected() {
+        let level = create_test_level();
+        let mut game = GameState::new(level);
+
+        // 'w' is not in allowed_keys (h, j, k, l)
+        game.handle_command(VimCommand::MoveWordForward);
+        assert_eq!(game.player_pos, Position { x: 0, y: 0 });
+        assert_eq!(game.keystrokes, 0);
+        assert_eq!(game.blocked_command, Some(VimCommand::MoveWordForward));
+
+        // Start commands are blocked before waiting for a char
+        game.handle_command(VimCommand::StartFindNext);
+        assert_eq!(game.input_mode, InputMode::Normal);
+
+        game.handle_command(VimCommand::MoveRight);
+        assert_eq!(game.player_pos.x, 1);
+    }
+
+    #[test]
+    fn test_disallowed_command_is_penalized() {
+        let mut level = create_test_level();
+        level.key_policy = KeyPolicy::Penalize;
+        let mut game = GameState::new(level);
+
+        game.handle_command(VimCommand::MoveLineEnd);
+        assert_eq!(game.player_pos.x, 2);
+        assert_eq!(game.keystrokes, 1);
+        assert_eq!(game.penalties, 1);
+        assert_eq!(game.blocked_command, Some(VimCommand::MoveLineEnd));
+    }
+
+    #[test]
+    fn test_counted_commands() {
+        let level = Level {
+            id: "count_test".to_string(),
+            name: "Count Test".to_string(),
+            description: "Test".to_string(),
+            layout: vec![
+                "a;b;c;d ee ff".to_string(), // 0123456789012
+                "...........~.".to_string(),
+            ],
+            start_pos: Position { x: 0, y: 0 },
+            target_pos: Position { x: 12, y: 1 },
+            goal: Goal::Reach,
+            allowed_keys: vec![],
+            key_policy: KeyPolicy::Reject,
+            undo_penalty: 0,
+            trained_commands: vec![],
+            tutorial_text: String::new(),
+            par_time: 10.0,
+            par_keystrokes: 5,
+        };
+        let mut game = GameState::new(level);
+
+        // 2l -> (2,0), costs two keystrokes
+        game.handle_command(VimCommand::Counted(2, Box::new(VimCommand::MoveRight)));
+        assert_eq!(game.player_pos.x, 2);
+        assert_eq!(game.keystrokes, 2);
+
+        // 2f; lands on the second ';' to the right
+        game.handle_command(VimCommand::Counted(2, Box::new(VimCommand::StartFindNext)));
+        game.handle_char_input(';');
+        assert_eq!(game.player_pos.x, 5);
+        assert_eq!(game.keystrokes, 5);
+
+        // , and ; repeat the find without its count, but take their own
+        game.handle_command(VimCommand::Counted(2, Box::new(VimCommand::RepeatFindReverse)));
+        assert_eq!(game.player_pos.x, 1);
+        game.handle_command(VimCommand::Counted(2, Box::new(VimCommand::RepeatFind)));
+        assert_eq!(game.player_pos.x, 5);
+        game.handle_command(VimCommand::RepeatFindReverse);
+        assert_eq!(game.player_pos.x, 3);
+        game.handle_command(VimCommand::RepeatFind);
+        assert_eq!(game.player_pos.x, 5);
+        assert_eq!(game.keystrokes, 11);
+
+        // There are too few e's for 9fe
+        game.handle_command(VimCommand::Counted(9, Box::new(VimCommand::StartFindNext)));
+        game.handle_char_input('e');
+        assert_eq!(game.player_pos.x, 5);
+
+        // 3w -> 'd', "ee", then the start of "ff"
+        game.handle_command(VimCommand::Counted(3, Box::new(VimCommand::MoveWordForward)));
+        assert_eq!(game.player_pos.x, 11);
+
+        // 2j stops as soon as the player falls in the water
+        game.handle_command(VimCommand::Counted(2, Box::new(VimCommand::MoveDown)));
+        assert_eq!(game.player_pos, Position { x: 11, y: 1 });
+        assert!(matches!(game.status, GameStatus::GameOver));
+    }
+
+    #[test]
+    fn test_word_vs_big_word_movement() {
+        let level = Level {
+            id: "big_word_test".to_string(),
+            name: "WORD Test".to_string(),
+            description: "Test".to_string(),
+            layout: vec![
+                "foo(bar); baz".to_string(), // 0123456789012
+            ],
+            start_pos: Position { x: 0, y: 0 },
+            target_pos: Position { x: 0, y: 1 },
+            goal: Goal::Reach,
+            allowed_keys: vec![],
+            key_policy: KeyPolicy::Reject,
+            undo_penalty: 0,
+            trained_commands: vec![],
+            tutorial_text: String::new(),
+            par_time: 10.0,
+            par_keystrokes: 5,
+        };
+        let mut game = GameState::new(level);
+
+        // 'w' stops at punctuation: '(' (3,0), then 'bar' (4,0), then ');' (7,0)
+        game.handle_command(VimCommand::MoveWordForward);
+        assert_eq!(game.player_pos.x, 3);
+        game.handle_command(VimCommand::MoveWordForward);
+        assert_eq!(game.player_pos.x, 4);
+        game.handle_command(VimCommand::MoveWordForward);
+        assert_eq!(game.player_pos.x, 7);
+
+        // 'B' goes back to the start of the WORD "foo(bar);"
+        game.handle_command(VimCommand::MoveBigWordBack);
+        assert_eq!(game.player_pos.x, 0);
+
+        // 'W' skips the whole WORD, 'E' lands on its end
+        game.handle_command(VimCommand::MoveBigWordForward);
+        assert_eq!(game.player_pos.x, 10);
+        game.handle_command(VimCommand::MoveBigWordEnd);
+        assert_eq!(game.player_pos.x, 12);
+
+        // 'gE' back to the end of "foo(bar);", 'ge' back to the end of "bar"
+        game.handle_command(VimCommand::MoveBigWordEndBack);
+        assert_eq!(game.player_pos.x, 8);
+        game.handle_command(VimCommand::MoveWordEndBack);
+        assert_eq!(game.player_pos.x, 6);
+        game.handle_command(VimCommand::MoveWordEndBack);
+        assert_eq!(game.player_pos.x, 3);
+    }
+
+    #[test]
+    fn test_undo_redo() {
+        let mut level = create_test_level();
+        level.layout[0] = "ab.".to_string();
+        level.allowed_keys = vec![];
+        let mut game = GameState::new(level);
+
+        // Bumping into the wall changes nothing, so there is nothing to undo
+        game.handle_command(VimCommand::MoveLeft);
+        game.handle_command(VimCommand::Undo);
+        assert_eq!(game.undos, 0);
+
+        game.handle_command(VimCommand::MoveRight);
+        game.handle_command(VimCommand::DeleteChar);
+        assert_eq!(game.current_level.layout[0], "a..");
+
+        game.handle_command(VimCommand::Undo);
+        assert_eq!(game.current_level.layout[0], "ab.");
+        assert_eq!(game.player_pos.x, 1);
+        game.handle_command(VimCommand::Undo);
+        assert_eq!(game.player_pos.x, 0);
+
+        game.handle_command(VimCommand::Counted(2, Box::new(VimCommand::Redo)));
+        assert_eq!(game.player_pos.x, 1);
+        assert_eq!(game.current_level.layout[0], "a..");
+        assert_eq!(game.undos, 2);
+        // h, u, l, x, u, u, 2<C-r>
+        assert_eq!(game.keystrokes, 8);
+
+        // A new command forgets the undone future
+        game.handle_command(VimCommand::Undo);
+        game.handle_command(VimCommand::MoveDown);
+        game.handle_command(VimCommand::Redo);
+        assert_eq!(game.player_pos, Position { x: 1, y: 1 });
+        assert_eq!(game.current_level.layout[0], "ab.");
+    }
+
+    #[test]
+    fn test_undo_out_of_game_over_needs_practice() {
+        let mut level = create_test_level();
+        level.layout[0] = "..~".to_string();
+        let mut game = GameState::new(level);
+
+        game.handle_command(VimCommand::Counted(2, Box::new(VimCommand::MoveRight)));
+        assert_eq!(game.status, GameStatus::GameOver);
+
+        game.handle_command(VimCommand::Undo);
+        assert_eq!(game.status, GameStatus::GameOver);
+
+        game.practice_mode = true;
+        game.handle_command(VimCommand::Undo);
+        assert_eq!(game.status, GameStatus::Playing);
+        assert_eq!(game.player_pos.x, 0);
+    }
+
+    fn one_line_level(row: &str) -> Level {
+        let mut level = create_test_level();
+        level.layout = vec![row.to_string(), ".".repeat(row.len())];
+        level.target_pos = Position { x: 0, y: 1 };
+        level.allowed_keys = vec![];
+        level
+    }
+
+    fn delete() -> VimCommand {
+        VimCommand::StartOperator(Operator::Delete)
+    }
+
+    #[test]
+    fn test_delete_with_motions() {
+        let mut game = GameState::new(one_line_level("foo bar;baz"));
+
+        game.handle_command(delete());
+        assert_eq!(game.input_mode, InputMode::OperatorPending);
+        game.handle_command(VimCommand::MoveWordForward);
+        assert_eq!(game.input_mode, InputMode::Normal);
+        assert_eq!(game.current_level.layout[0], "....bar;baz");
+        assert_eq!(game.register.as_ref().unwrap().text, vec!["foo "]);
+        assert_eq!(game.player_pos.x, 0);
+
+        // de is inclusive
+        game.handle_command(VimCommand::MoveWordForward);
+        game.handle_command(delete());
+        game.handle_command(VimCommand::MoveWordEnd);
+        assert_eq!(game.current_level.layout[0], ".......;baz");
+        assert_eq!(game.player_pos.x, 4);
+
+        game.handle_command(VimCommand::MoveWordForward);
+        game.handle_command(delete());
+        game.handle_command(VimCommand::MoveLineEnd);
+        assert_eq!(game.current_level.layout[0], "...........");
+        assert_eq!(game.register.as_ref().unwrap().text, vec![";baz"]);
+        // dw, w, de, w, d$
+        assert_eq!(game.keystrokes, 8);
+    }
+
+    #[test]
+    fn test_operator_counts_and_lines() {
+        let mut game = GameState::new(one_line_level("a b c d"));
+
+        // 2dw and d2w both delete two words
+        game.handle_command(VimCommand::Counted(2, Box::new(delete())));
+        game.handle_command(VimCommand::MoveWordForward);
+        assert_eq!(game.current_level.layout[0], "....c d");
+        assert_eq!(game.keystrokes, 3);
+
+        game.handle_command(delete());
+        game.handle_command(delete());
+        assert_eq!(game.current_level.layout[0], ".......");
+        let register = game.register.clone().unwrap();
+        assert!(register.linewise);
+        assert_eq!(register.text, vec!["....c d"]);
+        assert_eq!(game.keystrokes, 5);
+    }
+
+    #[test]
+    fn test_text_objects() {
+        let mut game = GameState::new(one_line_level("foo bar baz"));
+        game.player_pos.x = 5;
+        game.handle_command(delete());
+        game.handle_command(VimCommand::InsertMode);
+        game.handle_command(VimCommand::MoveWordForward);
+        assert_eq!(game.current_level.layout[0], "foo ... baz");
+
+        let mut game = GameState::new(one_line_level("foo bar baz"));
+        game.player_pos.x = 5;
+        game.handle_command(delete());
+        game.handle_command(VimCommand::Append);
+        game.handle_command(VimCommand::MoveWordForward);
+        assert_eq!(game.current_level.layout[0], "foo ....baz");
+        assert_eq!(game.player_pos.x, 4);
+        // d, a, w
+        assert_eq!(game.keystrokes, 3);
+    }
+
+    #[test]
+    fn test_escape_cancels_operator() {
+        let mut game = GameState::new(one_line_level("foo bar"));
+        game.handle_command(delete());
+        game.handle_command(VimCommand::Escape);
+        assert_eq!(game.input_mode, InputMode::Normal);
+        assert!(game.pending_operator.is_none());
+
+        // x isn't a motion, so it cancels rather than deleting
+        game.handle_command(delete());
+        game.handle_command(VimCommand::DeleteChar);
+        assert_eq!(game.input_mode, InputMode::Normal);
+        assert_eq!(game.current_level.layout[0], "foo bar");
+    }
+
+    #[test]
+    fn test_operator_over_the_exit_does_not_win() {
+        let mut level = one_line_level("S.E...");
+        level.target_pos = Position { x: 2, y: 0 };
+        let mut game = GameState::new(level);
+
+        // y4l measures 4l, which steps onto E on the way
+        game.handle_command(VimCommand::StartOperator(Operator::Yank));
+        game.handle_command(VimCommand::Counted(4, Box::new(VimCommand::MoveRight)));
+        assert_eq!(game.player_pos, Position { x: 0, y: 0 });
+        assert_eq!(game.status, GameStatus::Playing);
+        assert!(game.finished_replay.is_none());
+    }
+
+    #[test]
+    fn test_yank_and_put_build_a_bridge() {
+        let mut level = create_test_level();
+        level.layout = vec!["ab..".to_string(), "~~~~".to_string(), "...E".to_string()];
+        level.target_pos = Position { x: 3, y: 2 };
+        level.allowed_keys = vec![];
+        let mut game = GameState::new(level);
+
+        game.handle_command(VimCommand::StartOperator(Operator::Yank));
+        game.handle_command(VimCommand::StartOperator(Operator::Yank));
+        assert_eq!(game.current_level.layout[0], "ab..");
+        game.handle_command(VimCommand::PutAfter);
+        assert_eq!(game.current_level.layout[1], "ab..");
+        assert_eq!(game.player_pos, Position { x: 0, y: 1 });
+        assert_eq!(game.status, GameStatus::Playing);
+
+        // Charwise puts overwrite after the cursor and stop at the row's end
+        game.handle_command(VimCommand::StartOperator(Operator::Yank));
+        game.handle_command(VimCommand::InsertMode);
+        game.handle_command(VimCommand::MoveWordForward);
+        assert!(!game.register.as_ref().unwrap().linewise);
+        game.player_pos.x = 2;
+        game.handle_command(VimCommand::PutAfter);
+        assert_eq!(game.current_level.layout[1], "ab.a");
+        assert_eq!(game.player_pos, Position { x: 3, y: 1 });
+    }
+
+    #[test]
+    fn test_change_and_insert() {
+        let mut game = GameState::new(one_line_level("foo ~~~ bar"));
+
+        // cw on a word changes to its end, like ce
+        game.handle_command(VimCommand::StartOperator(Operator::Change));
+        game.handle_command(VimCommand::MoveWordForward);
+        assert_eq!(game.current_level.layout[0], "... ~~~ bar");
+        assert_eq!(game.input_mode, InputMode::Insert(Position { x: 0, y: 0 }));
+        game.handle_command(VimCommand::InsertChar('x'));
+        assert_eq!(game.player_pos.x, 1);
+        game.handle_command(VimCommand::Escape);
+        assert_eq!(game.input_mode, InputMode::Normal);
+        assert_eq!(game.player_pos.x, 0);
+        assert_eq!(game.current_level.layout[0], "x.. ~~~ bar");
+
+        // The cursor hovers over water while typing a bridge
+        game.player_pos.x = 3;
+        game.handle_command(VimCommand::Append);
+        for c in "===".chars() {
+            game.handle_command(VimCommand::InsertChar(c));
+        }
+        assert_eq!(game.status, GameStatus::Playing);
+        game.handle_command(VimCommand::Escape);
+        assert_eq!(game.current_level.layout[0], "x.. === bar");
+        assert_eq!(game.player_pos.x, 6);
+        assert_eq!(game.status, GameStatus::Playing);
+    }
+
+    #[test]
+    fn test_text_goals() {
+        let mut level = one_line_level("ab~.");
+        level.goal = Goal::Text {
+            layout: vec!["a.~.".to_string(), "....".to_string()],
+        };
+        let mut game = GameState::new(level.clone());
+        game.handle_command(VimCommand::MoveRight);
+        assert_eq!(game.status, GameStatus::Playing);
+        game.handle_command(VimCommand::DeleteChar);
+        assert_eq!(game.status, GameStatus::LevelComplete);
+
+        // Reaching the target isn't enough until the text matches
+        level.target_pos = Position { x: 3, y: 0 };
+        level.goal = Goal::TextThenReach {
+            layout: vec!["ab=.".to_string(), "....".to_string()],
+        };
+        let mut game = GameState::new(level);
+        game.handle_command(VimCommand::FindNextChar('.'));
+        assert_eq!(game.player_pos.x, 3);
+        assert_eq!(game.status, GameStatus::Playing);
+        game.handle_command(VimCommand::MoveLeft);
+        assert_eq!(game.status, GameStatus::GameOver);
+
+        let mut game = GameState::new(game.current_level.clone());
+        game.handle_command(VimCommand::MoveRight);
+        game.handle_command(VimCommand::Append);
+        game.handle_command(VimCommand::InsertChar('='));
+        game.handle_command(VimCommand::Escape);
+        assert_eq!(game.status, GameStatus::Playing);
+        game.handle_command(VimCommand::MoveRight);
+        assert_eq!(game.status, GameStatus::LevelComplete);
+    }
+
+    #[test]
+    fn test_recorded_run_replays() {
+        let mut level = one_line_level("ab ce ef!");
+        level.target_pos = Position { x: 8, y: 0 };
+        let mut game = GameState::new(level.clone());
+
+        game.update(0.5);
+        game.handle_command(VimCommand::StartOperator(Operator::Delete));
+        game.handle_command(VimCommand::MoveWordForward);
+        game.update(0.25);
+        game.handle_command(VimCommand::Counted(2, Box::new(VimCommand::StartFindNext)));
+        game.handle_char_input('e');
+        game.handle_command(VimCommand::Undo);
+        game.handle_command(VimCommand::StartFindNext);
+        game.handle_char_input('!');
+        assert_eq!(game.status, GameStatus::LevelComplete);
+
+        let replay = game.finished_replay.clone().expect("a win records the run");
+        assert_eq!(replay.level_hash, level.fingerprint());
+        assert_eq!(replay.keystrokes, game.keystrokes);
+        assert_eq!(
+            replay.steps.iter().map(|step| step.command.to_keys()).collect::<Vec<_>>(),
+            vec!["dw", "2fe", "u", "f!"]
+        );
+        assert_eq!(replay.steps[0].time, 0.5);
+        assert_eq!(replay.steps[1].time, 0.75);
+
+        // Played back at the recorded pace, the run ends the same way
+        let mut replayed = GameState::new(level);
+        replayed.start_auto_play(replay.steps);
+        replayed.update(0.6);
+        assert_eq!(replayed.current_level.layout[0], "...ce ef!");
+        assert_eq!(replayed.player_pos.x, 0);
+        replayed.update(0.2);
+        assert_eq!(replayed.status, GameStatus::LevelComplete);
+        assert_eq!(replayed.current_level.layout, game.current_level.layout);
+        assert_eq!(replayed.keystrokes, game.keystrokes);
+        assert!(replayed.finished_replay.is_none());
+    }
+
+    #[test]
+    fn test_replay_steps_ignore_terrain_cost() {
+        let mut level = one_line_level("aXXb");
+        level.target_pos = Position { x: 3, y: 0 };
+        let mut game = GameState::new(level.clone());
+
+        game.update(0.5);
+        game.handle_command(VimCommand::MoveRight);
+        game.update(0.5);
+        game.handle_command(VimCommand::MoveRight);
+        game.update(0.5);
+        game.handle_command(VimCommand::MoveRight);
+        assert_eq!(game.status, GameStatus::LevelComplete);
+
+        // Mud costs score time, but the replay keeps the pace it was played at
+        let replay = game.finished_replay.clone().unwrap();
+        assert!(replay.time > 10.0);
+        let times: Vec<f32> = replay.steps.iter().map(|step| step.time).collect();
+        assert_eq!(times, vec![0.5, 1.0, 1.5]);
+
+        let mut replayed = GameState::new(level);
+        replayed.start_auto_play(replay.steps);
+        replayed.update(1.6);
+        assert_eq!(replayed.status, GameStatus::LevelComplete);
+    }
+
+    /// A level taller than the screen: 12 rows, with a wall at (0, 5).
+    fn tall_level() -> Level {
+        let mut level = create_test_level();
+        level.layout = vec!["...".to_string(); 12];
+        level.layout[5] = "#..".to_string();
+        level.target_pos = Position { x: 2, y: 11 };
+        level.allowed_keys = ["j", "k", "H", "M", "L", "<C-e>", "<C-d>", "<C-b>"]
+            .iter()
+            .map(|k| k.to_string())
+            .collect();
+        level
+    }
+
+    #[test]
+    fn test_screen_motions_use_the_visible_rows() {
+        let mut game = GameState::new(tall_level());
+        game.resize_viewport(4, 3);
+        game.player_pos.x = 1;
+        game.handle_command(VimCommand::Counted(6, Box::new(VimCommand::MoveDown)));
+        assert_eq!(game.player_pos.y, 6);
+        assert_eq!((game.viewport.top, game.viewport.bottom()), (3, 6));
+
+        game.handle_command(VimCommand::MoveScreenTop);
+        assert_eq!(game.player_pos.y, 3);
+        game.handle_command(VimCommand::MoveScreenBottom);
+        assert_eq!(game.player_pos.y, 6);
+        game.handle_command(VimCommand::Counted(2, Box::new(VimCommand::MoveScreenTop)));
+        assert_eq!(game.player_pos.y, 4);
+        // The middle row (5) is a wall in column 0, so M lands next to it
+        game.player_pos.x = 0;
+        game.handle_command(VimCommand::MoveScreenMiddle);
+        assert_eq!(game.player_pos.y, 4);
+        game.player_pos.y = 6;
+        game.handle_command(VimCommand::Counted(3, Box::new(VimCommand::MoveScreenMiddle)));
+        assert_eq!(game.player_pos.y, 4, "the count is ignored");
+        assert_eq!(game.viewport.top, 3, "H/M/L never scroll");
+    }
+
+    #[test]
+    fn test_scrolling() {
+        let mut game = GameState::new(tall_level());
+        game.resize_viewport(4, 3);
+        game.player_pos.x = 1;
+
+        // Ctrl-e drags the cursor along only once it would leave the screen
+        game.handle_command(VimCommand::ScrollLineDown);
+        assert_eq!((game.viewport.top, game.player_pos.y), (1, 1));
+
+        // Ctrl-d moves view and cursor by half a screen, or by its count
+        game.handle_command(VimCommand::ScrollHalfPageDown);
+        assert_eq!((game.viewport.top, game.player_pos.y), (3, 3));
+        game.handle_command(VimCommand::Counted(
+            5,
+            Box::new(VimCommand::ScrollHalfPageDown),
+        ));
+        assert_eq!((game.viewport.top, game.player_pos.y), (8, 8));
+
+        // zt/zz/zb scroll around the cursor without moving it, on any level
+        game.handle_command(VimCommand::Counted(3, Box::new(VimCommand::MoveUp)));
+        assert_eq!((game.viewport.top, game.player_pos.y), (5, 5));
+        game.handle_command(VimCommand::ScrollCursorBottom);
+        assert_eq!(game.viewport.top, 2);
+        game.handle_command(VimCommand::ScrollCursorCenter);
+        assert_eq!(game.viewport.top, 3);
+        assert!(game.blocked_command.is_none());
+        assert_eq!(game.player_pos.y, 5);
+
+        // Ctrl-b keeps two lines of context and pulls the cursor back on screen
+        game.handle_command(VimCommand::ScrollPageUp);
+        assert_eq!((game.viewport.top, game.player_pos.y), (1, 4));
+
+        // Ctrl-f isn't allowed here
+        game.handle_command(VimCommand::ScrollPageDown);
+        assert_eq!(game.blocked_command, Some(VimCommand::ScrollPageDown));
+        assert_eq!(game.viewport.top, 1);
+    }
+
+    #[test]
+    fn test_line_jumps() {
+        let mut level = create_test_level();
+        level.layout = ["###", "...", "...", ".~.", "...", "###"]
+            .iter()
+            .map(|row| row.to_string())
+            .collect();
+        level.start_pos = Position { x: 0, y: 1 };
+        level.target_pos = Position { x: 2, y: 4 };
+        level.allowed_keys = vec!["gg".to_string(), "G".to_string(), ":".to_string()];
+        let mut game = GameState::new(level);
+
+        // gg and G stop at the first and last open rows
+        game.handle_command(VimCommand::GotoLastLine);
+        assert_eq!(game.player_pos.y, 4);
+        game.handle_command(VimCommand::GotoFirstLine);
+        assert_eq!(game.player_pos.y, 1);
+
+        // {N}G and :{N} go to line N, counted from 1, but never into a wall
+        game.handle_command(VimCommand::Counted(3, Box::new(VimCommand::GotoLastLine)));
+        assert_eq!(game.player_pos.y, 2);
+        game.handle_command(VimCommand::GotoLine(1));
+        assert_eq!(game.player_pos.y, 2);
+        game.handle_command(VimCommand::StartCommandLine);
+        game.handle_char_input('5');
+        game.handle_special_key(macroquad::input::KeyCode::Enter);
+        assert_eq!(game.player_pos.y, 4);
+        assert_eq!(game.keystrokes, 1 + 2 + 2 + 3 + 3);
+
+        // Jumping onto water is as deadly as walking into it
+        game.player_pos.x = 1;
+        game.handle_command(VimCommand::GotoLine(4));
+        assert_eq!(game.status, GameStatus::GameOver);
+    }
+
+    fn type_ex(game: &mut GameState, line: &str) {
+        game.handle_command(VimCommand::StartCommandLine);
+        for c in line.chars() {
+            game.handle_char_input(c);
+        }
+        game.handle_special_key(macroquad::input::KeyCode::Enter);
+    }
+
+    fn message(game: &GameState) -> String {
+        match &game.message {
+            Some(Message::Info(text)) | Some(Message::Error(text)) => text.clone(),
+            None => String::new(),
+        }
+    }
+
+    #[test]
+    fn test_ex_commands() {
+        let mut game = GameState::new(create_test_level());
+
+        // Any level may use the command line, though not its jumps
+        type_ex(&mut game, "q");
+        assert_eq!(game.ex_request.take(), Some(ExCommand::Quit));
+        assert_eq!(game.keystrokes, 0);
+        type_ex(&mut game, "3");
+        assert_eq!(game.blocked_command, Some(VimCommand::GotoLine(3)));
+        assert_eq!(game.player_pos.y, 0);
+
+        type_ex(&mut game, "set list");
+        assert_eq!(game.message, Some(Message::Error("E518: Unknown option: list".to_string())));
+        assert_eq!(game.input_mode, InputMode::Normal);
+        game.handle_command(VimCommand::MoveRight);
+        assert_eq!(game.message, None);
+
+        // :help explains keys, and ex commands with a `:` in front
+        type_ex(&mut game, "help l");
+        assert_eq!(message(&game), "l (Move Right)");
+        type_ex(&mut game, "help w");
+        assert!(message(&game).ends_with("not allowed in this level"));
+        type_ex(&mut game, "h :lev");
+        assert!(message(&game).starts_with(":level {N}"));
+        type_ex(&mut game, "help nothing");
+        assert_eq!(message(&game), "E149: Sorry, no help for nothing");
+        type_ex(&mut game, "help");
+        assert!(game.show_help);
+    }
+
+    #[test]
+    fn test_ex_completion() {
+        let mut game = GameState::new(create_test_level());
+        game.handle_command(VimCommand::StartCommandLine);
+        game.handle_char_input('s');
+        game.handle_special_key(macroquad::input::KeyCode::Tab);
+        assert_eq!(
+            game.command_line(),
+            Some((&VimCommand::StartCommandLine, "set"))
+        );
+        for c in " nonu".chars() {
+            game.handle_char_input(c);
+        }
+        game.handle_special_key(macroquad::input::KeyCode::Tab);
+        assert_eq!(message(&game), "");
+        assert_eq!(
+            game.command_line(),
+            Some((&VimCommand::StartCommandLine, "set nonumber"))
+        );
+        game.set_command_line("set no".to_string());
+        game.handle_special_key(macroquad::input::KeyCode::Tab);
+        assert_eq!(message(&game), "nonumber  norelativenumber  noignorecase  nosmartcase");
+        game.set_command_line("set nu".to_string());
+        game.handle_special_key(macroquad::input::KeyCode::Enter);
+        assert_eq!(
+            game.ex_request,
+            Some(ExCommand::Set(crate::ex::SetOption::Number(true)))
+        );
+        assert_eq!(game.command_line(), None);
+    }
+
+    #[test]
+    fn test_incremental_search_and_highlights() {
+        let mut game = GameState::new(one_line_level("ab.ab.#ab~ab"));
+        let starts = |game: &GameState| -> Vec<usize> {
+            game.search_highlights().iter().map(|(pos, _)| pos.x).collect()
+        };
+
+        // While typing, the landing cell and every match are shown
+        game.handle_command(VimCommand::Counted(
+            2,
+            Box::new(VimCommand::StartSearchForward),
+        ));
+        game.handle_char_input('a');
+        assert_eq!(game.search_preview(), Some(Position { x: 7, y: 0 }));
+        game.handle_char_input('b');
+        assert_eq!(starts(&game), vec![0, 3, 7, 10]);
+        assert_eq!(game.search_highlights()[0].1, 2);
+        assert_eq!(game.player_pos.x, 0, "the preview doesn't move the cursor");
+        game.handle_special_key(macroquad::input::KeyCode::Enter);
+        assert_eq!(game.player_pos.x, 7);
+        assert_eq!(game.search_preview(), None);
+
+        // The last search stays highlighted until :noh, and n brings it back
+        assert_eq!(starts(&game), vec![0, 3, 7, 10]);
+        type_ex(&mut game, "noh");
+        assert!(game.search_highlights().is_empty());
+        game.handle_command(VimCommand::NextMatch);
+        assert_eq!(game.player_pos.x, 10);
+        assert_eq!(starts(&game).len(), 4);
+
+        game.handle_command(VimCommand::StartSearchBackward);
+        game.handle_char_input('b');
+        assert_eq!(game.search_preview(), Some(Position { x: 8, y: 0 }));
+        game.handle_char_input('z');
+        assert_eq!(game.search_preview(), None);
+        assert!(game.search_highlights().is_empty());
+    }
+
+    #[test]
+    fn test_pattern_search() {
+        let mut game = GameState::new(one_line_level("Ab.cab.ab"));
+        game.handle_command(VimCommand::SearchForward("\\<ab".to_string()));
+        assert_eq!(game.player_pos.x, 7, "skips the ab inside cab, and the uppercase Ab");
+        game.handle_command(VimCommand::SearchBackward("^.b".to_string()));
+        assert_eq!(game.player_pos.x, 0);
+
+        game.case_options.ignore_case = true;
+        game.handle_command(VimCommand::SearchForward("ab$".to_string()));
+        assert_eq!(game.player_pos.x, 7);
+        game.handle_command(VimCommand::NextMatch);
+        assert_eq!(game.player_pos.x, 7, "the only match at the end of the line");
+        game.handle_command(VimCommand::SearchBackward("\\<ab".to_string()));
+        assert_eq!(game.player_pos.x, 0);
+
+        // `$` alone lands on the last cell of the line
+        game.handle_command(VimCommand::SearchForward("$".to_string()));
+        assert_eq!(game.player_pos.x, 8);
+    }
+
+    #[test]
+    fn test_empty_search_repeats_last_pattern() {
+        let mut game = GameState::new(one_line_level("..ab..ab.."));
+        game.handle_command(VimCommand::StartSearchForward);
+        game.handle_special_key(macroquad::input::KeyCode::Enter);
+        assert_eq!(game.player_pos.x, 0, "no previous pattern to repeat");
+        assert!(matches!(game.message, Some(Message::Error(_))));
+
+        game.handle_command(VimCommand::SearchForward("ab".to_string()));
+        assert_eq!(game.player_pos.x, 2);
+        game.handle_command(VimCommand::StartSearchForward);
+        game.handle_special_key(macroquad::input::KeyCode::Enter);
+        assert_eq!(game.player_pos.x, 6);
+        game.handle_command(VimCommand::StartSearchBackward);
+        game.handle_special_key(macroquad::input::KeyCode::Enter);
+        assert_eq!(game.player_pos.x, 2);
+    }
+
+    #[test]
+    fn test_marks() {
+        let mut level = create_test_level();
+        level.layout = vec!["#..ab.".to_string(), "......".to_string(), "#.....".to_string()];
+        level.start_pos = Position { x: 4, y: 0 };
+        level.allowed_keys = vec![];
+        let mut game = GameState::new(level);
+        let at = |game: &GameState| (game.player_pos.x, game.player_pos.y);
+
+        game.handle_command(VimCommand::StartSetMark);
+        game.handle_char_input('a');
+        assert_eq!(game.keystrokes, 2);
+        game.handle_command(VimCommand::GotoLastLine);
+        game.handle_command(VimCommand::MoveLeft);
+        assert_eq!(at(&game), (3, 2));
+
+        // `a is the mark itself, 'a the first non-blank of its line
+        game.handle_command(VimCommand::GotoMark('a'));
+        assert_eq!(at(&game), (4, 0));
+        game.handle_command(VimCommand::MoveDown);
+        game.handle_command(VimCommand::GotoMarkLine('a'));
+        assert_eq!(at(&game), (3, 0));
+
+        game.handle_command(VimCommand::GotoMark('b'));
+        assert_eq!(message(&game), "E20: Mark not set");
+        game.handle_command(VimCommand::GotoMark('1'));
+        assert_eq!(message(&game), "E78: Unknown mark");
+        assert_eq!(at(&game), (3, 0));
+
+        // d'a deletes linewise from the cursor's line to the mark's
+        game.handle_command(VimCommand::GotoLastLine);
+        game.handle_command(VimCommand::Operate(
+            Operator::Delete,
+            Box::new(VimCommand::GotoMarkLine('a')),
+        ));
+        assert_eq!(game.current_level.layout[0], "#.....");
+        assert_eq!(game.current_level.layout[2], "#.....");
+    }
+
+    #[test]
+    fn test_jumplist() {
+        let mut level = one_line_level("ab.ab.ab.ab");
+        level.layout.push("...........".to_string());
+        let mut game = GameState::new(level);
+        let search = VimCommand::SearchForward("ab".to_string());
+
+        game.handle_command(search.clone());
+        game.handle_command(VimCommand::NextMatch);
+        game.handle_command(VimCommand::GotoLastLine);
+        // Not a jump: left off the jumplist
+        game.handle_command(VimCommand::MoveWordForward);
+        assert_eq!((game.player_pos.x, game.player_pos.y), (6, 2));
+
+        game.handle_command(VimCommand::JumpOlder);
+        assert_eq!((game.player_pos.x, game.player_pos.y), (6, 0));
+        game.handle_command(VimCommand::Counted(2, Box::new(VimCommand::JumpOlder)));
+        assert_eq!(game.player_pos.x, 0);
+        game.handle_command(VimCommand::JumpOlder);
+        assert_eq!(game.player_pos.x, 0, "nothing older");
+        game.handle_command(VimCommand::Counted(3, Box::new(VimCommand::JumpNewer)));
+        assert_eq!((game.player_pos.x, game.player_pos.y), (6, 2));
+
+        // The jumplist survives undo, as in Vim
+        game.handle_command(VimCommand::Undo);
+        game.handle_command(VimCommand::JumpOlder);
+        assert_eq!((game.player_pos.x, game.player_pos.y), (6, 0));
+    }
+
+    #[test]
+    fn test_match_pair() {
+        let mut level = create_test_level();
+        level.layout = ["S if (a[1]) {", "  f(x);", "} E"].map(String::from).to_vec();
+        level.target_pos = Position { x: 0, y: 1 };
+        level.allowed_keys = vec![];
+        let mut game = GameState::new(level);
+        let at = |game: &GameState| (game.player_pos.x, game.player_pos.y);
+
+        // From the first bracket on the row, over the nested [ ]
+        game.handle_command(VimCommand::MatchPair);
+        assert_eq!(at(&game), (10, 0));
+        game.handle_command(VimCommand::MatchPair);
+        assert_eq!(at(&game), (5, 0));
+        // Across rows, skipping the ( ) in between
+        game.handle_command(VimCommand::MoveRight);
+        game.handle_command(VimCommand::Counted(6, Box::new(VimCommand::MoveRight)));
+        game.handle_command(VimCommand::MatchPair);
+        assert_eq!(at(&game), (0, 2));
+        // No bracket from here to the end of the row
+        game.handle_command(VimCommand::Counted(2, Box::new(VimCommand::MoveRight)));
+        game.handle_command(VimCommand::MatchPair);
+        assert_eq!(at(&game), (2, 2));
+
+        // d% deletes both brackets and what is between
+        game.player_pos = Position { x: 3, y: 1 };
+        game.handle_command(VimCommand::Operate(
+            Operator::Delete,
+            Box::new(VimCommand::MatchPair),
+        ));
+        assert_eq!(game.current_level.layout[1], "  f...;");
+
+        // {count}% goes to that percentage of the lines
+        game.handle_command(VimCommand::Counted(50, Box::new(VimCommand::MatchPair)));
+        assert_eq!(at(&game), (3, 1));
+    }
+
+    #[test]
+    fn test_line_motions() {
+        let mut level = create_test_level();
+        level.layout = ["  ab cd  ", "~~ef gh~~", "  ..  ", "#ij"]
+            .map(String::from)
+            .to_vec();
+        level.start_pos = Position { x: 5, y: 0 };
+        level.target_pos = Position { x: 2, y: 3 };
+        level.allowed_keys = vec![];
+        let mut game = GameState::new(level);
+        let at = |game: &GameState| (game.player_pos.x, game.player_pos.y);
+
+        game.handle_command(VimCommand::MoveFirstNonBlank);
+        assert_eq!(at(&game), (2, 0));
+        game.handle_command(VimCommand::MoveLastNonBlank);
+        assert_eq!(at(&game), (6, 0));
+        game.handle_command(VimCommand::MoveNextLineStart);
+        assert_eq!(at(&game), (2, 1));
+        game.handle_command(VimCommand::MovePrevLineStart);
+        assert_eq!(at(&game), (2, 0));
+        game.handle_command(VimCommand::Counted(2, Box::new(VimCommand::MoveLineFirstNonBlank)));
+        assert_eq!(at(&game), (2, 1));
+        game.handle_command(VimCommand::MoveLastNonBlank);
+        assert_eq!(at(&game), (6, 1));
+        // An all-blank row: ^ stops at its end, g_ at its start
+        game.handle_command(VimCommand::MoveNextLineStart);
+        assert_eq!(at(&game), (5, 2));
+        game.handle_command(VimCommand::MoveLastNonBlank);
+        assert_eq!(at(&game), (0, 2));
+        // Past the last row: no move
+        game.handle_command(VimCommand::Counted(2, Box::new(VimCommand::MoveNextLineStart)));
+        assert_eq!(at(&game), (0, 2));
+        game.handle_command(VimCommand::Counted(2, Box::new(VimCommand::MovePrevLineStart)));
+        assert_eq!(at(&game), (2, 0));
+        assert!(matches!(game.status, GameStatus::Playing));
+
+        // 0 doesn't skip the water ^ steps over
+        game.handle_command(VimCommand::MoveNextLineStart);
+        game.handle_command(VimCommand::MoveLineStart);
+        assert_eq!(at(&game), (0, 1));
+        assert!(matches!(game.status, GameStatus::GameOver));
+    }
+
+    #[test]
+    fn test_search_word() {
+        let mut level = create_test_level();
+        level.layout = ["S beacon. beacons ~~~", "~~~~~beacon~x~beacon~"]
+            .map(String::from)
+            .to_vec();
+        level.start_pos = Position { x: 4, y: 0 };
+        level.target_pos = Position { x: 20, y: 1 };
+        level.allowed_keys = vec![];
+        let mut game = GameState::new(level);
+        let at = |game: &GameState| (game.player_pos.x, game.player_pos.y);
+
+        // From inside the word; "beacons" isn't a whole-word match
+        game.handle_command(VimCommand::SearchWordForward);
+        assert_eq!(at(&game), (5, 1));
+        assert_eq!(game.last_search_query.as_deref(), Some("\\<beacon\\>"));
+        game.handle_command(VimCommand::Counted(2, Box::new(VimCommand::SearchWordForward)));
+        assert_eq!(at(&game), (2, 0));
+        game.handle_command(VimCommand::SearchWordBackward);
+        assert_eq!(at(&game), (14, 1));
+        // n keeps going the way # went
+        game.handle_command(VimCommand::NextMatch);
+        assert_eq!(at(&game), (5, 1));
+        game.handle_command(VimCommand::PrevMatch);
+        assert_eq!(at(&game), (14, 1));
+
+        // g* also finds it inside longer words
+        game.handle_command(VimCommand::SearchTextBackward);
+        assert_eq!(at(&game), (5, 1));
+        game.handle_command(VimCommand::Counted(3, Box::new(VimCommand::SearchTextForward)));
+        assert_eq!(at(&game), (10, 0));
+        // Before a keyword: the keyword after the cursor
+        game.player_pos = Position { x: 8, y: 0 };
+        game.handle_command(VimCommand::SearchWordForward);
+        assert_eq!(at(&game), (10, 0), "the only beacons");
+
+        assert_eq!(game.word_search.as_deref(), Some("\\<beacons\\>"));
+
+        game.player_pos = Position { x: 18, y: 0 };
+        game.handle_command(VimCommand::SearchWordForward);
+        assert_eq!(at(&game), (18, 0));
+        assert_eq!(
+            game.message,
+            Some(Message::Error("E348: No string under cursor".to_string()))
+        );
+
+        // 'smartcase' doesn't make a capital in the word match case
+        game.case_options = CaseOptions {
+            ignore_case: true,
+            smart_case: true,
+        };
+        game.current_level.layout[0] = "S Beacon. beacons ~~~".to_string();
+        game.player_pos = Position { x: 2, y: 0 };
+        game.handle_command(VimCommand::SearchWordForward);
+        assert_eq!(at(&game), (5, 1));
+        game.handle_command(VimCommand::NextMatch);
+        assert_eq!(at(&game), (14, 1));
+    }
+
+    #[test]
+    fn test_sentences() {
+        let mut level = create_test_level();
+        level.layout = ["Hi there. The cat (sat.) Yes", "no? Next", "~~~~", "  New para.E"]
+            .map(String::from)
+            .to_vec();
+        level.start_pos = Position { x: 0, y: 0 };
+        level.target_pos = Position { x: 11, y: 3 };
+        level.allowed_keys = vec![];
+        let mut game = GameState::new(level);
+        let at = |game: &GameState| (game.player_pos.x, game.player_pos.y);
+
+        game.handle_command(VimCommand::MoveSentenceForward);
+        assert_eq!(at(&game), (10, 0));
+        // Closing brackets after the period still end the sentence
+        game.handle_command(VimCommand::MoveSentenceForward);
+        assert_eq!(at(&game), (25, 0));
+        // A row end without punctuation doesn't
+        game.handle_command(VimCommand::MoveSentenceForward);
+        assert_eq!(at(&game), (4, 1));
+        game.handle_command(VimCommand::Counted(2, Box::new(VimCommand::MoveSentenceBack)));
+        assert_eq!(at(&game), (10, 0));
+        // From inside a sentence, ( goes to its start
+        game.player_pos = Position { x: 14, y: 0 };
+        game.handle_command(VimCommand::MoveSentenceBack);
+        assert_eq!(at(&game), (10, 0));
+        game.handle_command(VimCommand::MoveSentenceBack);
+        game.handle_command(VimCommand::MoveSentenceBack);
+        assert_eq!(at(&game), (0, 0));
+
+        // The blank row is a boundary, and here it is water
+        game.player_pos = Position { x: 4, y: 1 };
+        game.handle_command(VimCommand::MoveSentenceForward);
+        assert_eq!(at(&game), (0, 2));
+        assert!(matches!(game.status, GameStatus::GameOver));
+    }
+
+    #[test]
+    fn test_sections() {
+        let mut level = create_test_level();
+        level.layout = ["int x;", "{", "  f();", "}", "void g()", "{", "}"]
+            .map(String::from)
+            .to_vec();
+        level.start_pos = Position { x: 3, y: 2 };
+        level.target_pos = Position { x: 5, y: 0 };
+        level.allowed_keys = vec![];
+        let mut game = GameState::new(level);
+        let at = |game: &GameState| (game.player_pos.x, game.player_pos.y);
+
+        game.handle_command(VimCommand::MoveSectionForward);
+        assert_eq!(at(&game), (0, 5));
+        // No more sections: the last row
+        game.handle_command(VimCommand::MoveSectionForward);
+        assert_eq!(at(&game), (0, 6));
+        game.handle_command(VimCommand::Counted(2, Box::new(VimCommand::MoveSectionBack)));
+        assert_eq!(at(&game), (0, 1));
+        game.handle_command(VimCommand::MoveSectionBack);
+        assert_eq!(at(&game), (0, 0));
+        game.handle_command(VimCommand::MoveSectionEndForward);
+        assert_eq!(at(&game), (0, 3));
+        game.handle_command(VimCommand::MoveSectionEndForward);
+        assert_eq!(at(&game), (0, 6));
+        game.handle_command(VimCommand::MoveSectionEndBack);
+        assert_eq!(at(&game), (0, 3));
+        assert_eq!(game.jumps.len(), 6, "section motions are jumps");
+    }
+
+    #[test]
+    fn test_sections_inside_walls() {
+        let mut level = create_test_level();
+        level.layout = ["#######", "#int; #", "#{    #", "# f();#", "#}    #", "#######"]
+            .map(String::from)
+            .to_vec();
+        level.start_pos = Position { x: 2, y: 1 };
+        level.target_pos = Position { x: 5, y: 4 };
+        level.allowed_keys = vec![];
+        let mut game = GameState::new(level);
+        let at = |game: &GameState| (game.player_pos.x, game.player_pos.y);
+
+        game.handle_command(VimCommand::MoveSectionForward);
+        assert_eq!(at(&game), (1, 2));
+        game.handle_command(VimCommand::MoveSectionEndForward);
+        assert_eq!(at(&game), (1, 4));
+        // No more sections: the last row that isn't all wall
+        game.handle_command(VimCommand::MoveSectionForward);
+        assert_eq!(at(&game), (1, 4));
+        game.handle_command(VimCommand::MoveSectionBack);
+        assert_eq!(at(&game), (1, 2));
+        game.handle_command(VimCommand::MoveSectionBack);
+        assert_eq!(at(&game), (1, 1));
+    }
+
+    #[test]
+    fn test_unmatched_brackets() {
+        let mut level = create_test_level();
+        level.layout = ["{ f(a, (b), {c}", "  g(d) }"].map(String::from).to_vec();
+        level.start_pos = Position { x: 11, y: 0 };
+        level.allowed_keys = vec![];
+        let mut game = GameState::new(level);
+        let at = |game: &GameState| (game.player_pos.x, game.player_pos.y);
+
+        // [( skips the (b) pair that is closed before the cursor
+        game.handle_command(VimCommand::PrevUnmatchedParen);
+        assert_eq!(at(&game), (3, 0));
+        game.handle_command(VimCommand::NextUnmatchedBrace);
+        assert_eq!(at(&game), (7, 1));
+        game.handle_command(VimCommand::PrevUnmatchedBrace);
+        assert_eq!(at(&game), (0, 0));
+        // No unmatched ( before the start: no move
+        game.handle_command(VimCommand::PrevUnmatchedParen);
+        assert_eq!(at(&game), (0, 0));
+
+        // With a count, the enclosing pair further out
+        game.player_pos = Position { x: 13, y: 0 };
+        game.handle_command(VimCommand::Counted(2, Box::new(VimCommand::PrevUnmatchedBrace)));
+        assert_eq!(at(&game), (0, 0));
+    }
 }
@@ -0,0 +1,349 @@
+//! Procedural levels: seeded rivers of words and water, kept only when the
+//! solver confirms that the trained commands are really needed.
+
+use crate::input::{InputHandler, VimCommand};
+use crate::level::{Goal, KeyPolicy, Level, Position};
+use crate::solver::{Solution, SolveError, Solver};
+use std::fmt;
+
+/// Layouts tried before giving up on a command set.
+const MAX_ATTEMPTS: u32 = 200;
+
+/// Always allowed alongside the trained commands, so a level can be walked.
+const FILLER_KEYS: [&str; 4] = ["h", "j", "k", "l"];
+
+/// Small deterministic generator (SplitMix64), so a seed always gives the
+/// same level on every platform.
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Self(seed)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// A number in `low..=high`.
+    pub fn range(&mut self, low: usize, high: usize) -> usize {
+        low + (self.next_u64() % (high - low + 1) as u64) as usize
+    }
+
+    pub fn pick<T: Copy>(&mut self, items: &[T]) -> T {
+        items[self.range(0, items.len() - 1)]
+    }
+
+    /// Puts `items` in a random order (Fisher-Yates).
+    pub fn shuffle<T>(&mut self, items: &mut [T]) {
+        for i in (1..items.len()).rev() {
+            items.swap(i, self.range(0, i));
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Difficulty {
+    Easy,
+    Normal,
+    Hard,
+}
+
+impl Difficulty {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "easy" => Some(Difficulty::Easy),
+            "normal" => Some(Difficulty::Normal),
+            "hard" => Some(Difficulty::Hard),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Difficulty::Easy => "easy",
+            Difficulty::Normal => "normal",
+            Difficulty::Hard => "hard",
+        }
+    }
+
+    /// (width, rows of islands, shortest gap, longest gap)
+    fn shape(self) -> (usize, usize, usize, usize) {
+        match self {
+            Difficulty::Easy => (16, 1, 1, 2),
+            Difficulty::Normal => (24, 2, 1, 3),
+            Difficulty::Hard => (32, 3, 2, 4),
+        }
+    }
+
+    /// Fewest keystrokes a level of this difficulty may be solved in.
+    fn min_par(self) -> u32 {
+        match self {
+            Difficulty::Easy => 2,
+            Difficulty::Normal => 3,
+            Difficulty::Hard => 4,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum GenerateError {
+    NoCommands,
+    UnknownCommand(String),
+    /// No layout tried needed every trained command.
+    GaveUp { attempts: u32 },
+}
+
+impl fmt::Display for GenerateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GenerateError::NoCommands => write!(f, "no commands to train"),
+            GenerateError::UnknownCommand(name) => write!(f, "unknown command '{}'", name),
+            GenerateError::GaveUp { attempts } => write!(
+                f,
+                "no layout out of {} needed every trained command",
+                attempts
+            ),
+        }
+    }
+}
+
+/// Generates a level training `commands` (names as in `allowed_keys`).
+/// The same arguments always give the same level.
+pub fn generate(
+    commands: &[String],
+    difficulty: Difficulty,
+    seed: u64,
+) -> Result<Level, GenerateError> {
+    if commands.is_empty() {
+        return Err(GenerateError::NoCommands);
+    }
+    let mut trained = Vec::new();
+    for name in commands {
+        match InputHandler::from_string(name) {
+            VimCommand::None => return Err(GenerateError::UnknownCommand(name.clone())),
+            command => trained.push(command),
+        }
+    }
+
+    let mut allowed_keys = commands.to_vec();
+    for key in FILLER_KEYS {
+        if !allowed_keys.iter().any(|k| k == key) {
+            allowed_keys.push(key.to_string());
+        }
+    }
+
+    let mut rng = Rng::new(seed);
+    for _ in 0..MAX_ATTEMPTS {
+        let mut level = Level {
+            id: format!("generated_{}_{}", difficulty.name(), seed),
+            name: format!("Generated #{}", seed),
+            description: format!("Cross the river with {}.", commands.join(", ")),
+            layout: river(&mut rng, &trained, difficulty),
+            start_pos: Position { x: 0, y: 0 },
+            target_pos: Position { x: 0, y: 0 },
+            goal: Goal::Reach,
+            allowed_keys: allowed_keys.clone(),
+            key_policy: KeyPolicy::Reject,
+            undo_penalty: 50,
+            trained_commands: commands.to_vec(),
+            tutorial_text: format!(
+                "A generated level. Walking won't get you across:\nuse {}.",
+                commands.join(", ")
+            ),
+            par_time: 0.0,
+            par_keystrokes: 0,
+        };
+        level.start_pos = find_glyph(&level.layout, 'S');
+        level.target_pos = find_glyph(&level.layout, 'E');
+        if !level.validate().is_empty() {
+            continue;
+        }
+        let Ok(solution) = Solver::new(level.clone()).solve() else {
+            continue;
+        };
+        if solution.keystrokes < difficulty.min_par()
+            || !needs_trained(&level, &solution, &trained)
+        {
+            continue;
+        }
+        level.par_keystrokes = solution.keystrokes;
+        level.par_time = 5.0 + 2.0 * solution.keystrokes as f32;
+        return Ok(level);
+    }
+    Err(GenerateError::GaveUp {
+        attempts: MAX_ATTEMPTS,
+    })
+}
+
+/// Rows of islands separated by water, walled in, with `S` at the top left
+/// and `E` at the bottom right, or mirrored so that backward motions get a
+/// turn. The islands' characters suit the commands: punctuation for WORD
+/// motions, a few repeated letters for `f`/`t`.
+fn river(rng: &mut Rng, trained: &[VimCommand], difficulty: Difficulty) -> Vec<String> {
+    let (width, rows, min_gap, max_gap) = difficulty.shape();
+    let trains = |commands: &[VimCommand]| trained.iter().any(|c| commands.contains(c));
+    let (alphabet, max_island): (&[char], usize) = if trains(&[
+        VimCommand::MoveBigWordForward,
+        VimCommand::MoveBigWordBack,
+        VimCommand::MoveBigWordEnd,
+        VimCommand::MoveBigWordEndBack,
+    ]) {
+        (&['a', 'b', 'c', '-', ':', '+', '!'], 6)
+    } else if trains(&[
+        VimCommand::StartFindNext,
+        VimCommand::StartFindPrev,
+        VimCommand::StartTillNext,
+        VimCommand::StartTillPrev,
+        VimCommand::RepeatFind,
+        VimCommand::RepeatFindReverse,
+    ]) {
+        (&['a', 'b', 'c'], 2)
+    } else {
+        (&['a', 'e', 'i', 'o', 'r', 's', 't', 'n'], 5)
+    };
+
+    let inner = width - 2;
+    let mut layout = vec!["#".repeat(width)];
+    for _ in 0..rows {
+        let mut row = String::new();
+        while row.len() < inner {
+            for _ in 0..rng.range(1, max_island) {
+                row.push(rng.pick(alphabet));
+            }
+            row.push_str(&"~".repeat(rng.range(min_gap, max_gap)));
+        }
+        row.truncate(inner);
+        layout.push(format!("#{}#", row));
+    }
+    layout.push("#".repeat(width));
+
+    layout[1].replace_range(1..2, "S");
+    layout[rows].replace_range(inner..inner + 1, "E");
+    if rng.range(0, 1) == 1 {
+        for row in &mut layout {
+            *row = row.chars().rev().collect();
+        }
+    }
+    layout
+}
+
+fn find_glyph(layout: &[String], glyph: char) -> Position {
+    layout
+        .iter()
+        .enumerate()
+        .find_map(|(y, row)| row.find(glyph).map(|x| Position { x, y }))
+        .unwrap_or(Position { x: 0, y: 0 })
+}
+
+/// True if the trained commands are needed: without them the level can't
+/// be done, or not as cheaply.
+fn needs_trained(level: &Level, solution: &Solution, trained: &[VimCommand]) -> bool {
+    match check_trained(level, solution, trained) {
+        TrainedCheck::Needed => true,
+        TrainedCheck::NotNeeded => false,
+        TrainedCheck::Solve(without) => still_needed(solution, Solver::new(*without).solve()),
+    }
+}
+
+/// How far `needs_trained` gets without solving the level again.
+pub(crate) enum TrainedCheck {
+    Needed,
+    NotNeeded,
+    /// Solve this copy of the level, which lacks the trained commands, and
+    /// pass the result to `still_needed`.
+    Solve(Box<Level>),
+}
+
+pub(crate) fn check_trained(
+    level: &Level,
+    solution: &Solution,
+    trained: &[VimCommand],
+) -> TrainedCheck {
+    if !solution.commands.iter().any(|c| trained.contains(&c.base())) {
+        return TrainedCheck::NotNeeded;
+    }
+    let mut without = level.clone();
+    without
+        .allowed_keys
+        .retain(|key| !trained.contains(&InputHandler::from_string(key)));
+    // An empty allow list would allow everything
+    if without.allowed_keys.is_empty() {
+        return TrainedCheck::Needed;
+    }
+    TrainedCheck::Solve(Box::new(without))
+}
+
+/// True if `other`, the solve without the trained commands, can't match
+/// `solution`.
+pub(crate) fn still_needed(solution: &Solution, other: Result<Solution, SolveError>) -> bool {
+    match other {
+        Ok(other) => other.keystrokes > solution.keystrokes,
+        Err(SolveError::Unsolvable) => true,
+        // Can't tell, so don't count on it
+        Err(SolveError::NodeLimit(_)) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(keys: &[&str]) -> Vec<String> {
+        keys.iter().map(|k| k.to_string()).collect()
+    }
+
+    #[test]
+    fn test_same_seed_same_level() {
+        let a = generate(&names(&["w"]), Difficulty::Easy, 7).unwrap();
+        let b = generate(&names(&["w"]), Difficulty::Easy, 7).unwrap();
+        assert_eq!(a.layout, b.layout);
+        assert_eq!(a.par_keystrokes, b.par_keystrokes);
+        let c = generate(&names(&["w"]), Difficulty::Easy, 8).unwrap();
+        assert_ne!(a.layout, c.layout);
+    }
+
+    #[test]
+    fn test_generated_levels_need_their_commands() {
+        for (keys, difficulty) in [
+            (&["w", "e"][..], Difficulty::Easy),
+            (&["f", ";"][..], Difficulty::Easy),
+            (&["W"][..], Difficulty::Normal),
+            (&["b"][..], Difficulty::Easy),
+        ] {
+            let trained = names(keys);
+            let level = generate(&trained, difficulty, 42).unwrap();
+            assert!(level.validate().is_empty());
+            assert_eq!(level.trained_commands, trained);
+
+            let solution = Solver::new(level.clone()).solve().unwrap();
+            assert_eq!(solution.keystrokes, level.par_keystrokes);
+
+            let mut walking = level.clone();
+            walking.allowed_keys = names(&FILLER_KEYS);
+            let walked = Solver::new(walking).solve();
+            assert!(
+                !walked.is_ok_and(|w| w.keystrokes <= solution.keystrokes),
+                "{:?} can be walked: {:?}",
+                keys,
+                level.layout
+            );
+        }
+    }
+
+    #[test]
+    fn test_generate_errors() {
+        assert_eq!(
+            generate(&[], Difficulty::Easy, 1).unwrap_err(),
+            GenerateError::NoCommands
+        );
+        assert_eq!(
+            generate(&names(&["w", "zq"]), Difficulty::Easy, 1).unwrap_err(),
+            GenerateError::UnknownCommand("zq".to_string())
+        );
+    }
+}
@@ -1,6 +1,54 @@
 use macroquad::input::KeyCode;
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, PartialEq, Eq, Clone, Hash)]
+/// Operators that act on the text a motion moves over.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash, Serialize, Deserialize)]
+pub enum Operator {
+    Delete, // d
+    Change, // c
+    Yank,   // y
+}
+
+impl Operator {
+    pub fn key(self) -> char {
+        match self {
+            Operator::Delete => 'd',
+            Operator::Change => 'c',
+            Operator::Yank => 'y',
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Operator::Delete => "Delete",
+            Operator::Change => "Change",
+            Operator::Yank => "Yank",
+        }
+    }
+}
+
+/// Text objects, only valid right after an operator (e.g. the `iw` in `yiw`).
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash, Serialize, Deserialize)]
+#[allow(clippy::enum_variant_names)] // Named after iw, aw, iW and aW
+pub enum TextObject {
+    InnerWord,    // iw
+    AWord,        // aw
+    InnerBigWord, // iW
+    ABigWord,     // aW
+}
+
+impl TextObject {
+    pub fn keys(self) -> &'static str {
+        match self {
+            TextObject::InnerWord => "iw",
+            TextObject::AWord => "aw",
+            TextObject::InnerBigWord => "iW",
+            TextObject::ABigWord => "aW",
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Hash, Serialize, Deserialize)]
 pub enum VimCommand {
     MoveLeft,
     MoveDown,
@@ -9,13 +57,46 @@ pub enum VimCommand {
     MoveWordForward,
     MoveWordBack,
     MoveWordEnd,
+    MoveWordEndBack,    // ge
+    MoveBigWordForward, // W
+    MoveBigWordBack,    // B
+    MoveBigWordEnd,     // E
+    MoveBigWordEndBack, // gE
     MoveLineStart,
     MoveLineEnd,
+    MoveFirstNonBlank,     // ^
+    MoveLastNonBlank,      // g_
+    MoveLineFirstNonBlank, // _
+    MoveNextLineStart,     // + and Enter
+    MovePrevLineStart,     // -
     MoveScreenTop,
     MoveScreenMiddle,
     MoveScreenBottom,
     MoveParagraphForward,
     MoveParagraphBack,
+    MoveSentenceForward,   // )
+    MoveSentenceBack,      // (
+    MoveSectionForward,    // ]]
+    MoveSectionBack,       // [[
+    MoveSectionEndForward, // ][
+    MoveSectionEndBack,    // []
+    MatchPair,          // %
+    PrevUnmatchedParen, // [(
+    PrevUnmatchedBrace, // [{
+    NextUnmatchedParen, // ])
+    NextUnmatchedBrace, // ]}
+    ScrollLineDown,     // Ctrl-e
+    ScrollLineUp,       // Ctrl-y
+    ScrollHalfPageDown, // Ctrl-d
+    ScrollHalfPageUp,   // Ctrl-u
+    ScrollPageDown,     // Ctrl-f
+    ScrollPageUp,       // Ctrl-b
+    ScrollCursorTop,    // zt
+    ScrollCursorCenter, // zz
+    ScrollCursorBottom, // zb
+    GotoFirstLine,      // gg
+    GotoLastLine,       // G
+    StartCommandLine,   // :
     InsertMode,
     Escape,
     None,
@@ -30,7 +111,23 @@ pub enum VimCommand {
     StartSearchBackward, // ?
     NextMatch,           // n
     PrevMatch,           // N
+    SearchWordForward,   // *
+    SearchWordBackward,  // #
+    SearchTextForward,   // g*
+    SearchTextBackward,  // g#
     DeleteChar,          // x
+    Undo,                // u
+    Redo,                // Ctrl-r
+    Append,              // a
+    PutAfter,            // p
+    PutBefore,           // P
+    StartSetMark,        // m
+    StartGotoMarkLine,   // '
+    StartGotoMark,       // `
+    JumpOlder,           // Ctrl-o
+    JumpNewer,           // Ctrl-i
+    StartOperator(Operator), // d, c, y
+    TextObject(TextObject),
     // Completed commands (generated by GameState)
     FindNextChar(char),
     FindPrevChar(char),
@@ -38,18 +135,182 @@ pub enum VimCommand {
     TillPrevChar(char),
     SearchForward(String),
     SearchBackward(String),
+    InsertChar(char), // A char typed in insert mode
+    SetMark(char),
+    GotoMarkLine(char), // 'a: the mark's line
+    GotoMark(char),     // `a: the mark itself
+    GotoLine(u32),    // :{N}<CR>
+    // An operator with its motion (e.g. dw). `dd` is the operator applied to itself.
+    Operate(Operator, Box<VimCommand>),
+    // A command prefixed with a count (e.g. 3w)
+    Counted(u32, Box<VimCommand>),
 }
 
+/// Counts larger than this are clamped, like Vim does for huge counts.
+const MAX_COUNT: u32 = 9999;
+
 pub struct InputHandler {
-    // State for multi-key commands could go here
+    // Digits typed so far for a count prefix (e.g. the "10" in "10l")
+    count: Option<u32>,
+    // First key of a two-key command (e.g. the 'g' in "ge", the '[' in "[(")
+    prefix: Option<char>,
 }
 
 impl InputHandler {
     pub fn new() -> Self {
-        Self {}
+        Self {
+            count: None,
+            prefix: None,
+        }
+    }
+
+    /// Keys typed so far for an unfinished command, as Vim's showcmd shows them.
+    pub fn pending_keys(&self) -> String {
+        let mut keys = self.count.map(|n| n.to_string()).unwrap_or_default();
+        if let Some(prefix) = self.prefix {
+            keys.push(prefix);
+        }
+        keys
     }
 
     pub fn map_key(&mut self, key: KeyCode, shift: bool) -> VimCommand {
+        // Shift/Ctrl arrive as keys too; they must not disturb pending state
+        if Self::is_modifier(key) {
+            return VimCommand::None;
+        }
+
+        if let Some(prefix) = self.prefix.take() {
+            let command = Self::map_prefixed_key(prefix, key, shift);
+            if command == VimCommand::None {
+                // Unknown sequence: Vim drops it along with the count
+                self.count = None;
+                return command;
+            }
+            return self.apply_count(command);
+        }
+
+        if !shift {
+            let prefix = match key {
+                KeyCode::G => Some('g'),
+                KeyCode::Z => Some('z'),
+                KeyCode::LeftBracket => Some('['),
+                KeyCode::RightBracket => Some(']'),
+                _ => None,
+            };
+            if prefix.is_some() {
+                self.prefix = prefix;
+                return VimCommand::None;
+            }
+        }
+
+        if !shift && let Some(digit) = Self::digit_value(key) {
+            // '0' only extends a count; on its own it is a motion
+            if digit != 0 || self.count.is_some() {
+                let count = self.count.unwrap_or(0) * 10 + digit;
+                self.count = Some(count.min(MAX_COUNT));
+                return VimCommand::None;
+            }
+        }
+
+        let command = self.map_single_key(key, shift);
+        match command {
+            VimCommand::Escape => {
+                self.count = None;
+                command
+            }
+            VimCommand::None => command,
+            _ => self.apply_count(command),
+        }
+    }
+
+    /// Maps a key pressed while Ctrl is held (e.g. Ctrl-r, Ctrl-d).
+    pub fn map_ctrl_key(&mut self, key: KeyCode) -> VimCommand {
+        if Self::is_modifier(key) {
+            return VimCommand::None;
+        }
+        self.prefix = None;
+        let command = match key {
+            KeyCode::R => VimCommand::Redo,
+            KeyCode::E => VimCommand::ScrollLineDown,
+            KeyCode::Y => VimCommand::ScrollLineUp,
+            KeyCode::D => VimCommand::ScrollHalfPageDown,
+            KeyCode::U => VimCommand::ScrollHalfPageUp,
+            KeyCode::F => VimCommand::ScrollPageDown,
+            KeyCode::B => VimCommand::ScrollPageUp,
+            KeyCode::O => VimCommand::JumpOlder,
+            KeyCode::I => VimCommand::JumpNewer,
+            _ => VimCommand::None,
+        };
+        if command == VimCommand::None {
+            self.count = None;
+            return command;
+        }
+        self.apply_count(command)
+    }
+
+    fn apply_count(&mut self, command: VimCommand) -> VimCommand {
+        match self.count.take() {
+            Some(count) => VimCommand::Counted(count, Box::new(command)),
+            None => command,
+        }
+    }
+
+    fn is_modifier(key: KeyCode) -> bool {
+        matches!(
+            key,
+            KeyCode::LeftShift
+                | KeyCode::RightShift
+                | KeyCode::LeftControl
+                | KeyCode::RightControl
+                | KeyCode::LeftAlt
+                | KeyCode::RightAlt
+                | KeyCode::LeftSuper
+                | KeyCode::RightSuper
+        )
+    }
+
+    /// Second key of a two-key command.
+    fn map_prefixed_key(prefix: char, key: KeyCode, shift: bool) -> VimCommand {
+        match (prefix, key, shift) {
+            ('g', KeyCode::E, false) => VimCommand::MoveWordEndBack,
+            ('g', KeyCode::E, true) => VimCommand::MoveBigWordEndBack,
+            ('g', KeyCode::G, false) => VimCommand::GotoFirstLine,
+            ('g', KeyCode::Minus, true) => VimCommand::MoveLastNonBlank, // _ is Shift+-
+            ('g', KeyCode::Key8, true) => VimCommand::SearchTextForward,
+            ('g', KeyCode::Key3, true) => VimCommand::SearchTextBackward,
+            ('z', KeyCode::T, false) => VimCommand::ScrollCursorTop,
+            ('z', KeyCode::Z, false) => VimCommand::ScrollCursorCenter,
+            ('z', KeyCode::B, false) => VimCommand::ScrollCursorBottom,
+            // ( is Shift+9, ) Shift+0, { Shift+[ and } Shift+]
+            ('[', KeyCode::Key9, true) => VimCommand::PrevUnmatchedParen,
+            ('[', KeyCode::LeftBracket, false) => VimCommand::MoveSectionBack,
+            ('[', KeyCode::RightBracket, false) => VimCommand::MoveSectionEndBack,
+            (']', KeyCode::RightBracket, false) => VimCommand::MoveSectionForward,
+            (']', KeyCode::LeftBracket, false) => VimCommand::MoveSectionEndForward,
+            ('[', KeyCode::LeftBracket, true) => VimCommand::PrevUnmatchedBrace,
+            (']', KeyCode::Key0, true) => VimCommand::NextUnmatchedParen,
+            (']', KeyCode::RightBracket, true) => VimCommand::NextUnmatchedBrace,
+            _ => VimCommand::None,
+        }
+    }
+
+    fn digit_value(key: KeyCode) -> Option<u32> {
+        match key {
+            KeyCode::Key0 => Some(0),
+            KeyCode::Key1 => Some(1),
+            KeyCode::Key2 => Some(2),
+            KeyCode::Key3 => Some(3),
+            KeyCode::Key4 => Some(4),
+            KeyCode::Key5 => Some(5),
+            KeyCode::Key6 => Some(6),
+            KeyCode::Key7 => Some(7),
+            KeyCode::Key8 => Some(8),
+            KeyCode::Key9 => Some(9),
+            _ => None,
+        }
+    }
+
+    fn map_single_key(&self, key: KeyCode, shift: bool) -> VimCommand {
         match key {
             KeyCode::H => {
                 if shift {
@@ -71,12 +332,32 @@ impl InputHandler {
                 if shift {
                     VimCommand::MoveScreenMiddle
                 } else {
-                    VimCommand::None
+                    VimCommand::StartSetMark
+                }
+            }
+            KeyCode::W => {
+                if shift {
+                    VimCommand::MoveBigWordForward
+                } else {
+                    VimCommand::MoveWordForward
+                }
+            }
+            KeyCode::B => {
+                if shift {
+                    VimCommand::MoveBigWordBack
+                } else {
+                    VimCommand::MoveWordBack
+                }
+            }
+            KeyCode::E => {
+                if shift {
+                    VimCommand::MoveBigWordEnd
+                } else {
+                    VimCommand::MoveWordEnd
                 }
             }
-            KeyCode::W => VimCommand::MoveWordForward,
-            KeyCode::B => VimCommand::MoveWordBack,
-            KeyCode::E => VimCommand::MoveWordEnd,
+            KeyCode::Key9 if shift => VimCommand::MoveSentenceBack, // ( is Shift+9
+            KeyCode::Key0 if shift => VimCommand::MoveSentenceForward, // ) is Shift+0
             KeyCode::Key0 => VimCommand::MoveLineStart,
             KeyCode::Key4 => {
                 if shift {
@@ -85,6 +366,19 @@ impl InputHandler {
                     VimCommand::None
                 }
             } // $ is Shift+4
+            KeyCode::Key5 if shift => VimCommand::MatchPair, // % is Shift+5
+            KeyCode::Key6 if shift => VimCommand::MoveFirstNonBlank, // ^ is Shift+6
+            KeyCode::Key8 if shift => VimCommand::SearchWordForward, // * is Shift+8
+            KeyCode::Key3 if shift => VimCommand::SearchWordBackward, // # is Shift+3
+            KeyCode::Minus => {
+                if shift {
+                    VimCommand::MoveLineFirstNonBlank
+                } else {
+                    VimCommand::MovePrevLineStart
+                }
+            } // _ is Shift+-
+            KeyCode::Equal if shift => VimCommand::MoveNextLineStart, // + is Shift+=
+            KeyCode::Enter | KeyCode::KpEnter => VimCommand::MoveNextLineStart,
             KeyCode::RightBracket => {
                 if shift {
                     VimCommand::MoveParagraphForward
@@ -99,6 +393,7 @@ impl InputHandler {
                     VimCommand::None
                 }
             } // { is Shift+[
+            KeyCode::G if shift => VimCommand::GotoLastLine,
             KeyCode::I => VimCommand::InsertMode,
             KeyCode::Escape => VimCommand::Escape,
             KeyCode::F => {
@@ -115,8 +410,18 @@ impl InputHandler {
                     VimCommand::StartTillNext
                 }
             }
-            KeyCode::Semicolon => VimCommand::RepeatFind,
+            KeyCode::Semicolon => {
+                if shift {
+                    VimCommand::StartCommandLine
+                } else {
+                    VimCommand::RepeatFind
+                }
+            } // : is Shift+;
             KeyCode::Comma => VimCommand::RepeatFindReverse,
+            KeyCode::Apostrophe if !shift => VimCommand::StartGotoMarkLine,
+            KeyCode::GraveAccent if !shift => VimCommand::StartGotoMark,
+            // Tab and Ctrl-i are the same key to Vim
+            KeyCode::Tab => VimCommand::JumpNewer,
             KeyCode::Slash => {
                 if shift {
                     VimCommand::StartSearchBackward
@@ -132,22 +437,30 @@ impl InputHandler {
                 }
             }
             KeyCode::X => VimCommand::DeleteChar,
-            _ => VimCommand::None,
-        }
-    }
-
-    // Helper to convert char to command if we process chars instead of KeyCodes
-    pub fn map_char(&mut self, c: char) -> VimCommand {
-        match c {
-            'h' => VimCommand::MoveLeft,
-            'j' => VimCommand::MoveDown,
-            'k' => VimCommand::MoveUp,
-            'l' => VimCommand::MoveRight,
-            'w' => VimCommand::MoveWordForward,
-            'b' => VimCommand::MoveWordBack,
-            'e' => VimCommand::MoveWordEnd,
-            'i' => VimCommand::InsertMode,
-            // Escape is usually not a char in this context, handled by KeyCode
+            KeyCode::U => {
+                if shift {
+                    VimCommand::None
+                } else {
+                    VimCommand::Undo
+                }
+            }
+            KeyCode::A => {
+                if shift {
+                    VimCommand::None
+                } else {
+                    VimCommand::Append
+                }
+            }
+            KeyCode::D if !shift => VimCommand::StartOperator(Operator::Delete),
+            KeyCode::C if !shift => VimCommand::StartOperator(Operator::Change),
+            KeyCode::Y if !shift => VimCommand::StartOperator(Operator::Yank),
+            KeyCode::P => {
+                if shift {
+                    VimCommand::PutBefore
+                } else {
+                    VimCommand::PutAfter
+                }
+            }
             _ => VimCommand::None,
         }
     }
@@ -161,23 +474,201 @@ impl InputHandler {
             "w" => VimCommand::MoveWordForward,
             "b" => VimCommand::MoveWordBack,
             "e" => VimCommand::MoveWordEnd,
+            "ge" => VimCommand::MoveWordEndBack,
+            "W" => VimCommand::MoveBigWordForward,
+            "B" => VimCommand::MoveBigWordBack,
+            "E" => VimCommand::MoveBigWordEnd,
+            "gE" => VimCommand::MoveBigWordEndBack,
             "0" => VimCommand::MoveLineStart,
             "$" => VimCommand::MoveLineEnd,
+            "^" => VimCommand::MoveFirstNonBlank,
+            "g_" => VimCommand::MoveLastNonBlank,
+            "_" => VimCommand::MoveLineFirstNonBlank,
+            "+" | "<CR>" => VimCommand::MoveNextLineStart,
+            "-" => VimCommand::MovePrevLineStart,
             "H" => VimCommand::MoveScreenTop,
             "M" => VimCommand::MoveScreenMiddle,
             "L" => VimCommand::MoveScreenBottom,
             "}" => VimCommand::MoveParagraphForward,
             "{" => VimCommand::MoveParagraphBack,
-            "StartSearchBackward" => VimCommand::StartSearchBackward,
-            "StartSearchForward" => VimCommand::StartSearchForward,
-            "NextMatch" => VimCommand::NextMatch,
-            "DeleteChar" => VimCommand::DeleteChar,
+            ")" => VimCommand::MoveSentenceForward,
+            "(" => VimCommand::MoveSentenceBack,
+            "]]" => VimCommand::MoveSectionForward,
+            "[[" => VimCommand::MoveSectionBack,
+            "][" => VimCommand::MoveSectionEndForward,
+            "[]" => VimCommand::MoveSectionEndBack,
+            "%" => VimCommand::MatchPair,
+            "[(" => VimCommand::PrevUnmatchedParen,
+            "[{" => VimCommand::PrevUnmatchedBrace,
+            "])" => VimCommand::NextUnmatchedParen,
+            "]}" => VimCommand::NextUnmatchedBrace,
+            "<C-e>" => VimCommand::ScrollLineDown,
+            "<C-y>" => VimCommand::ScrollLineUp,
+            "<C-d>" => VimCommand::ScrollHalfPageDown,
+            "<C-u>" => VimCommand::ScrollHalfPageUp,
+            "<C-f>" => VimCommand::ScrollPageDown,
+            "<C-b>" => VimCommand::ScrollPageUp,
+            "zt" => VimCommand::ScrollCursorTop,
+            "zz" => VimCommand::ScrollCursorCenter,
+            "zb" => VimCommand::ScrollCursorBottom,
+            "gg" => VimCommand::GotoFirstLine,
+            "G" => VimCommand::GotoLastLine,
+            ":" | "StartCommandLine" => VimCommand::StartCommandLine,
+            "f" | "StartFindNext" => VimCommand::StartFindNext,
+            "F" | "StartFindPrev" => VimCommand::StartFindPrev,
+            "t" | "StartTillNext" => VimCommand::StartTillNext,
+            "T" | "StartTillPrev" => VimCommand::StartTillPrev,
+            ";" | "RepeatFind" => VimCommand::RepeatFind,
+            "," | "RepeatFindReverse" => VimCommand::RepeatFindReverse,
+            "/" | "StartSearchForward" => VimCommand::StartSearchForward,
+            "?" | "StartSearchBackward" => VimCommand::StartSearchBackward,
+            "n" | "NextMatch" => VimCommand::NextMatch,
+            "N" | "PrevMatch" => VimCommand::PrevMatch,
+            "*" => VimCommand::SearchWordForward,
+            "#" => VimCommand::SearchWordBackward,
+            "g*" => VimCommand::SearchTextForward,
+            "g#" => VimCommand::SearchTextBackward,
+            "x" | "DeleteChar" => VimCommand::DeleteChar,
+            "u" | "Undo" => VimCommand::Undo,
+            "<C-r>" | "Redo" => VimCommand::Redo,
+            "a" | "Append" => VimCommand::Append,
+            "d" | "Delete" => VimCommand::StartOperator(Operator::Delete),
+            "c" | "Change" => VimCommand::StartOperator(Operator::Change),
+            "y" | "Yank" => VimCommand::StartOperator(Operator::Yank),
+            "p" | "PutAfter" => VimCommand::PutAfter,
+            "P" | "PutBefore" => VimCommand::PutBefore,
+            "m" | "StartSetMark" => VimCommand::StartSetMark,
+            "'" | "StartGotoMarkLine" => VimCommand::StartGotoMarkLine,
+            "`" | "StartGotoMark" => VimCommand::StartGotoMark,
+            "<C-o>" | "JumpOlder" => VimCommand::JumpOlder,
+            "<C-i>" | "<Tab>" | "JumpNewer" => VimCommand::JumpNewer,
+            "i" => VimCommand::InsertMode,
             _ => VimCommand::None,
         }
     }
 }
 
 impl VimCommand {
+    /// The command that the player actually types to start this one.
+    /// Completed commands (e.g. `FindNextChar('a')`) map back to their
+    /// starting key (`StartFindNext`), so key gating and penalties treat
+    /// `f` and `fa` the same way.
+    pub fn base(&self) -> VimCommand {
+        match self {
+            VimCommand::FindNextChar(_) => VimCommand::StartFindNext,
+            VimCommand::FindPrevChar(_) => VimCommand::StartFindPrev,
+            VimCommand::TillNextChar(_) => VimCommand::StartTillNext,
+            VimCommand::TillPrevChar(_) => VimCommand::StartTillPrev,
+            VimCommand::SearchForward(_) => VimCommand::StartSearchForward,
+            VimCommand::SearchBackward(_) => VimCommand::StartSearchBackward,
+            VimCommand::GotoLine(_) => VimCommand::StartCommandLine,
+            VimCommand::SetMark(_) => VimCommand::StartSetMark,
+            VimCommand::GotoMarkLine(_) => VimCommand::StartGotoMarkLine,
+            VimCommand::GotoMark(_) => VimCommand::StartGotoMark,
+            VimCommand::Operate(operator, _) => VimCommand::StartOperator(*operator),
+            VimCommand::Counted(_, inner) => inner.base(),
+            other => other.clone(),
+        }
+    }
+
+    /// Jumps are the motions that can go anywhere in the level: their
+    /// starting point goes on the jumplist for `Ctrl-o`.
+    pub fn is_jump(&self) -> bool {
+        match self {
+            VimCommand::Counted(_, inner) => inner.is_jump(),
+            command => matches!(
+                command,
+                VimCommand::SearchForward(_)
+                    | VimCommand::SearchBackward(_)
+                    | VimCommand::NextMatch
+                    | VimCommand::PrevMatch
+                    | VimCommand::SearchWordForward
+                    | VimCommand::SearchWordBackward
+                    | VimCommand::SearchTextForward
+                    | VimCommand::SearchTextBackward
+                    | VimCommand::GotoFirstLine
+                    | VimCommand::GotoLastLine
+                    | VimCommand::GotoLine(_)
+                    | VimCommand::MoveParagraphForward
+                    | VimCommand::MoveParagraphBack
+                    | VimCommand::MoveSentenceForward
+                    | VimCommand::MoveSentenceBack
+                    | VimCommand::MoveSectionForward
+                    | VimCommand::MoveSectionBack
+                    | VimCommand::MoveSectionEndForward
+                    | VimCommand::MoveSectionEndBack
+                    | VimCommand::MatchPair
+                    | VimCommand::MoveScreenTop
+                    | VimCommand::MoveScreenMiddle
+                    | VimCommand::MoveScreenBottom
+                    | VimCommand::GotoMarkLine(_)
+                    | VimCommand::GotoMark(_)
+            ),
+        }
+    }
+
+    /// Number of keys the player types for this command, including any
+    /// count digits, the argument of `f{char}` and the `<Enter>` ending a
+    /// search. Start commands (`f`, `/`, ...) cost nothing on their own;
+    /// they are charged in full once the completed command arrives.
+    pub fn keystrokes(&self) -> u32 {
+        match self {
+            VimCommand::None
+            | VimCommand::StartFindNext
+            | VimCommand::StartFindPrev
+            | VimCommand::StartTillNext
+            | VimCommand::StartTillPrev
+            | VimCommand::StartSearchForward
+            | VimCommand::StartSearchBackward
+            | VimCommand::StartCommandLine
+            | VimCommand::StartSetMark
+            | VimCommand::StartGotoMarkLine
+            | VimCommand::StartGotoMark
+            | VimCommand::StartOperator(_) => 0,
+            VimCommand::Counted(count, inner) => match inner.keystrokes() {
+                0 => 0,
+                keys => count.to_string().len() as u32 + keys,
+            },
+            VimCommand::MoveWordEndBack
+            | VimCommand::MoveBigWordEndBack
+            | VimCommand::MoveLastNonBlank
+            | VimCommand::SearchTextForward
+            | VimCommand::SearchTextBackward
+            | VimCommand::FindNextChar(_)
+            | VimCommand::FindPrevChar(_)
+            | VimCommand::TillNextChar(_)
+            | VimCommand::TillPrevChar(_)
+            | VimCommand::SetMark(_)
+            | VimCommand::GotoMarkLine(_)
+            | VimCommand::GotoMark(_)
+            | VimCommand::TextObject(_)
+            | VimCommand::ScrollCursorTop
+            | VimCommand::ScrollCursorCenter
+            | VimCommand::ScrollCursorBottom
+            | VimCommand::PrevUnmatchedParen
+            | VimCommand::PrevUnmatchedBrace
+            | VimCommand::NextUnmatchedParen
+            | VimCommand::NextUnmatchedBrace
+            | VimCommand::MoveSectionForward
+            | VimCommand::MoveSectionBack
+            | VimCommand::MoveSectionEndForward
+            | VimCommand::MoveSectionEndBack
+            | VimCommand::GotoFirstLine => 2,
+            // The operator key, then its motion (or the operator again for dd)
+            VimCommand::Operate(_, motion) => match motion.as_ref() {
+                VimCommand::StartOperator(_) => 2,
+                motion => 1 + motion.keystrokes(),
+            },
+            // '/' or '?', the query, then Enter
+            VimCommand::SearchForward(query) | VimCommand::SearchBackward(query) => {
+                query.chars().count() as u32 + 2
+            }
+            // ':', the line number, then Enter
+            VimCommand::GotoLine(line) => line.to_string().len() as u32 + 2,
+            _ => 1,
+        }
+    }
+
     pub fn to_display_string(&self) -> String {
         match self {
             VimCommand::MoveLeft => "h (Move Left)".to_string(),
@@ -187,13 +678,46 @@ impl VimCommand {
             VimCommand::MoveWordForward => "w (Word Forward)".to_string(),
             VimCommand::MoveWordBack => "b (Word Back)".to_string(),
             VimCommand::MoveWordEnd => "e (Word End)".to_string(),
+            VimCommand::MoveWordEndBack => "ge (Word End Back)".to_string(),
+            VimCommand::MoveBigWordForward => "W (WORD Forward)".to_string(),
+            VimCommand::MoveBigWordBack => "B (WORD Back)".to_string(),
+            VimCommand::MoveBigWordEnd => "E (WORD End)".to_string(),
+            VimCommand::MoveBigWordEndBack => "gE (WORD End Back)".to_string(),
             VimCommand::MoveLineStart => "0 (Line Start)".to_string(),
             VimCommand::MoveLineEnd => "$ (Line End)".to_string(),
+            VimCommand::MoveFirstNonBlank => "^ (First Non-Blank)".to_string(),
+            VimCommand::MoveLastNonBlank => "g_ (Last Non-Blank)".to_string(),
+            VimCommand::MoveLineFirstNonBlank => "_ (Line's First Non-Blank)".to_string(),
+            VimCommand::MoveNextLineStart => "+ (Next Line Start)".to_string(),
+            VimCommand::MovePrevLineStart => "- (Previous Line Start)".to_string(),
             VimCommand::MoveScreenTop => "H (Screen Top)".to_string(),
             VimCommand::MoveScreenMiddle => "M (Screen Middle)".to_string(),
             VimCommand::MoveScreenBottom => "L (Screen Bottom)".to_string(),
             VimCommand::MoveParagraphForward => "} (Paragraph Fwd)".to_string(),
             VimCommand::MoveParagraphBack => "{ (Paragraph Back)".to_string(),
+            VimCommand::MoveSentenceForward => ") (Sentence Fwd)".to_string(),
+            VimCommand::MoveSentenceBack => "( (Sentence Back)".to_string(),
+            VimCommand::MoveSectionForward => "]] (Section Fwd)".to_string(),
+            VimCommand::MoveSectionBack => "[[ (Section Back)".to_string(),
+            VimCommand::MoveSectionEndForward => "][ (Section End Fwd)".to_string(),
+            VimCommand::MoveSectionEndBack => "[] (Section End Back)".to_string(),
+            VimCommand::MatchPair => "% (Matching Bracket)".to_string(),
+            VimCommand::PrevUnmatchedParen => "[( (Unmatched '(')".to_string(),
+            VimCommand::PrevUnmatchedBrace => "[{ (Unmatched '{')".to_string(),
+            VimCommand::NextUnmatchedParen => "]) (Unmatched ')')".to_string(),
+            VimCommand::NextUnmatchedBrace => "]} (Unmatched '}')".to_string(),
+            VimCommand::ScrollLineDown => "Ctrl-e (Scroll Down)".to_string(),
+            VimCommand::ScrollLineUp => "Ctrl-y (Scroll Up)".to_string(),
+            VimCommand::ScrollHalfPageDown => "Ctrl-d (Half Page Down)".to_string(),
+            VimCommand::ScrollHalfPageUp => "Ctrl-u (Half Page Up)".to_string(),
+            VimCommand::ScrollPageDown => "Ctrl-f (Page Down)".to_string(),
+            VimCommand::ScrollPageUp => "Ctrl-b (Page Up)".to_string(),
+            VimCommand::ScrollCursorTop => "zt (Line to Top)".to_string(),
+            VimCommand::ScrollCursorCenter => "zz (Line to Middle)".to_string(),
+            VimCommand::ScrollCursorBottom => "zb (Line to Bottom)".to_string(),
+            VimCommand::GotoFirstLine => "gg (First Line)".to_string(),
+            VimCommand::GotoLastLine => "G (Last Line)".to_string(),
+            VimCommand::StartCommandLine => ": (Command Line)".to_string(),
             VimCommand::InsertMode => "i (Insert Mode)".to_string(),
             VimCommand::Escape => "Esc".to_string(),
             VimCommand::StartFindNext => "f (Find Next)".to_string(),
@@ -206,16 +730,137 @@ impl VimCommand {
             VimCommand::StartSearchBackward => "? (Search Back)".to_string(),
             VimCommand::NextMatch => "n (Next Match)".to_string(),
             VimCommand::PrevMatch => "N (Prev Match)".to_string(),
+            VimCommand::SearchWordForward => "* (Search Word Fwd)".to_string(),
+            VimCommand::SearchWordBackward => "# (Search Word Back)".to_string(),
+            VimCommand::SearchTextForward => "g* (Search Text Fwd)".to_string(),
+            VimCommand::SearchTextBackward => "g# (Search Text Back)".to_string(),
             VimCommand::DeleteChar => "x (Delete Char)".to_string(),
+            VimCommand::Undo => "u (Undo)".to_string(),
+            VimCommand::Redo => "Ctrl-r (Redo)".to_string(),
+            VimCommand::Append => "a (Append)".to_string(),
+            VimCommand::PutAfter => "p (Put After)".to_string(),
+            VimCommand::PutBefore => "P (Put Before)".to_string(),
+            VimCommand::StartSetMark => "m (Set Mark)".to_string(),
+            VimCommand::StartGotoMarkLine => "' (Mark's Line)".to_string(),
+            VimCommand::StartGotoMark => "` (Go to Mark)".to_string(),
+            VimCommand::JumpOlder => "Ctrl-o (Older Jump)".to_string(),
+            VimCommand::JumpNewer => "Ctrl-i (Newer Jump)".to_string(),
+            VimCommand::StartOperator(op) => format!("{} ({})", op.key(), op.name()),
+            VimCommand::TextObject(object) => object.keys().to_string(),
+            VimCommand::InsertChar(c) => format!("{} (Insert '{}')", c, c),
+            VimCommand::Operate(op, motion) => {
+                format!("{}{} ({})", op.key(), motion.to_keys(), op.name())
+            }
             VimCommand::FindNextChar(c) => format!("f {} (Find '{}')", c, c),
             VimCommand::FindPrevChar(c) => format!("F {} (Find Prev '{}')", c, c),
             VimCommand::TillNextChar(c) => format!("t {} (Till '{}')", c, c),
             VimCommand::TillPrevChar(c) => format!("T {} (Till Prev '{}')", c, c),
             VimCommand::SearchForward(s) => format!("/{} (Search '{}')", s, s),
             VimCommand::SearchBackward(s) => format!("?{} (Search Back '{}')", s, s),
+            VimCommand::GotoLine(line) => format!(":{} (Go to Line {})", line, line),
+            VimCommand::SetMark(c) => format!("m{} (Set Mark '{}')", c, c),
+            VimCommand::GotoMarkLine(c) => format!("'{} (Line of Mark '{}')", c, c),
+            VimCommand::GotoMark(c) => format!("`{} (Go to Mark '{}')", c, c),
+            VimCommand::Counted(count, inner) => format!("{}{}", count, inner.to_display_string()),
             VimCommand::None => "None".to_string(),
         }
     }
+
+    /// The exact keys a player types for this command, e.g. `3w` or `/door<CR>`.
+    pub fn to_keys(&self) -> String {
+        match self {
+            VimCommand::MoveLeft => "h".to_string(),
+            VimCommand::MoveDown => "j".to_string(),
+            VimCommand::MoveUp => "k".to_string(),
+            VimCommand::MoveRight => "l".to_string(),
+            VimCommand::MoveWordForward => "w".to_string(),
+            VimCommand::MoveWordBack => "b".to_string(),
+            VimCommand::MoveWordEnd => "e".to_string(),
+            VimCommand::MoveWordEndBack => "ge".to_string(),
+            VimCommand::MoveBigWordForward => "W".to_string(),
+            VimCommand::MoveBigWordBack => "B".to_string(),
+            VimCommand::MoveBigWordEnd => "E".to_string(),
+            VimCommand::MoveBigWordEndBack => "gE".to_string(),
+            VimCommand::MoveLineStart => "0".to_string(),
+            VimCommand::MoveLineEnd => "$".to_string(),
+            VimCommand::MoveFirstNonBlank => "^".to_string(),
+            VimCommand::MoveLastNonBlank => "g_".to_string(),
+            VimCommand::MoveLineFirstNonBlank => "_".to_string(),
+            VimCommand::MoveNextLineStart => "+".to_string(),
+            VimCommand::MovePrevLineStart => "-".to_string(),
+            VimCommand::MoveScreenTop => "H".to_string(),
+            VimCommand::MoveScreenMiddle => "M".to_string(),
+            VimCommand::MoveScreenBottom => "L".to_string(),
+            VimCommand::MoveParagraphForward => "}".to_string(),
+            VimCommand::MoveParagraphBack => "{".to_string(),
+            VimCommand::MoveSentenceForward => ")".to_string(),
+            VimCommand::MoveSentenceBack => "(".to_string(),
+            VimCommand::MoveSectionForward => "]]".to_string(),
+            VimCommand::MoveSectionBack => "[[".to_string(),
+            VimCommand::MoveSectionEndForward => "][".to_string(),
+            VimCommand::MoveSectionEndBack => "[]".to_string(),
+            VimCommand::MatchPair => "%".to_string(),
+            VimCommand::PrevUnmatchedParen => "[(".to_string(),
+            VimCommand::PrevUnmatchedBrace => "[{".to_string(),
+            VimCommand::NextUnmatchedParen => "])".to_string(),
+            VimCommand::NextUnmatchedBrace => "]}".to_string(),
+            VimCommand::ScrollLineDown => "<C-e>".to_string(),
+            VimCommand::ScrollLineUp => "<C-y>".to_string(),
+            VimCommand::ScrollHalfPageDown => "<C-d>".to_string(),
+            VimCommand::ScrollHalfPageUp => "<C-u>".to_string(),
+            VimCommand::ScrollPageDown => "<C-f>".to_string(),
+            VimCommand::ScrollPageUp => "<C-b>".to_string(),
+            VimCommand::ScrollCursorTop => "zt".to_string(),
+            VimCommand::ScrollCursorCenter => "zz".to_string(),
+            VimCommand::ScrollCursorBottom => "zb".to_string(),
+            VimCommand::GotoFirstLine => "gg".to_string(),
+            VimCommand::GotoLastLine => "G".to_string(),
+            VimCommand::StartCommandLine => ":".to_string(),
+            VimCommand::InsertMode => "i".to_string(),
+            VimCommand::Escape => "<Esc>".to_string(),
+            VimCommand::StartFindNext => "f".to_string(),
+            VimCommand::StartFindPrev => "F".to_string(),
+            VimCommand::StartTillNext => "t".to_string(),
+            VimCommand::StartTillPrev => "T".to_string(),
+            VimCommand::RepeatFind => ";".to_string(),
+            VimCommand::RepeatFindReverse => ",".to_string(),
+            VimCommand::StartSearchForward => "/".to_string(),
+            VimCommand::StartSearchBackward => "?".to_string(),
+            VimCommand::NextMatch => "n".to_string(),
+            VimCommand::PrevMatch => "N".to_string(),
+            VimCommand::SearchWordForward => "*".to_string(),
+            VimCommand::SearchWordBackward => "#".to_string(),
+            VimCommand::SearchTextForward => "g*".to_string(),
+            VimCommand::SearchTextBackward => "g#".to_string(),
+            VimCommand::DeleteChar => "x".to_string(),
+            VimCommand::Undo => "u".to_string(),
+            VimCommand::Redo => "<C-r>".to_string(),
+            VimCommand::Append => "a".to_string(),
+            VimCommand::PutAfter => "p".to_string(),
+            VimCommand::PutBefore => "P".to_string(),
+            VimCommand::StartSetMark => "m".to_string(),
+            VimCommand::StartGotoMarkLine => "'".to_string(),
+            VimCommand::StartGotoMark => "`".to_string(),
+            VimCommand::JumpOlder => "<C-o>".to_string(),
+            VimCommand::JumpNewer => "<C-i>".to_string(),
+            VimCommand::StartOperator(op) => op.key().to_string(),
+            VimCommand::TextObject(object) => object.keys().to_string(),
+            VimCommand::InsertChar(c) => c.to_string(),
+            VimCommand::Operate(op, motion) => format!("{}{}", op.key(), motion.to_keys()),
+            VimCommand::FindNextChar(c) => format!("f{}", c),
+            VimCommand::FindPrevChar(c) => format!("F{}", c),
+            VimCommand::TillNextChar(c) => format!("t{}", c),
+            VimCommand::TillPrevChar(c) => format!("T{}", c),
+            VimCommand::SearchForward(s) => format!("/{}<CR>", s),
+            VimCommand::SearchBackward(s) => format!("?{}<CR>", s),
+            VimCommand::GotoLine(line) => format!(":{}<CR>", line),
+            VimCommand::SetMark(c) => format!("m{}", c),
+            VimCommand::GotoMarkLine(c) => format!("'{}", c),
+            VimCommand::GotoMark(c) => format!("`{}", c),
+            VimCommand::Counted(count, inner) => format!("{}{}", count, inner.to_keys()),
+            VimCommand::None => String::new(),
+        }
+    }
 }
 
 #[cfg(test)]
@@ -230,4 +875,288 @@ mod tests {
         assert_eq!(handler.map_key(KeyCode::K, false), VimCommand::MoveUp);
         assert_eq!(handler.map_key(KeyCode::L, false), VimCommand::MoveRight);
     }
+
+    #[test]
+    fn test_count_prefix() {
+        let mut handler = InputHandler::new();
+        assert_eq!(handler.map_key(KeyCode::Key3, false), VimCommand::None);
+        assert_eq!(handler.pending_keys(), "3");
+        assert_eq!(
+            handler.map_key(KeyCode::W, false),
+            VimCommand::Counted(3, Box::new(VimCommand::MoveWordForward))
+        );
+        assert_eq!(handler.pending_keys(), "");
+
+        // '0' extends a pending count...
+        handler.map_key(KeyCode::Key1, false);
+        handler.map_key(KeyCode::Key0, false);
+        assert_eq!(
+            handler.map_key(KeyCode::L, false),
+            VimCommand::Counted(10, Box::new(VimCommand::MoveRight))
+        );
+
+        // ...but is a motion on its own
+        assert_eq!(handler.map_key(KeyCode::Key0, false), VimCommand::MoveLineStart);
+
+        // Escape cancels the count
+        handler.map_key(KeyCode::Key5, false);
+        assert_eq!(handler.map_key(KeyCode::Escape, false), VimCommand::Escape);
+        assert_eq!(handler.map_key(KeyCode::J, false), VimCommand::MoveDown);
+    }
+
+    #[test]
+    fn test_word_motion_mapping() {
+        let mut handler = InputHandler::new();
+        assert_eq!(handler.map_key(KeyCode::W, true), VimCommand::MoveBigWordForward);
+        assert_eq!(handler.map_key(KeyCode::E, true), VimCommand::MoveBigWordEnd);
+
+        // ge and gE, with Shift pressed in between
+        assert_eq!(handler.map_key(KeyCode::G, false), VimCommand::None);
+        assert_eq!(handler.pending_keys(), "g");
+        assert_eq!(handler.map_key(KeyCode::E, false), VimCommand::MoveWordEndBack);
+        handler.map_key(KeyCode::Key2, false);
+        handler.map_key(KeyCode::G, false);
+        handler.map_key(KeyCode::LeftShift, true);
+        assert_eq!(handler.pending_keys(), "2g");
+        assert_eq!(
+            handler.map_key(KeyCode::E, true),
+            VimCommand::Counted(2, Box::new(VimCommand::MoveBigWordEndBack))
+        );
+    }
+
+    #[test]
+    fn test_keystroke_costs() {
+        assert_eq!(VimCommand::MoveRight.keystrokes(), 1);
+        assert_eq!(VimCommand::StartFindNext.keystrokes(), 0);
+        assert_eq!(
+            VimCommand::Counted(12, Box::new(VimCommand::MoveRight)).keystrokes(),
+            3
+        );
+        assert_eq!(
+            VimCommand::Counted(2, Box::new(VimCommand::StartFindNext)).keystrokes(),
+            0
+        );
+        assert_eq!(VimCommand::FindNextChar('a').keystrokes(), 2);
+        assert_eq!(VimCommand::SearchForward("foo".to_string()).keystrokes(), 5);
+    }
+
+    #[test]
+    fn test_undo_redo_mapping() {
+        let mut handler = InputHandler::new();
+        assert_eq!(handler.map_key(KeyCode::U, false), VimCommand::Undo);
+        assert_eq!(handler.map_key(KeyCode::Key3, false), VimCommand::None);
+        assert_eq!(
+            handler.map_ctrl_key(KeyCode::R),
+            VimCommand::Counted(3, Box::new(VimCommand::Redo))
+        );
+        // Ctrl itself arrives as a key and must not clear the count
+        handler.map_key(KeyCode::Key2, false);
+        assert_eq!(handler.map_ctrl_key(KeyCode::LeftControl), VimCommand::None);
+        assert_eq!(handler.pending_keys(), "2");
+    }
+
+    #[test]
+    fn test_scroll_mapping() {
+        let mut handler = InputHandler::new();
+        assert_eq!(handler.map_ctrl_key(KeyCode::E), VimCommand::ScrollLineDown);
+        assert_eq!(handler.map_ctrl_key(KeyCode::B), VimCommand::ScrollPageUp);
+        handler.map_key(KeyCode::Key4, false);
+        assert_eq!(
+            handler.map_ctrl_key(KeyCode::D),
+            VimCommand::Counted(4, Box::new(VimCommand::ScrollHalfPageDown))
+        );
+
+        assert_eq!(handler.map_key(KeyCode::Z, false), VimCommand::None);
+        assert_eq!(handler.pending_keys(), "z");
+        assert_eq!(handler.map_key(KeyCode::T, false), VimCommand::ScrollCursorTop);
+        handler.map_key(KeyCode::Z, false);
+        assert_eq!(handler.map_key(KeyCode::Z, false), VimCommand::ScrollCursorCenter);
+        assert_eq!(VimCommand::ScrollCursorCenter.keystrokes(), 2);
+        assert_eq!(InputHandler::from_string("<C-u>"), VimCommand::ScrollHalfPageUp);
+        assert_eq!(VimCommand::ScrollPageDown.to_keys(), "<C-f>");
+    }
+
+    #[test]
+    fn test_line_jump_mapping() {
+        let mut handler = InputHandler::new();
+        assert_eq!(handler.map_key(KeyCode::G, true), VimCommand::GotoLastLine);
+        handler.map_key(KeyCode::G, false);
+        assert_eq!(handler.map_key(KeyCode::G, false), VimCommand::GotoFirstLine);
+        handler.map_key(KeyCode::Key1, false);
+        handler.map_key(KeyCode::Key2, false);
+        assert_eq!(
+            handler.map_key(KeyCode::G, true),
+            VimCommand::Counted(12, Box::new(VimCommand::GotoLastLine))
+        );
+        assert_eq!(
+            handler.map_key(KeyCode::Semicolon, true),
+            VimCommand::StartCommandLine
+        );
+        assert_eq!(VimCommand::GotoLine(12).keystrokes(), 4);
+        assert_eq!(VimCommand::GotoLine(12).to_keys(), ":12<CR>");
+        assert_eq!(VimCommand::GotoLine(12).base(), VimCommand::StartCommandLine);
+    }
+
+    #[test]
+    fn test_operator_mapping() {
+        let mut handler = InputHandler::new();
+        assert_eq!(
+            handler.map_key(KeyCode::D, false),
+            VimCommand::StartOperator(Operator::Delete)
+        );
+        assert_eq!(handler.map_key(KeyCode::P, true), VimCommand::PutBefore);
+        handler.map_key(KeyCode::Key2, false);
+        assert_eq!(
+            handler.map_key(KeyCode::Y, false),
+            VimCommand::Counted(2, Box::new(VimCommand::StartOperator(Operator::Yank)))
+        );
+
+        let dd = VimCommand::Operate(
+            Operator::Delete,
+            Box::new(VimCommand::StartOperator(Operator::Delete)),
+        );
+        assert_eq!(dd.to_keys(), "dd");
+        assert_eq!(dd.keystrokes(), 2);
+        let d2w = VimCommand::Operate(
+            Operator::Delete,
+            Box::new(VimCommand::Counted(2, Box::new(VimCommand::MoveWordForward))),
+        );
+        assert_eq!(d2w.to_keys(), "d2w");
+        assert_eq!(d2w.keystrokes(), 3);
+        let yiw = VimCommand::Operate(
+            Operator::Yank,
+            Box::new(VimCommand::TextObject(TextObject::InnerWord)),
+        );
+        assert_eq!(yiw.to_keys(), "yiw");
+        assert_eq!(yiw.keystrokes(), 3);
+    }
+
+    #[test]
+    fn test_to_keys() {
+        assert_eq!(
+            VimCommand::Counted(3, Box::new(VimCommand::MoveWordForward)).to_keys(),
+            "3w"
+        );
+        assert_eq!(VimCommand::MoveBigWordEndBack.to_keys(), "gE");
+        assert_eq!(VimCommand::TillPrevChar('x').to_keys(), "Tx");
+        assert_eq!(
+            VimCommand::SearchForward("door".to_string()).to_keys(),
+            "/door<CR>"
+        );
+    }
+
+    #[test]
+    fn test_from_string_accepts_keys_and_names() {
+        assert_eq!(InputHandler::from_string("f"), VimCommand::StartFindNext);
+        assert_eq!(InputHandler::from_string(";"), VimCommand::RepeatFind);
+        assert_eq!(InputHandler::from_string("/"), VimCommand::StartSearchForward);
+        assert_eq!(
+            InputHandler::from_string("StartSearchForward"),
+            VimCommand::StartSearchForward
+        );
+        assert_eq!(InputHandler::from_string("Enter"), VimCommand::None);
+    }
+
+    #[test]
+    fn test_mark_mapping() {
+        let mut handler = InputHandler::new();
+        assert_eq!(handler.map_key(KeyCode::M, false), VimCommand::StartSetMark);
+        assert_eq!(handler.map_key(KeyCode::M, true), VimCommand::MoveScreenMiddle);
+        assert_eq!(handler.map_key(KeyCode::Apostrophe, false), VimCommand::StartGotoMarkLine);
+        assert_eq!(handler.map_key(KeyCode::GraveAccent, false), VimCommand::StartGotoMark);
+        assert_eq!(handler.map_ctrl_key(KeyCode::O), VimCommand::JumpOlder);
+        handler.map_key(KeyCode::Key2, false);
+        assert_eq!(
+            handler.map_key(KeyCode::Tab, false),
+            VimCommand::Counted(2, Box::new(VimCommand::JumpNewer))
+        );
+        assert_eq!(VimCommand::GotoMark('a').keystrokes(), 2);
+        assert_eq!(VimCommand::GotoMarkLine('a').to_keys(), "'a");
+        assert_eq!(VimCommand::SetMark('a').base(), VimCommand::StartSetMark);
+        assert!(VimCommand::Counted(3, Box::new(VimCommand::NextMatch)).is_jump());
+        assert!(!VimCommand::MoveWordForward.is_jump());
+    }
+
+    #[test]
+    fn test_bracket_mapping() {
+        let mut handler = InputHandler::new();
+        assert_eq!(handler.map_key(KeyCode::Key5, true), VimCommand::MatchPair);
+        assert_eq!(handler.map_key(KeyCode::LeftBracket, false), VimCommand::None);
+        assert_eq!(handler.pending_keys(), "[");
+        assert_eq!(handler.map_key(KeyCode::Key9, true), VimCommand::PrevUnmatchedParen);
+        handler.map_key(KeyCode::Key2, false);
+        handler.map_key(KeyCode::RightBracket, false);
+        assert_eq!(
+            handler.map_key(KeyCode::RightBracket, true),
+            VimCommand::Counted(2, Box::new(VimCommand::NextUnmatchedBrace))
+        );
+        // { and } on their own are still paragraphs
+        assert_eq!(handler.map_key(KeyCode::RightBracket, true), VimCommand::MoveParagraphForward);
+        assert_eq!(VimCommand::NextUnmatchedParen.to_keys(), "])");
+        assert_eq!(VimCommand::PrevUnmatchedBrace.keystrokes(), 2);
+    }
+
+    #[test]
+    fn test_line_motion_mapping() {
+        let mut handler = InputHandler::new();
+        assert_eq!(handler.map_key(KeyCode::Key6, true), VimCommand::MoveFirstNonBlank);
+        assert_eq!(handler.map_key(KeyCode::Minus, true), VimCommand::MoveLineFirstNonBlank);
+        assert_eq!(handler.map_key(KeyCode::Minus, false), VimCommand::MovePrevLineStart);
+        assert_eq!(handler.map_key(KeyCode::Equal, true), VimCommand::MoveNextLineStart);
+        handler.map_key(KeyCode::Key3, false);
+        assert_eq!(
+            handler.map_key(KeyCode::Enter, false),
+            VimCommand::Counted(3, Box::new(VimCommand::MoveNextLineStart))
+        );
+        handler.map_key(KeyCode::G, false);
+        assert_eq!(handler.map_key(KeyCode::Minus, true), VimCommand::MoveLastNonBlank);
+        assert_eq!(VimCommand::MoveLastNonBlank.keystrokes(), 2);
+        assert_eq!(InputHandler::from_string("<CR>"), VimCommand::MoveNextLineStart);
+    }
+
+    #[test]
+    fn test_star_mapping() {
+        let mut handler = InputHandler::new();
+        assert_eq!(handler.map_key(KeyCode::Key8, true), VimCommand::SearchWordForward);
+        assert_eq!(handler.map_key(KeyCode::Key3, true), VimCommand::SearchWordBackward);
+        handler.map_key(KeyCode::Key2, false);
+        handler.map_key(KeyCode::G, false);
+        assert_eq!(
+            handler.map_key(KeyCode::Key8, true),
+            VimCommand::Counted(2, Box::new(VimCommand::SearchTextForward))
+        );
+        handler.map_key(KeyCode::G, false);
+        assert_eq!(handler.map_key(KeyCode::Key3, true), VimCommand::SearchTextBackward);
+        assert_eq!(VimCommand::SearchTextBackward.keystrokes(), 2);
+        assert_eq!(InputHandler::from_string("g*"), VimCommand::SearchTextForward);
+        assert!(VimCommand::SearchWordBackward.is_jump());
+    }
+
+    #[test]
+    fn test_sentence_and_section_mapping() {
+        let mut handler = InputHandler::new();
+        assert_eq!(handler.map_key(KeyCode::Key9, true), VimCommand::MoveSentenceBack);
+        assert_eq!(handler.map_key(KeyCode::Key0, true), VimCommand::MoveSentenceForward);
+        for (first, second, command) in [
+            (KeyCode::RightBracket, KeyCode::RightBracket, VimCommand::MoveSectionForward),
+            (KeyCode::LeftBracket, KeyCode::LeftBracket, VimCommand::MoveSectionBack),
+            (KeyCode::RightBracket, KeyCode::LeftBracket, VimCommand::MoveSectionEndForward),
+            (KeyCode::LeftBracket, KeyCode::RightBracket, VimCommand::MoveSectionEndBack),
+        ] {
+            assert_eq!(handler.map_key(first, false), VimCommand::None);
+            assert_eq!(handler.map_key(second, false), command);
+        }
+        assert_eq!(InputHandler::from_string("]["), VimCommand::MoveSectionEndForward);
+        assert_eq!(VimCommand::MoveSectionBack.keystrokes(), 2);
+    }
+
+    #[test]
+    fn test_base_command() {
+        assert_eq!(VimCommand::FindNextChar('a').base(), VimCommand::StartFindNext);
+        assert_eq!(
+            VimCommand::SearchBackward("x".to_string()).base(),
+            VimCommand::StartSearchBackward
+        );
+        assert_eq!(VimCommand::MoveLeft.base(), VimCommand::MoveLeft);
+    }
 }
@@ -1,11 +1,185 @@
+use crate::input::{InputHandler, VimCommand};
 use serde::{Deserialize, Serialize};
+use std::fmt;
 
-#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct Position {
     pub x: usize,
     pub y: usize,
 }
 
+/// What happens when the player uses a command outside `allowed_keys`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum KeyPolicy {
+    /// The command is ignored and shown as blocked.
+    #[default]
+    Reject,
+    /// The command runs, but costs a penalty.
+    Penalize,
+}
+
+/// What the player has to do to finish a level.
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Goal {
+    /// Reach `target_pos`.
+    #[default]
+    Reach,
+    /// Edit the layout until it matches `layout` exactly.
+    Text { layout: Vec<String> },
+    /// Reach `target_pos` while the layout matches `layout`.
+    TextThenReach { layout: Vec<String> },
+}
+
+impl Goal {
+    /// The layout the player has to produce, if any.
+    pub fn layout(&self) -> Option<&[String]> {
+        match self {
+            Goal::Reach => None,
+            Goal::Text { layout } | Goal::TextThenReach { layout } => Some(layout),
+        }
+    }
+
+    pub fn is_met(&self, at_target: bool, layout: &[String]) -> bool {
+        let text_matches = self.layout().is_none_or(|goal| goal == layout);
+        let reached = at_target || matches!(self, Goal::Text { .. });
+        text_matches && reached
+    }
+
+    /// Cells where `layout` still differs from the goal layout.
+    pub fn differences(&self, layout: &[String]) -> Vec<Position> {
+        let Some(goal) = self.layout() else {
+            return Vec::new();
+        };
+        let mut cells = Vec::new();
+        for (y, (row, goal_row)) in layout.iter().zip(goal).enumerate() {
+            for (x, (c, want)) in row.chars().zip(goal_row.chars()).enumerate() {
+                if c != want {
+                    cells.push(Position { x, y });
+                }
+            }
+        }
+        cells
+    }
+}
+
+/// A problem that stops a level (or a pack of levels) from loading or makes it unplayable.
+#[derive(Debug, Clone, PartialEq)]
+pub enum LevelError {
+    /// The level file could not be read at all.
+    Unreadable(String),
+    /// The JSON is malformed or doesn't match the schema (1-based position).
+    Json {
+        line: usize,
+        column: usize,
+        message: String,
+    },
+    /// An id that isn't a plain file name; saves are stored under the id.
+    BadId(String),
+    EmptyLayout,
+    /// A row whose length differs from the first row's.
+    RaggedRow {
+        row: usize,
+        len: usize,
+        expected: usize,
+    },
+    StartOutOfBounds(Position),
+    TargetOutOfBounds(Position),
+    StartOnWall(Position),
+    TargetOnWall(Position),
+    /// An `S`/`E` glyph in the layout that disagrees with `start_pos`/`target_pos`.
+    GlyphMismatch {
+        glyph: char,
+        found: Position,
+        expected: Position,
+    },
+    /// A name in `allowed_keys` or `trained_commands` that isn't a known command.
+    UnknownCommand { field: &'static str, name: String },
+    /// A goal layout whose size differs from the level's layout.
+    GoalShapeMismatch {
+        width: usize,
+        height: usize,
+        expected_width: usize,
+        expected_height: usize,
+    },
+    /// A goal layout that changes a wall, `S` or `E`, which no edit can do.
+    GoalEditsFixedCell(Position),
+}
+
+impl fmt::Display for LevelError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LevelError::Unreadable(e) => write!(f, "could not read level: {}", e),
+            LevelError::Json {
+                line,
+                column,
+                message,
+            } => write!(f, "line {}, column {}: {}", line, column, message),
+            LevelError::BadId(id) => {
+                write!(f, "id '{}' can't contain '/', '\\' or '..'", id)
+            }
+            LevelError::EmptyLayout => write!(f, "layout is empty"),
+            LevelError::RaggedRow { row, len, expected } => write!(
+                f,
+                "layout row {} is {} wide, expected {}",
+                row, len, expected
+            ),
+            LevelError::StartOutOfBounds(p) => {
+                write!(f, "start_pos ({}, {}) is outside the layout", p.x, p.y)
+            }
+            LevelError::TargetOutOfBounds(p) => {
+                write!(f, "target_pos ({}, {}) is outside the layout", p.x, p.y)
+            }
+            LevelError::StartOnWall(p) => write!(f, "start_pos ({}, {}) is a wall", p.x, p.y),
+            LevelError::TargetOnWall(p) => write!(f, "target_pos ({}, {}) is a wall", p.x, p.y),
+            LevelError::GlyphMismatch {
+                glyph,
+                found,
+                expected,
+            } => write!(
+                f,
+                "'{}' is drawn at ({}, {}) but the level says ({}, {})",
+                glyph, found.x, found.y, expected.x, expected.y
+            ),
+            LevelError::UnknownCommand { field, name } => {
+                write!(f, "unknown command '{}' in {}", name, field)
+            }
+            LevelError::GoalShapeMismatch {
+                width,
+                height,
+                expected_width,
+                expected_height,
+            } => write!(
+                f,
+                "goal layout is {}x{}, expected {}x{}",
+                width, height, expected_width, expected_height
+            ),
+            LevelError::GoalEditsFixedCell(p) => write!(
+                f,
+                "goal layout changes the wall, S or E at ({}, {}), which can't be edited",
+                p.x, p.y
+            ),
+        }
+    }
+}
+
+impl From<serde_json::Error> for LevelError {
+    fn from(e: serde_json::Error) -> Self {
+        // serde_json appends " at line L column C" to its message; keep it structured instead
+        let message = e.to_string();
+        let message = match message.rsplit_once(" at line ") {
+            Some((head, _)) => head.to_string(),
+            None => message,
+        };
+        LevelError::Json {
+            line: e.line(),
+            column: e.column(),
+            message,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Level {
     pub id: String,
@@ -14,8 +188,16 @@ pub struct Level {
     pub layout: Vec<String>,
     pub start_pos: Position,
     pub target_pos: Position,
+    /// Defaults to reaching `target_pos`.
+    #[serde(default)]
+    pub goal: Goal,
     pub allowed_keys: Vec<String>,
     #[serde(default)]
+    pub key_policy: KeyPolicy,
+    /// Score lost each time the player undoes a command.
+    #[serde(default = "default_undo_penalty")]
+    pub undo_penalty: u32,
+    #[serde(default)]
     pub trained_commands: Vec<String>,
     #[serde(default)]
     pub tutorial_text: String,
@@ -23,6 +205,10 @@ pub struct Level {
     pub par_keystrokes: u32,
 }
 
+fn default_undo_penalty() -> u32 {
+    50
+}
+
 impl Level {
     pub fn width(&self) -> usize {
         self.layout.iter().map(|row| row.len()).max().unwrap_or(0)
@@ -42,6 +228,126 @@ impl Level {
         }
         row.chars().nth(x).unwrap_or('.') == '#'
     }
+
+    /// FNV-1a hash of everything that affects play, so a replay can tell
+    /// whether it was recorded on this version of the level.
+    pub fn fingerprint(&self) -> u64 {
+        let gameplay = (
+            &self.layout,
+            self.start_pos,
+            self.target_pos,
+            &self.goal,
+            &self.allowed_keys,
+            self.key_policy,
+        );
+        let bytes = serde_json::to_vec(&gameplay).unwrap_or_default();
+        bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+            (hash ^ u64::from(byte)).wrapping_mul(0x0000_0100_0000_01b3)
+        })
+    }
+
+    fn in_bounds(&self, pos: Position) -> bool {
+        pos.y < self.height() && pos.x < self.layout[pos.y].len()
+    }
+
+    /// Parses a level and validates it, collecting every problem found.
+    pub fn from_json(json: &str) -> Result<Level, Vec<LevelError>> {
+        let level: Level = serde_json::from_str(json).map_err(|e| vec![LevelError::from(e)])?;
+        let errors = level.validate();
+        if errors.is_empty() {
+            Ok(level)
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Checks the level for problems that would make it unplayable.
+    pub fn validate(&self) -> Vec<LevelError> {
+        if self.layout.iter().all(|row| row.is_empty()) {
+            return vec![LevelError::EmptyLayout];
+        }
+
+        let mut errors = Vec::new();
+        if ["/", "\\", ".."].iter().any(|bad| self.id.contains(bad)) {
+            errors.push(LevelError::BadId(self.id.clone()));
+        }
+        let expected = self.layout[0].len();
+        for (row, line) in self.layout.iter().enumerate() {
+            if line.len() != expected {
+                errors.push(LevelError::RaggedRow {
+                    row,
+                    len: line.len(),
+                    expected,
+                });
+            }
+        }
+
+        if !self.in_bounds(self.start_pos) {
+            errors.push(LevelError::StartOutOfBounds(self.start_pos));
+        } else if self.is_wall(self.start_pos.x, self.start_pos.y) {
+            errors.push(LevelError::StartOnWall(self.start_pos));
+        }
+        if !self.in_bounds(self.target_pos) {
+            errors.push(LevelError::TargetOutOfBounds(self.target_pos));
+        } else if self.is_wall(self.target_pos.x, self.target_pos.y) {
+            errors.push(LevelError::TargetOnWall(self.target_pos));
+        }
+
+        // Glyphs are optional, but when drawn they must agree with the positions
+        for (glyph, expected) in [('S', self.start_pos), ('E', self.target_pos)] {
+            for (y, line) in self.layout.iter().enumerate() {
+                for (x, c) in line.chars().enumerate() {
+                    let found = Position { x, y };
+                    if c == glyph && found != expected {
+                        errors.push(LevelError::GlyphMismatch {
+                            glyph,
+                            found,
+                            expected,
+                        });
+                    }
+                }
+            }
+        }
+
+        if let Some(goal) = self.goal.layout() {
+            let shape = |rows: &[String]| (rows.first().map_or(0, |row| row.len()), rows.len());
+            let (width, height) = shape(goal);
+            let (expected_width, expected_height) = shape(&self.layout);
+            if goal.iter().any(|row| row.len() != width)
+                || (width, height) != (expected_width, expected_height)
+            {
+                errors.push(LevelError::GoalShapeMismatch {
+                    width,
+                    height,
+                    expected_width,
+                    expected_height,
+                });
+            } else {
+                let fixed = |c: char| matches!(c, '#' | 'S' | 'E');
+                for pos in self.goal.differences(&self.layout) {
+                    let cell = |rows: &[String]| rows[pos.y].chars().nth(pos.x).unwrap_or(' ');
+                    if fixed(cell(&self.layout)) || fixed(cell(goal)) {
+                        errors.push(LevelError::GoalEditsFixedCell(pos));
+                    }
+                }
+            }
+        }
+
+        for (field, names) in [
+            ("allowed_keys", &self.allowed_keys),
+            ("trained_commands", &self.trained_commands),
+        ] {
+            for name in names {
+                if InputHandler::from_string(name) == VimCommand::None {
+                    errors.push(LevelError::UnknownCommand {
+                        field,
+                        name: name.clone(),
+                    });
+                }
+            }
+        }
+        errors
+    }
 }
 
 #[cfg(test)]
@@ -57,7 +363,10 @@ mod tests {
             layout: vec!["...".to_string(), "...".to_string()],
             start_pos: Position { x: 0, y: 0 },
             target_pos: Position { x: 2, y: 1 },
+            goal: Goal::Reach,
             allowed_keys: vec![],
+            key_policy: KeyPolicy::Reject,
+            undo_penalty: 0,
             trained_commands: vec![],
             tutorial_text: String::new(),
             par_time: 10.0,
@@ -66,4 +375,133 @@ mod tests {
         assert_eq!(level.width(), 3);
         assert_eq!(level.height(), 2);
     }
+
+    #[test]
+    fn test_validate() {
+        let mut level = Level {
+            id: "test".to_string(),
+            name: "Test".to_string(),
+            description: "Test".to_string(),
+            layout: vec!["#S.E#".to_string()],
+            start_pos: Position { x: 1, y: 0 },
+            target_pos: Position { x: 3, y: 0 },
+            goal: Goal::Reach,
+            allowed_keys: vec![],
+            key_policy: KeyPolicy::Reject,
+            undo_penalty: 0,
+            trained_commands: vec![],
+            tutorial_text: String::new(),
+            par_time: 10.0,
+            par_keystrokes: 2,
+        };
+        assert!(level.validate().is_empty());
+
+        level.start_pos = Position { x: 0, y: 0 };
+        level.target_pos = Position { x: 3, y: 1 };
+        assert_eq!(
+            level.validate(),
+            vec![
+                LevelError::StartOnWall(Position { x: 0, y: 0 }),
+                LevelError::TargetOutOfBounds(Position { x: 3, y: 1 }),
+                LevelError::GlyphMismatch {
+                    glyph: 'S',
+                    found: Position { x: 1, y: 0 },
+                    expected: Position { x: 0, y: 0 },
+                },
+                LevelError::GlyphMismatch {
+                    glyph: 'E',
+                    found: Position { x: 3, y: 0 },
+                    expected: Position { x: 3, y: 1 },
+                },
+            ]
+        );
+
+        level.start_pos = Position { x: 1, y: 0 };
+        level.target_pos = Position { x: 3, y: 0 };
+        level.layout.push("#..".to_string());
+        level.trained_commands = vec!["w".to_string(), "Teleport".to_string()];
+        assert_eq!(
+            level.validate(),
+            vec![
+                LevelError::RaggedRow {
+                    row: 1,
+                    len: 3,
+                    expected: 5,
+                },
+                LevelError::UnknownCommand {
+                    field: "trained_commands",
+                    name: "Teleport".to_string(),
+                },
+            ]
+        );
+
+        level.layout.pop();
+        level.trained_commands.clear();
+        for id in ["../../.config/x", "a\\b", "..", "packs/a"] {
+            level.id = id.to_string();
+            assert_eq!(level.validate(), vec![LevelError::BadId(id.to_string())]);
+        }
+    }
+
+    #[test]
+    fn test_goal() {
+        let mut level = Level {
+            id: "test".to_string(),
+            name: "Test".to_string(),
+            description: "Test".to_string(),
+            layout: vec!["#Sab.E#".to_string()],
+            start_pos: Position { x: 1, y: 0 },
+            target_pos: Position { x: 5, y: 0 },
+            goal: Goal::TextThenReach {
+                layout: vec!["#S.b.E#".to_string()],
+            },
+            allowed_keys: vec![],
+            key_policy: KeyPolicy::Reject,
+            undo_penalty: 0,
+            trained_commands: vec![],
+            tutorial_text: String::new(),
+            par_time: 10.0,
+            par_keystrokes: 2,
+        };
+        assert!(level.validate().is_empty());
+        assert_eq!(level.goal.differences(&level.layout), vec![Position { x: 2, y: 0 }]);
+        let fixed = vec!["#S.b.E#".to_string()];
+        assert!(!level.goal.is_met(true, &level.layout));
+        assert!(!level.goal.is_met(false, &fixed));
+        assert!(level.goal.is_met(true, &fixed));
+
+        level.goal = Goal::Text {
+            layout: vec!["#S...E.".to_string()],
+        };
+        assert!(level.goal.is_met(false, &["#S...E.".to_string()]));
+        assert_eq!(
+            level.validate(),
+            vec![LevelError::GoalEditsFixedCell(Position { x: 6, y: 0 })]
+        );
+
+        level.goal = Goal::Text {
+            layout: vec!["#S.E#".to_string(), "#...#".to_string()],
+        };
+        assert_eq!(
+            level.validate(),
+            vec![LevelError::GoalShapeMismatch {
+                width: 5,
+                height: 2,
+                expected_width: 7,
+                expected_height: 1,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_from_json_reports_position() {
+        let errors = Level::from_json("{\n  \"id\": \"x\",\n  \"name\": 3\n}").unwrap_err();
+        match &errors[..] {
+            [LevelError::Json { line, column, .. }] => {
+                assert_eq!(*line, 3);
+                assert_eq!(*column, 11);
+            }
+            other => panic!("unexpected errors: {:?}", other),
+        }
+    }
 }
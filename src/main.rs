@@ -1,71 +1,184 @@
 use macroquad::prelude::*;
 
+mod cli;
+mod daily;
+mod editor;
+mod ex;
 mod game;
+mod generator;
 mod input;
 mod level;
+mod marks;
+mod pack;
+mod pattern;
+mod progress;
 mod render;
+mod replay;
 mod solver;
-
-use game::{GameState, GameStatus, InputMode};
-use input::InputHandler;
-use level::Level;
+mod storage;
+mod viewport;
+
+use daily::{ChallengeBuilder, DailyChallenge, DailyHistory, DailyResult, Date};
+use editor::Editor;
+use ex::{ExCommand, SetOption};
+use game::{GameState, GameStatus, InputMode, Message};
+use input::{InputHandler, VimCommand};
+use level::{Level, LevelError};
+use pack::{PackError, PackManifest};
+use pattern::CaseOptions;
+use progress::Progress;
+use replay::{AUTO_PLAY_TICK, Replay};
 
 use solver::Solver;
+use viewport::LineNumbers;
+
+/// A level that failed to load, shown in the menu instead of crashing.
+struct LoadFailure {
+    path: String,
+    errors: Vec<String>,
+}
+
+/// A pack as shown in the menu.
+struct LoadedPack {
+    dir: String,
+    manifest: PackManifest,
+    paths: Vec<String>,
+    /// Each level as loaded at startup (None if it failed): names, pars and
+    /// the unlock rule. Playing a level loads it afresh.
+    catalog: Vec<Option<Level>>,
+}
+
+impl LoadedPack {
+    /// Level ids in campaign order, for the unlock rule.
+    fn campaign(&self) -> Vec<Option<&str>> {
+        self.catalog
+            .iter()
+            .map(|level| level.as_ref().map(|level| level.id.as_str()))
+            .collect()
+    }
+}
+
+async fn load_pack(dir: &str) -> Result<LoadedPack, LoadFailure> {
+    let path = format!("{}/{}", dir.trim_end_matches('/'), pack::MANIFEST_FILE);
+    let result = match load_string(&path).await {
+        Ok(json_content) => PackManifest::from_json(&json_content),
+        Err(e) => Err(vec![PackError::Unreadable(e.to_string())]),
+    };
+    let manifest = result.map_err(|errors| load_failure(&path, &errors))?;
+
+    let paths = manifest.level_paths(dir);
+    let mut catalog = Vec::new();
+    for path in &paths {
+        catalog.push(load_level_from_file(path).await.ok());
+    }
+    Ok(LoadedPack {
+        dir: dir.to_string(),
+        manifest,
+        paths,
+        catalog,
+    })
+}
+
+/// Loads every pack to offer. A pack that fails to load is left out, and
+/// the first failure is returned to be reported.
+async fn load_packs(args: &[String]) -> (Vec<LoadedPack>, Option<LoadFailure>) {
+    let mut packs = Vec::new();
+    let mut first_failure = None;
+    for dir in pack::pack_dirs(args) {
+        match load_pack(&dir).await {
+            Ok(pack) => packs.push(pack),
+            Err(failure) => {
+                first_failure.get_or_insert(failure);
+            }
+        }
+    }
+    (packs, first_failure)
+}
 
-async fn load_level_from_file(path: &str) -> Level {
-    let json_content = load_string(path).await.expect("Failed to read level file");
-    serde_json::from_str(&json_content).expect("Failed to parse level JSON")
+async fn load_level_from_file(path: &str) -> Result<Level, LoadFailure> {
+    let result = match load_string(path).await {
+        Ok(json_content) => Level::from_json(&json_content),
+        Err(e) => Err(vec![LevelError::Unreadable(e.to_string())]),
+    };
+    result.map_err(|errors| load_failure(path, &errors))
 }
 
-#[macroquad::main("VIM Game")]
-async fn main() {
+/// Logs a file's errors, and keeps them to show in the menu.
+fn load_failure(path: &str, errors: &[impl ToString]) -> LoadFailure {
+    let errors: Vec<String> = errors.iter().map(ToString::to_string).collect();
+    for error in &errors {
+        eprintln!("{}: {}", path, error);
+    }
+    LoadFailure {
+        path: path.to_string(),
+        errors,
+    }
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    // Headless: exit before a window is ever created
+    match args.get(1).map(String::as_str) {
+        Some("check") => std::process::exit(cli::run_check(&args[2..])),
+        Some("generate") => std::process::exit(cli::run_generate(&args[2..])),
+        _ => {}
+    }
+    macroquad::Window::new("VIM Game", run());
+}
+
+async fn run() {
     let resources = render::Resources::new().await;
 
-    let levels = vec![
-        "assets/levels/level_01.json",
-        "assets/levels/level_02.json",
-        "assets/levels/level_03.json",
-        "assets/levels/level_04.json",
-        "assets/levels/level_05.json",
-        "assets/levels/level_06.json",
-        "assets/levels/level_07.json",
-        "assets/levels/level_08.json",
-        "assets/levels/level_09.json",
-        "assets/levels/level_10.json",
-        "assets/levels/level_11.json",
-        "assets/levels/level_12.json",
-        "assets/levels/level_13.json",
-        "assets/levels/level_14.json",
-        "assets/levels/level_15.json",
-        "assets/levels/level_16.json",
-        "assets/levels/level_17.json",
-        "assets/levels/level_18.json",
-        "assets/levels/level_19.json",
-        "assets/levels/level_20.json",
-    ];
-
-    let mut current_level_index: Option<usize> = None;
+    // The level being played, as (pack, level) indexes
+    let mut current_level: Option<(usize, usize)> = None;
     let mut game_state: Option<GameState> = None;
+    let mut editor: Option<Editor> = None;
     let mut input_handler = InputHandler::new();
+    let mut load_failure: Option<LoadFailure> = None;
 
     let args: Vec<String> = std::env::args().collect();
     let solve_all_mode = args.contains(&"--solve-all".to_string());
 
+    let (mut packs, pack_failure) = load_packs(&args).await;
+    if pack_failure.is_some() {
+        load_failure = pack_failure;
+    }
+    let mut progress = Progress::load();
+
+    // Daily challenge: the calendar is open while `daily_month` is set
+    let mut daily_history = DailyHistory::load();
+    let mut daily_month: Option<Date> = None;
+    let mut daily_challenge: Option<DailyChallenge> = None;
+    // Today's challenge while it is being made, a slice each frame
+    let mut daily_builder: Option<ChallengeBuilder> = None;
+    let mut playing_daily = false;
+
     // Menu state
+    let mut menu_pack = 0;
     let mut menu_selection = 0;
     let mut menu_scroll_y = 0.0;
 
     let cell_size = 40.0;
-
-    if solve_all_mode {
-        let level = load_level_from_file(levels[0]).await;
-        let mut state = GameState::new(level);
-        let solver = Solver::new(state.current_level.clone());
-        if let Some(solution) = solver.solve() {
-            state.start_auto_play(solution);
+    let mut line_numbers = LineNumbers::Hybrid;
+    // Lines typed after `:`, and searches, kept from level to level
+    let mut ex_history = ex::History::default();
+    let mut search_history = ex::History::default();
+    let mut case_options = CaseOptions::default();
+
+    if solve_all_mode && !packs.is_empty() {
+        match load_level_from_file(&packs[0].paths[0]).await {
+            Ok(level) => {
+                let mut state = GameState::new(level);
+                let (rows, cols) = render::visible_cells(cell_size, line_numbers);
+                let solver = Solver::new(state.current_level.clone()).with_screen(rows, cols);
+                if let Ok(solution) = solver.solve() {
+                    state.start_auto_play(replay::paced(solution.commands, AUTO_PLAY_TICK));
+                }
+                game_state = Some(state);
+                current_level = Some((0, 0));
+            }
+            Err(failure) => load_failure = Some(failure),
         }
-        game_state = Some(state);
-        current_level_index = Some(0);
     }
 
     loop {
@@ -81,52 +194,138 @@ async fn main() {
 
         clear_background(BLACK);
 
+        if let Some(ref mut ed) = editor {
+            // EDITOR
+            ed.update(get_frame_time());
+            if let Some(key) = get_last_key_pressed() {
+                ed.handle_special_key(key);
+            }
+            // Characters come off a stack; replay them in the order typed
+            let mut typed = Vec::new();
+            while let Some(c) = get_char_pressed() {
+                typed.push(c);
+            }
+            for c in typed.into_iter().rev() {
+                ed.handle_char_input(c);
+            }
+            render::draw_editor(ed, &resources, cell_size);
+
+            if ed.quit {
+                // Pick up saved changes in the menu
+                if ed.saved {
+                    let (reloaded, failure) = load_packs(&args).await;
+                    packs = reloaded;
+                    load_failure = failure;
+                    menu_pack = menu_pack.min(packs.len().saturating_sub(1));
+                    menu_selection = 0;
+                    menu_scroll_y = 0.0;
+                }
+                editor = None;
+            }
+            next_frame().await;
+            continue;
+        }
+
+        // Keys that leave a level mustn't also act on the screen shown next
+        let was_playing = game_state.is_some();
         let mut should_exit_to_menu = false;
         let mut should_load_next_level = false;
+        let mut should_watch_replay = false;
+        let mut should_restart = false;
+        let mut jump_to_level: Option<usize> = None;
 
         if let Some(ref mut state) = game_state {
             // GAME UPDATE
 
             // Update
+            let (rows, cols) = render::visible_cells(cell_size, line_numbers);
+            state.resize_viewport(rows, cols);
+            // Auto-play keeps the options it was recorded or solved with
+            if !state.is_auto_playing {
+                state.case_options = case_options;
+            }
             let dt = get_frame_time();
             state.update(dt);
 
-            if solve_all_mode {
-                if let GameStatus::LevelComplete = state.status {
-                    if state.level_complete_timer > 2.0 {
-                        should_load_next_level = true;
-                    }
-                }
+            if solve_all_mode
+                && let GameStatus::LevelComplete = state.status
+                && state.level_complete_timer > 2.0
+            {
+                should_load_next_level = true;
             }
 
             // Input
+            if state.command_line().is_none() {
+                ex_history.reset();
+                search_history.reset();
+            }
             if let Some(key) = get_last_key_pressed() {
                 let mut handled = false;
-                if !matches!(state.input_mode, InputMode::Normal) {
-                    state.handle_special_key(key);
-                    if let Some(c) = get_char_pressed() {
-                        if key != KeyCode::Enter
-                            && key != KeyCode::Backspace
-                            && key != KeyCode::Escape
-                        {
-                            state.handle_char_input(c);
+                if let Some((kind, typed)) = state.command_line()
+                    && matches!(key, KeyCode::Up | KeyCode::Down | KeyCode::Enter)
+                {
+                    // `/` and `?` share a history, as in Vim; `:` has its own
+                    let history = if *kind == VimCommand::StartCommandLine {
+                        &mut ex_history
+                    } else {
+                        &mut search_history
+                    };
+                    // Up/Down recall earlier lines
+                    let recalled = match key {
+                        KeyCode::Up => history.older(typed),
+                        KeyCode::Down => history.newer(),
+                        _ => {
+                            history.push(typed);
+                            None
                         }
+                    };
+                    if let Some(line) = recalled {
+                        state.set_command_line(line);
+                    }
+                    handled = key != KeyCode::Enter;
+                }
+                if !handled && state.input_mode.takes_text() {
+                    state.handle_special_key(key);
+                    if let Some(c) = get_char_pressed()
+                        && key != KeyCode::Enter
+                        && key != KeyCode::Backspace
+                        && key != KeyCode::Escape
+                        && key != KeyCode::Tab
+                    {
+                        state.handle_char_input(c);
                     }
                     handled = true;
                 }
 
                 if !handled {
-                    if key == KeyCode::Escape {
+                    if key == KeyCode::Escape
+                        && matches!(state.input_mode, InputMode::OperatorPending)
+                    {
+                        // Escape cancels the operator rather than leaving the level
+                        input_handler.map_key(key, false);
+                        state.handle_command(VimCommand::Escape);
+                    } else if key == KeyCode::Escape && !input_handler.pending_keys().is_empty() {
+                        // Escape cancels a half-typed command before leaving the level
+                        input_handler.map_key(key, false);
+                    } else if key == KeyCode::Escape {
                         should_exit_to_menu = true;
                     } else if key == KeyCode::F1 {
                         state.toggle_help();
-                    } else if key == KeyCode::P
-                        && (is_key_down(KeyCode::LeftShift) || is_key_down(KeyCode::RightShift))
-                    {
+                    } else if key == KeyCode::F2 {
+                        state.practice_mode = !state.practice_mode;
+                    } else if key == KeyCode::F5 {
+                        line_numbers = line_numbers.next();
+                    } else if key == KeyCode::F4 {
+                        should_watch_replay = true;
+                    } else if key == KeyCode::F3 {
                         // Auto-play / Solve
-                        let solver = Solver::new(state.current_level.clone());
-                        if let Some(solution) = solver.solve() {
-                            state.start_auto_play(solution);
+                        let (rows, cols) = render::visible_cells(cell_size, line_numbers);
+                        let solver =
+                            Solver::new(state.current_level.clone()).with_screen(rows, cols);
+                        if let Ok(solution) = solver.solve() {
+                            // The solver searches with Vim's default options
+                            state.case_options = CaseOptions::default();
+                            state.start_auto_play(replay::paced(solution.commands, AUTO_PLAY_TICK));
                         }
                     } else if let GameStatus::LevelComplete = state.status {
                         if key == KeyCode::Enter {
@@ -135,74 +334,347 @@ async fn main() {
                     } else {
                         let shift =
                             is_key_down(KeyCode::LeftShift) || is_key_down(KeyCode::RightShift);
-                        let command = input_handler.map_key(key, shift);
+                        let ctrl = is_key_down(KeyCode::LeftControl)
+                            || is_key_down(KeyCode::RightControl);
+                        let command = if ctrl {
+                            input_handler.map_ctrl_key(key)
+                        } else {
+                            input_handler.map_key(key, shift)
+                        };
                         state.handle_command(command);
                     }
                 }
             }
+
+            if let Some(request) = state.ex_request.take() {
+                match request {
+                    ExCommand::Quit => should_exit_to_menu = true,
+                    ExCommand::Restart => should_restart = true,
+                    ExCommand::Set(option) => {
+                        let (number, relative) = line_numbers.options();
+                        match option {
+                            SetOption::Number(on) => {
+                                line_numbers = LineNumbers::from_options(on, relative);
+                            }
+                            SetOption::RelativeNumber(on) => {
+                                line_numbers = LineNumbers::from_options(number, on);
+                            }
+                            SetOption::IgnoreCase(on) => case_options.ignore_case = on,
+                            SetOption::SmartCase(on) => case_options.smart_case = on,
+                        }
+                    }
+                    ExCommand::Level(number) => {
+                        let error = |text: String| Some(Message::Error(text));
+                        if playing_daily {
+                            state.message = error("Not available in the daily challenge".into());
+                        } else if let Some((pack_idx, _)) = current_level {
+                            let pack = &packs[pack_idx];
+                            if number > pack.paths.len() {
+                                state.message = error("E16: Invalid range".into());
+                            } else if !progress.is_unlocked(&pack.campaign(), number - 1) {
+                                state.message = error(format!("Level {} is locked", number));
+                            } else {
+                                jump_to_level = Some(number - 1);
+                            }
+                        }
+                    }
+                    // The game runs these itself
+                    ExCommand::Goto(_) | ExCommand::NoHighlight | ExCommand::Help(_) => {}
+                }
+            }
+
+            if let Some(query) = state.word_search.take() {
+                search_history.push(&query);
+            }
+
+            if state.status == GameStatus::LevelComplete
+                && let Some(replay) = state.finished_replay.take()
+            {
+                if playing_daily && let Some(challenge) = &daily_challenge {
+                    // A day keeps its best run, and the replay of that run
+                    let result = DailyResult {
+                        keystrokes: replay.keystrokes,
+                        budget: challenge.budget(),
+                        time: replay.time,
+                        score: replay.score,
+                    };
+                    if daily_history.record(challenge.date, result) {
+                        if let Err(e) = daily_history.save() {
+                            eprintln!("Could not save daily history: {}", e);
+                        }
+                        if let Err(e) = replay.save() {
+                            eprintln!("Could not save replay: {}", e);
+                        }
+                    }
+                } else {
+                    progress.record(&replay.level_id, replay.score, replay.keystrokes, replay.time);
+                    if let Err(e) = progress.save() {
+                        eprintln!("Could not save progress: {}", e);
+                    }
+                    if let Err(e) = replay.save() {
+                        eprintln!("Could not save replay: {}", e);
+                    }
+                }
+            }
         }
 
         if should_exit_to_menu {
             game_state = None;
-            current_level_index = None;
+            current_level = None;
+            playing_daily = false;
         }
 
-        if should_load_next_level {
-            if let Some(idx) = current_level_index {
-                let next_idx = idx + 1;
-                if next_idx < levels.len() {
-                    let level = load_level_from_file(levels[next_idx]).await;
-                    let mut new_state = GameState::new(level);
-
-                    if solve_all_mode {
-                        let solver = Solver::new(new_state.current_level.clone());
-                        if let Some(solution) = solver.solve() {
-                            new_state.start_auto_play(solution);
+        // A finished daily challenge goes back to the calendar
+        if should_load_next_level && playing_daily {
+            game_state = None;
+            playing_daily = false;
+        }
+
+        if should_load_next_level
+            && let Some((pack_idx, idx)) = current_level
+        {
+            // Solve-all carries on into the next pack
+            let next = if idx + 1 < packs[pack_idx].paths.len() {
+                Some((pack_idx, idx + 1))
+            } else if solve_all_mode && pack_idx + 1 < packs.len() {
+                Some((pack_idx + 1, 0))
+            } else {
+                None
+            };
+            if let Some((next_pack, next_idx)) = next {
+                match load_level_from_file(&packs[next_pack].paths[next_idx]).await {
+                    Ok(level) => {
+                        let mut new_state = GameState::new(level);
+
+                        if solve_all_mode {
+                            let (rows, cols) = render::visible_cells(cell_size, line_numbers);
+                            let solver = Solver::new(new_state.current_level.clone())
+                                .with_screen(rows, cols);
+                            if let Ok(solution) = solver.solve() {
+                                new_state.start_auto_play(replay::paced(solution.commands, AUTO_PLAY_TICK));
+                            }
                         }
+
+                        game_state = Some(new_state);
+                        current_level = Some((next_pack, next_idx));
+                    }
+                    Err(failure) => {
+                        game_state = None;
+                        current_level = None;
+                        load_failure = Some(failure);
                     }
+                }
+            } else {
+                if solve_all_mode {
+                    break;
+                }
+                // No more levels, return to menu
+                game_state = None;
+                current_level = None;
+            }
+        }
+
+        // :restart, and :level N within the current pack
+        if should_restart {
+            if playing_daily && let Some(challenge) = &daily_challenge {
+                game_state = Some(GameState::new(challenge.level.clone()));
+                input_handler = InputHandler::new();
+            } else if let Some((_, idx)) = current_level {
+                jump_to_level = Some(idx);
+            }
+        }
+        if let Some(idx) = jump_to_level
+            && let Some((pack_idx, _)) = current_level
+        {
+            match load_level_from_file(&packs[pack_idx].paths[idx]).await {
+                Ok(level) => {
+                    game_state = Some(GameState::new(level));
+                    current_level = Some((pack_idx, idx));
+                    input_handler = InputHandler::new();
+                }
+                Err(failure) => {
+                    game_state = None;
+                    current_level = None;
+                    load_failure = Some(failure);
+                }
+            }
+        }
 
+        // Restart the level and play its saved replay back in real time
+        if should_watch_replay
+            && playing_daily
+            && let Some(challenge) = &daily_challenge
+        {
+            match Replay::load(&challenge.level) {
+                Ok(replay) => {
+                    let mut new_state = GameState::new(challenge.level.clone());
+                    new_state.case_options = replay.case_options;
+                    new_state.start_auto_play(replay.steps);
                     game_state = Some(new_state);
-                    current_level_index = Some(next_idx);
-                } else {
-                    if solve_all_mode {
-                        break;
+                    input_handler = InputHandler::new();
+                }
+                Err(e) => eprintln!("{}", e),
+            }
+        }
+        if should_watch_replay
+            && let Some((pack_idx, idx)) = current_level
+        {
+            match load_level_from_file(&packs[pack_idx].paths[idx]).await {
+                Ok(level) => match Replay::load(&level) {
+                    Ok(replay) => {
+                        let mut new_state = GameState::new(level);
+                        new_state.case_options = replay.case_options;
+                        new_state.start_auto_play(replay.steps);
+                        game_state = Some(new_state);
+                        input_handler = InputHandler::new();
                     }
-                    // No more levels, return to menu
+                    Err(e) => eprintln!("{}", e),
+                },
+                Err(failure) => {
                     game_state = None;
-                    current_level_index = None;
+                    current_level = None;
+                    load_failure = Some(failure);
                 }
             }
         }
 
         if let Some(ref state) = game_state {
             // GAME RENDER
-            render::draw_game(state, &resources, cell_size);
+            render::draw_game(state, &resources, cell_size, line_numbers);
+            render::draw_pending_keys(&input_handler.pending_keys());
+        } else if let Some(ref failure) = load_failure {
+            render::draw_load_errors(&failure.path, &failure.errors);
+            if is_key_pressed(KeyCode::Enter) || is_key_pressed(KeyCode::Escape) {
+                load_failure = None;
+            }
+        } else if let Some(month) = daily_month {
+            // DAILY CHALLENGE RENDER & INPUT
+            let today = Date::today();
+            if let Some(builder) = &mut daily_builder
+                && let Some(challenge) = builder.step(solver::FRAME_BUDGET)
+            {
+                daily_challenge = challenge;
+                daily_builder = None;
+            }
+            render::draw_daily(
+                today,
+                month,
+                &daily_history,
+                daily_challenge.as_ref(),
+                daily_builder.is_some(),
+            );
+            if !was_playing {
+                if is_key_pressed(KeyCode::Enter)
+                    && let Some(challenge) = &daily_challenge
+                {
+                    game_state = Some(GameState::new(challenge.level.clone()));
+                    input_handler = InputHandler::new();
+                    playing_daily = true;
+                } else if is_key_pressed(KeyCode::H) || is_key_pressed(KeyCode::Left) {
+                    daily_month = Some(month.add_months(-1));
+                } else if is_key_pressed(KeyCode::L) || is_key_pressed(KeyCode::Right) {
+                    daily_month = Some(month.add_months(1));
+                } else if is_key_pressed(KeyCode::Escape) {
+                    daily_month = None;
+                }
+            }
+        } else if packs.is_empty() {
+            draw_text("VIM GAME", 100.0, 100.0, 60.0, GREEN);
+            draw_text("No level packs could be loaded.", 100.0, 180.0, 30.0, RED);
         } else {
             // MENU RENDER & INPUT
             draw_text("VIM GAME", 100.0, 100.0, 60.0, GREEN);
             draw_text(
-                "Select a Level (j/k to scroll, Enter to select):",
+                "Select a Level (j/k to scroll, h/l to switch pack, Enter to select):",
                 100.0,
-                180.0,
+                160.0,
                 30.0,
                 WHITE,
             );
 
+            // Pack tabs
+            if (is_key_pressed(KeyCode::L) || is_key_pressed(KeyCode::Right))
+                && menu_pack + 1 < packs.len()
+            {
+                menu_pack += 1;
+                menu_selection = 0;
+                menu_scroll_y = 0.0;
+            }
+            if (is_key_pressed(KeyCode::H) || is_key_pressed(KeyCode::Left)) && menu_pack > 0 {
+                menu_pack -= 1;
+                menu_selection = 0;
+                menu_scroll_y = 0.0;
+            }
+            let mut tab_x = 100.0;
+            for (i, pack) in packs.iter().enumerate() {
+                let color = if i == menu_pack { GREEN } else { GRAY };
+                let dims = measure_text(&pack.manifest.name, None, 25, 1.0);
+                draw_text(&pack.manifest.name, tab_x, 200.0, 25.0, color);
+                tab_x += dims.width + 30.0;
+            }
+            let pack = &packs[menu_pack];
+            if !pack.manifest.author.is_empty() {
+                draw_text(
+                    &format!("by {}", pack.manifest.author),
+                    tab_x,
+                    200.0,
+                    20.0,
+                    DARKGRAY,
+                );
+            }
+
+            let campaign = pack.campaign();
+
             // Menu Input
-            if is_key_pressed(KeyCode::J) || is_key_pressed(KeyCode::Down) {
-                if menu_selection < levels.len() - 1 {
-                    menu_selection += 1;
-                }
+            if (is_key_pressed(KeyCode::J) || is_key_pressed(KeyCode::Down))
+                && menu_selection < pack.paths.len() - 1
+            {
+                menu_selection += 1;
             }
             if is_key_pressed(KeyCode::K) || is_key_pressed(KeyCode::Up) {
-                if menu_selection > 0 {
-                    menu_selection -= 1;
+                menu_selection = menu_selection.saturating_sub(1);
+            }
+            if (is_key_pressed(KeyCode::Enter) || is_key_pressed(KeyCode::Space))
+                && progress.is_unlocked(&campaign, menu_selection)
+            {
+                match load_level_from_file(&pack.paths[menu_selection]).await {
+                    Ok(level) => {
+                        game_state = Some(GameState::new(level));
+                        current_level = Some((menu_pack, menu_selection));
+                    }
+                    Err(failure) => load_failure = Some(failure),
+                }
+            }
+
+            // Daily challenge, made from the built-in pack
+            if is_key_pressed(KeyCode::D) {
+                let today = Date::today();
+                if daily_challenge.as_ref().is_none_or(|c| c.date != today)
+                    && daily_builder.as_ref().is_none_or(|b| b.date != today)
+                {
+                    let levels: Vec<Level> = packs
+                        .iter()
+                        .filter(|pack| pack.dir == pack::BUILTIN_PACK_DIR)
+                        .flat_map(|pack| pack.catalog.iter().flatten().cloned())
+                        .collect();
+                    daily_challenge = None;
+                    daily_builder = Some(ChallengeBuilder::new(today, &levels));
                 }
+                daily_month = Some(today.add_months(0));
             }
-            if is_key_pressed(KeyCode::Enter) || is_key_pressed(KeyCode::Space) {
-                let level = load_level_from_file(levels[menu_selection]).await;
-                game_state = Some(GameState::new(level));
-                current_level_index = Some(menu_selection);
+
+            // Editor: e edits the selected level, n starts a new one in this pack
+            if is_key_pressed(KeyCode::E) {
+                match load_level_from_file(&pack.paths[menu_selection]).await {
+                    Ok(level) => editor = Some(Editor::open(level, &pack.paths[menu_selection])),
+                    Err(failure) => load_failure = Some(failure),
+                }
+            } else if is_key_pressed(KeyCode::N) {
+                editor = Some(Editor::create(&pack.dir));
+            }
+            if editor.is_some() {
+                // Drop characters typed in the menu so they don't reach the editor
+                while get_char_pressed().is_some() {}
             }
 
             // Scroll Logic
@@ -238,21 +710,24 @@ async fn main() {
             );
 
             // Draw List with Clipping
-            for (i, level_path) in levels.iter().enumerate() {
+            for (i, level_path) in pack.paths.iter().enumerate() {
                 let local_y = i as f32 * item_height - menu_scroll_y;
                 // Add padding so text baseline is comfortably inside the row
                 let screen_y = list_start_y + local_y + 30.0;
 
                 // Only draw if fully visible within the list area
                 // Text is drawn upwards from baseline (screen_y), approx 30px height
+                let unlocked = progress.is_unlocked(&campaign, i);
                 if screen_y - 30.0 >= list_start_y && screen_y <= list_start_y + list_height {
-                    let label = format!(
-                        "{}. {}",
-                        i + 1,
-                        level_path
-                            .replace("assets/levels/", "")
-                            .replace(".json", "")
-                    );
+                    let name = match &pack.catalog[i] {
+                        Some(level) => level.name.clone(),
+                        None => level_path
+                            .rsplit('/')
+                            .next()
+                            .unwrap_or(level_path)
+                            .replace(".json", ""),
+                    };
+                    let label = format!("{}. {}", i + 1, name);
 
                     let color = if i == menu_selection {
                         // Draw cursor/highlight
@@ -262,7 +737,24 @@ async fn main() {
                         LIGHTGRAY
                     };
 
-                    draw_text(&label, 120.0, screen_y, 30.0, color);
+                    if !unlocked {
+                        draw_text(&label, 120.0, screen_y, 30.0, DARKGRAY);
+                        draw_text("(locked)", 560.0, screen_y, 25.0, DARKGRAY);
+                    } else {
+                        draw_text(&label, 120.0, screen_y, 30.0, color);
+                        if let Some(level) = &pack.catalog[i]
+                            && let Some(record) = progress.get(&level.id)
+                        {
+                            let stars = progress.stars(level) as usize;
+                            let rating = format!("{}{}", "*".repeat(stars), "-".repeat(3 - stars));
+                            draw_text(&rating, 560.0, screen_y, 30.0, GOLD);
+                            let bests = format!(
+                                "best {} keys, {:.1}s, {} pts",
+                                record.best_keystrokes, record.best_time, record.best_score
+                            );
+                            draw_text(&bests, 620.0, screen_y, 22.0, GRAY);
+                        }
+                    }
                 }
 
                 // Simple key press detection for 1-9 and 0
@@ -280,16 +772,20 @@ async fn main() {
                     _ => KeyCode::Unknown,
                 };
 
-                if is_key_pressed(key_to_check) {
-                    let level = load_level_from_file(level_path).await;
-                    game_state = Some(GameState::new(level));
-                    current_level_index = Some(i);
+                if is_key_pressed(key_to_check) && unlocked {
+                    match load_level_from_file(level_path).await {
+                        Ok(level) => {
+                            game_state = Some(GameState::new(level));
+                            current_level = Some((menu_pack, i));
+                        }
+                        Err(failure) => load_failure = Some(failure),
+                    }
                     menu_selection = i; // Update selection to match
                 }
             }
 
             draw_text(
-                "Press 1-9, 0 to select",
+                "Press 1-9, 0 to select | d for the daily challenge | e to edit the selected level, n for a new one",
                 100.0,
                 screen_height() - 50.0,
                 20.0,
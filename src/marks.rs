@@ -0,0 +1,133 @@
+//! Named marks (`ma`, `'a`, `` `a ``) and the jumplist that `Ctrl-o` and
+//! `Ctrl-i` walk through.
+
+use crate::level::Position;
+use std::collections::BTreeMap;
+
+/// Entries kept in the jumplist, as in Vim.
+const JUMPLIST_SIZE: usize = 100;
+
+/// Marks set with `m`, by letter. The level is a single buffer, so
+/// uppercase marks work just like lowercase ones.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct Marks(BTreeMap<char, Position>);
+
+impl Marks {
+    pub fn is_valid_name(name: char) -> bool {
+        name.is_ascii_alphabetic()
+    }
+
+    /// Sets mark `name`. Returns false if `name` can't be a mark.
+    pub fn set(&mut self, name: char, pos: Position) -> bool {
+        if !Self::is_valid_name(name) {
+            return false;
+        }
+        self.0.insert(name, pos);
+        true
+    }
+
+    pub fn get(&self, name: char) -> Option<Position> {
+        self.0.get(&name).copied()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (char, Position)> + '_ {
+        self.0.iter().map(|(&name, &pos)| (name, pos))
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+}
+
+/// Where jumps (searches, `G`, marks, ...) left from, oldest first.
+/// `index` is the entry `Ctrl-o`/`Ctrl-i` last went to, or the length of
+/// the list when not browsing it.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct JumpList {
+    entries: Vec<Position>,
+    index: usize,
+}
+
+impl JumpList {
+    /// Records the place a jump left. As in Vim, an older entry for the
+    /// same place is dropped, and browsing starts over from the newest.
+    pub fn record(&mut self, from: Position) {
+        self.entries.retain(|&pos| pos != from);
+        self.entries.push(from);
+        if self.entries.len() > JUMPLIST_SIZE {
+            self.entries.remove(0);
+        }
+        self.index = self.entries.len();
+    }
+
+    /// `Ctrl-o`: `count` entries back. Leaving the newest end records
+    /// `here`, so `Ctrl-i` can come back to it.
+    pub fn older(&mut self, count: usize, here: Position) -> Option<Position> {
+        let mut list = self.clone();
+        if list.index == list.entries.len() {
+            list.record(here);
+            list.index -= 1;
+        }
+        list.index = list.index.checked_sub(count.max(1))?;
+        *self = list;
+        Some(self.entries[self.index])
+    }
+
+    /// `Ctrl-i`: `count` entries forward, up to the newest.
+    pub fn newer(&mut self, count: usize) -> Option<Position> {
+        let index = self.index + count.max(1);
+        let pos = *self.entries.get(index)?;
+        self.index = index;
+        Some(pos)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(x: usize) -> Position {
+        Position { x, y: 0 }
+    }
+
+    #[test]
+    fn test_marks() {
+        let mut marks = Marks::default();
+        assert!(marks.set('a', at(1)));
+        assert!(marks.set('a', at(2)));
+        assert!(marks.set('Z', at(3)));
+        assert!(!marks.set('1', at(4)));
+        assert_eq!(marks.get('a'), Some(at(2)));
+        assert_eq!(marks.get('b'), None);
+        assert_eq!(marks.len(), 2);
+    }
+
+    #[test]
+    fn test_jumplist() {
+        let mut jumps = JumpList::default();
+        assert_eq!(jumps.older(1, at(0)), None);
+        assert_eq!(jumps.len(), 0, "a failed Ctrl-o records nothing");
+
+        // Jumped 1 -> 2 -> 3 -> 4
+        jumps.record(at(1));
+        jumps.record(at(2));
+        jumps.record(at(3));
+        assert_eq!(jumps.older(1, at(4)), Some(at(3)));
+        assert_eq!(jumps.older(2, at(3)), Some(at(1)));
+        assert_eq!(jumps.older(1, at(1)), None);
+        assert_eq!(jumps.newer(1), Some(at(2)));
+        assert_eq!(jumps.newer(2), Some(at(4)));
+        assert_eq!(jumps.newer(1), None);
+
+        // A new jump from the middle goes to the end, without duplicates
+        jumps.older(3, at(4));
+        jumps.record(at(2));
+        assert_eq!(jumps.entries, vec![at(1), at(3), at(4), at(2)]);
+        assert_eq!(jumps.newer(1), None);
+        assert_eq!(jumps.older(1, at(5)), Some(at(2)));
+    }
+}
@@ -0,0 +1,171 @@
+//! Level packs: a directory of levels with a `pack.json` manifest giving
+//! the pack's name, author, level order and the features it relies on.
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+pub const MANIFEST_FILE: &str = "pack.json";
+
+/// The pack that ships with the game.
+pub const BUILTIN_PACK_DIR: &str = "assets/levels";
+
+/// Features a pack may list in `requires`. A pack asking for anything else
+/// was made for a newer build, and is refused rather than half-working.
+pub const SUPPORTED_FEATURES: &[&str] = &[
+    "counts",
+    "big_words",
+    "operators",
+    "text_goals",
+    "ex_commands",
+    "patterns",
+    "marks",
+    "brackets",
+    "line_motions",
+    "word_search",
+    "sentences",
+    "sections",
+];
+
+/// A problem with a pack manifest.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PackError {
+    /// The manifest file couldn't be read.
+    Unreadable(String),
+    /// The manifest isn't valid JSON, or is missing a field.
+    Json(String),
+    /// A manifest with no levels.
+    EmptyPack,
+    /// A level file listed twice.
+    DuplicateLevel(String),
+    /// A feature this build doesn't have.
+    UnsupportedFeature(String),
+}
+
+impl fmt::Display for PackError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PackError::Unreadable(e) => write!(f, "could not read pack: {}", e),
+            PackError::Json(e) => write!(f, "{}", e),
+            PackError::EmptyPack => write!(f, "pack lists no levels"),
+            PackError::DuplicateLevel(file) => write!(f, "pack lists '{}' twice", file),
+            PackError::UnsupportedFeature(feature) => write!(
+                f,
+                "pack requires '{}', which this version of the game doesn't support",
+                feature
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PackManifest {
+    pub name: String,
+    #[serde(default)]
+    pub author: String,
+    /// Level files, relative to the pack directory, in campaign order.
+    pub levels: Vec<String>,
+    #[serde(default)]
+    pub requires: Vec<String>,
+}
+
+impl PackManifest {
+    /// Parses a manifest and validates it, collecting every problem found.
+    pub fn from_json(json: &str) -> Result<PackManifest, Vec<PackError>> {
+        let manifest: PackManifest =
+            serde_json::from_str(json).map_err(|e| vec![PackError::Json(e.to_string())])?;
+        let errors = manifest.validate();
+        if errors.is_empty() {
+            Ok(manifest)
+        } else {
+            Err(errors)
+        }
+    }
+
+    pub fn validate(&self) -> Vec<PackError> {
+        let mut errors = Vec::new();
+        if self.levels.is_empty() {
+            errors.push(PackError::EmptyPack);
+        }
+        for (i, file) in self.levels.iter().enumerate() {
+            if self.levels[..i].contains(file) {
+                errors.push(PackError::DuplicateLevel(file.clone()));
+            }
+        }
+        for feature in &self.requires {
+            if !SUPPORTED_FEATURES.contains(&feature.as_str()) {
+                errors.push(PackError::UnsupportedFeature(feature.clone()));
+            }
+        }
+        errors
+    }
+
+    /// Paths of the pack's levels, in order.
+    pub fn level_paths(&self, dir: &str) -> Vec<String> {
+        self.levels
+            .iter()
+            .map(|file| format!("{}/{}", dir.trim_end_matches('/'), file))
+            .collect()
+    }
+}
+
+/// The packs to offer: the built-in one, then each `--pack <dir>` in order.
+pub fn pack_dirs(args: &[String]) -> Vec<String> {
+    let mut dirs = vec![BUILTIN_PACK_DIR.to_string()];
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if arg == "--pack"
+            && let Some(dir) = args.next()
+        {
+            dirs.push(dir.clone());
+        }
+    }
+    dirs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_manifest_validation() {
+        let manifest = PackManifest::from_json(
+            r#"{"name": "Training", "levels": ["a.json", "b.json"], "requires": ["counts"]}"#,
+        )
+        .unwrap();
+        assert_eq!(manifest.author, "");
+        assert_eq!(
+            manifest.level_paths("packs/training/"),
+            vec!["packs/training/a.json", "packs/training/b.json"]
+        );
+
+        let errors = PackManifest::from_json(
+            r#"{"name": "Future", "levels": ["a.json", "a.json"], "requires": ["time_travel"]}"#,
+        )
+        .unwrap_err();
+        assert_eq!(
+            errors,
+            vec![
+                PackError::DuplicateLevel("a.json".to_string()),
+                PackError::UnsupportedFeature("time_travel".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_builtin_pack_features() {
+        let path = format!("{}/{}", BUILTIN_PACK_DIR, MANIFEST_FILE);
+        let json = std::fs::read_to_string(path).unwrap();
+        let manifest = PackManifest::from_json(&json).unwrap();
+        // The built-in pack uses every feature there is
+        assert_eq!(manifest.requires, SUPPORTED_FEATURES);
+    }
+
+    #[test]
+    fn test_pack_dirs() {
+        let args: Vec<String> = ["vimgame", "--pack", "team", "--solve-all", "--pack", "more"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        assert_eq!(pack_dirs(&args), vec![BUILTIN_PACK_DIR, "team", "more"]);
+    }
+}
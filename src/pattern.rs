@@ -0,0 +1,319 @@
+//! Search patterns for `/` and `?`: a small Vim-flavoured regex engine.
+//! Supports `.`, `*`, `[...]` classes, `^` and `$`, `\<` and `\>`, and
+//! `\c`/`\C`. A match never spans rows.
+
+use serde::{Deserialize, Serialize};
+
+/// How letter case is compared, from Vim's `ignorecase` and `smartcase`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CaseOptions {
+    pub ignore_case: bool,
+    /// With `ignore_case`, a pattern with an uppercase letter matches case.
+    pub smart_case: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Atom {
+    Char(char),
+    Any,
+    Class { negated: bool, items: Vec<(char, char)> },
+    LineStart,
+    LineEnd,
+    WordStart,
+    WordEnd,
+}
+
+impl Atom {
+    fn is_zero_width(&self) -> bool {
+        matches!(
+            self,
+            Atom::LineStart | Atom::LineEnd | Atom::WordStart | Atom::WordEnd
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Piece {
+    atom: Atom,
+    /// Followed by `*`: any number of times, as many as possible.
+    repeat: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pattern {
+    pieces: Vec<Piece>,
+    ignore_case: bool,
+}
+
+impl Pattern {
+    /// Parses a pattern. Anything that isn't valid syntax (a `[` without
+    /// its `]`, a `*` with nothing to repeat) is taken literally.
+    pub fn new(text: &str, case: CaseOptions) -> Self {
+        let chars: Vec<char> = text.chars().collect();
+        let mut pieces: Vec<Piece> = Vec::new();
+        let mut force_case: Option<bool> = None;
+        let mut has_upper = false;
+        let mut i = 0;
+        while i < chars.len() {
+            let c = chars[i];
+            i += 1;
+            let atom = match c {
+                '\\' if i < chars.len() => {
+                    i += 1;
+                    match chars[i - 1] {
+                        '<' => Atom::WordStart,
+                        '>' => Atom::WordEnd,
+                        'c' => {
+                            force_case = Some(true);
+                            continue;
+                        }
+                        'C' => {
+                            force_case.get_or_insert(false);
+                            continue;
+                        }
+                        escaped => Atom::Char(escaped),
+                    }
+                }
+                '.' => Atom::Any,
+                '^' if pieces.is_empty() => Atom::LineStart,
+                '$' if i == chars.len() => Atom::LineEnd,
+                '*' => match pieces.last_mut() {
+                    Some(last) if !last.repeat && !last.atom.is_zero_width() => {
+                        last.repeat = true;
+                        continue;
+                    }
+                    _ => Atom::Char('*'),
+                },
+                '[' => match parse_class(&chars[i..]) {
+                    Some((atom, used)) => {
+                        i += used;
+                        atom
+                    }
+                    None => Atom::Char('['),
+                },
+                c => Atom::Char(c),
+            };
+            if let Atom::Char(c) = atom {
+                has_upper |= c.is_uppercase();
+            }
+            pieces.push(Piece {
+                atom,
+                repeat: false,
+            });
+        }
+        let ignore_case = force_case
+            .unwrap_or(case.ignore_case && !(case.smart_case && has_upper));
+        Self {
+            pieces,
+            ignore_case,
+        }
+    }
+
+    /// Length in cells of a match starting at `row[x]`, if there is one.
+    /// The match may be empty (e.g. `a*`), even at `x == row.len()`.
+    pub fn match_at(&self, row: &[char], x: usize) -> Option<usize> {
+        if x > row.len() {
+            return None;
+        }
+        self.match_here(&self.pieces, row, x).map(|end| end - x)
+    }
+
+    // Backtracking: returns where a match of `pieces` from `pos` ends
+    fn match_here(&self, pieces: &[Piece], row: &[char], pos: usize) -> Option<usize> {
+        let Some((piece, rest)) = pieces.split_first() else {
+            return Some(pos);
+        };
+        if piece.repeat {
+            let mut end = pos;
+            while end < row.len() && self.matches_char(&piece.atom, row[end]) {
+                end += 1;
+            }
+            return (pos..=end)
+                .rev()
+                .find_map(|from| self.match_here(rest, row, from));
+        }
+        let is_word = |i: usize| row.get(i).is_some_and(|&c| is_word_char(c));
+        let before_is_word = pos > 0 && is_word(pos - 1);
+        match piece.atom {
+            Atom::LineStart if pos == 0 => self.match_here(rest, row, pos),
+            Atom::LineEnd if pos == row.len() => self.match_here(rest, row, pos),
+            Atom::WordStart if is_word(pos) && !before_is_word => self.match_here(rest, row, pos),
+            Atom::WordEnd if before_is_word && !is_word(pos) => self.match_here(rest, row, pos),
+            ref atom if !atom.is_zero_width() => {
+                let c = *row.get(pos)?;
+                if self.matches_char(atom, c) {
+                    self.match_here(rest, row, pos + 1)
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        }
+    }
+
+    fn matches_char(&self, atom: &Atom, c: char) -> bool {
+        let same = |a: char, b: char| {
+            a == b || (self.ignore_case && a.to_lowercase().eq(b.to_lowercase()))
+        };
+        match atom {
+            Atom::Char(want) => same(*want, c),
+            Atom::Any => true,
+            Atom::Class { negated, items } => {
+                let folded = [c, c.to_ascii_lowercase(), c.to_ascii_uppercase()];
+                let candidates = if self.ignore_case { &folded[..] } else { &folded[..1] };
+                let found = items.iter().any(|&(low, high)| {
+                    candidates.iter().any(|c| (low..=high).contains(c))
+                });
+                found != *negated
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Parses the inside of `[...]`, starting after the `[`. Returns the class
+/// and how many chars it used, or None if there is no closing `]`.
+fn parse_class(chars: &[char]) -> Option<(Atom, usize)> {
+    let mut i = 0;
+    let negated = chars.first() == Some(&'^');
+    if negated {
+        i += 1;
+    }
+    let mut items = Vec::new();
+    // A `]` right after the `[` (or `[^`) is a literal one
+    if chars.get(i) == Some(&']') {
+        items.push((']', ']'));
+        i += 1;
+    }
+    loop {
+        let c = *chars.get(i)?;
+        i += 1;
+        match c {
+            ']' => break,
+            '\\' if i < chars.len() => {
+                items.push((chars[i], chars[i]));
+                i += 1;
+            }
+            c if chars.get(i) == Some(&'-') && chars.get(i + 1).is_some_and(|&e| e != ']') => {
+                items.push((c, chars[i + 1]));
+                i += 2;
+            }
+            c => items.push((c, c)),
+        }
+    }
+    Some((Atom::Class { negated, items }, i))
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// `text` as a pattern that matches it literally, with as few backslashes
+/// as Vim needs.
+pub fn escape(text: &str) -> String {
+    let len = text.chars().count();
+    let mut escaped = String::new();
+    for (i, c) in text.chars().enumerate() {
+        let magic = match c {
+            '\\' | '.' | '*' | '[' => true,
+            '^' => i == 0,
+            '$' => i == len - 1,
+            _ => false,
+        };
+        if magic {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Start and length of every match in `row`.
+    fn find(pattern: &str, row: &str) -> Vec<(usize, usize)> {
+        find_with(pattern, row, CaseOptions::default())
+    }
+
+    fn find_with(pattern: &str, row: &str, case: CaseOptions) -> Vec<(usize, usize)> {
+        let pattern = Pattern::new(pattern, case);
+        let row: Vec<char> = row.chars().collect();
+        (0..=row.len())
+            .filter_map(|x| pattern.match_at(&row, x).map(|len| (x, len)))
+            .collect()
+    }
+
+    #[test]
+    fn test_literals_and_wildcards() {
+        assert_eq!(find("ab", "ab.abc"), vec![(0, 2), (3, 2)]);
+        assert_eq!(find("a.c", "abc.axc"), vec![(0, 3), (4, 3)]);
+        assert_eq!(find("ab*c", "ac.abbbc"), vec![(0, 2), (3, 5)]);
+        // `.*` is greedy, but gives back what the rest needs
+        assert_eq!(find("a.*c", "abcbc"), vec![(0, 5)]);
+        // Nothing to repeat: a literal star
+        assert_eq!(find("*a", "b*a"), vec![(1, 2)]);
+        assert_eq!(find("a\\.b", "axb.a.b"), vec![(4, 3)]);
+    }
+
+    #[test]
+    fn test_anchors_and_words() {
+        assert_eq!(find("^ab", "abab"), vec![(0, 2)]);
+        assert_eq!(find("ab$", "abab"), vec![(2, 2)]);
+        assert_eq!(find("a^", "a^"), vec![(0, 2)]);
+        assert_eq!(find("\\<cat", "cat.concat"), vec![(0, 3)]);
+        assert_eq!(find("cat\\>", "cats.concat"), vec![(8, 3)]);
+        assert_eq!(find("\\<in\\>", "tin.in.inn"), vec![(4, 2)]);
+    }
+
+    #[test]
+    fn test_empty_match_at_line_end() {
+        assert_eq!(find("$", "ab"), vec![(2, 0)]);
+        assert_eq!(find("b*$", "ab"), vec![(1, 1), (2, 0)]);
+        assert_eq!(find("^$", ""), vec![(0, 0)]);
+        assert_eq!(find("a", "ab"), vec![(0, 1)]);
+    }
+
+    #[test]
+    fn test_classes() {
+        assert_eq!(find("[bc]a", "aa.ba.ca"), vec![(3, 2), (6, 2)]);
+        assert_eq!(find("[a-c]x", "ax.dx.cx"), vec![(0, 2), (6, 2)]);
+        assert_eq!(find("[^a.]", "a.b"), vec![(2, 1)]);
+        assert_eq!(find("x[]]", "x]"), vec![(0, 2)]);
+        // No closing bracket: a literal one
+        assert_eq!(find("[a", "a[a"), vec![(1, 2)]);
+    }
+
+    #[test]
+    fn test_case() {
+        let ignore = CaseOptions {
+            ignore_case: true,
+            smart_case: false,
+        };
+        let smart = CaseOptions {
+            ignore_case: true,
+            smart_case: true,
+        };
+        assert_eq!(find("ab", "AB.ab"), vec![(3, 2)]);
+        assert_eq!(find("ab\\c", "AB.ab"), vec![(0, 2), (3, 2)]);
+        assert_eq!(find_with("ab", "AB.ab", ignore), vec![(0, 2), (3, 2)]);
+        assert_eq!(find_with("\\Cab", "AB.ab", ignore), vec![(3, 2)]);
+        assert_eq!(find_with("[a-b]", "B", ignore), vec![(0, 1)]);
+        // smartcase: an uppercase letter makes the pattern match case
+        assert_eq!(find_with("ab", "AB.ab", smart), vec![(0, 2), (3, 2)]);
+        assert_eq!(find_with("Ab", "AB.Ab", smart), vec![(3, 2)]);
+    }
+
+    #[test]
+    fn test_escape() {
+        for text in ["a.b*", "^$", "[x]", "\\~", "$a^"] {
+            let pattern = Pattern::new(&escape(text), CaseOptions::default());
+            let row: Vec<char> = format!("..{}", text).chars().collect();
+            assert_eq!(pattern.match_at(&row, 2), Some(text.chars().count()), "{}", text);
+            assert_eq!(pattern.match_at(&row, 1), None);
+        }
+        assert_eq!(escape("$a^"), "$a^");
+        assert_eq!(escape("^a$"), "\\^a\\$");
+    }
+}
@@ -0,0 +1,134 @@
+//! Save data: each level's best run, and the campaign rule deciding which
+//! levels are open.
+
+use crate::level::Level;
+use crate::storage;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+const STORAGE_KEY: &str = "progress";
+
+/// Bests for a completed level. Each best can come from a different run.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LevelRecord {
+    pub completions: u32,
+    pub best_score: i32,
+    pub best_keystrokes: u32,
+    pub best_time: f32,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Progress {
+    /// Keyed by level id; only completed levels have a record.
+    #[serde(default)]
+    levels: BTreeMap<String, LevelRecord>,
+}
+
+impl Progress {
+    /// Loads saved progress. Missing or unreadable save data starts afresh.
+    pub fn load() -> Self {
+        let Some(json) = storage::load(STORAGE_KEY) else {
+            return Self::default();
+        };
+        serde_json::from_str(&json).unwrap_or_else(|e| {
+            eprintln!("Ignoring unreadable save data: {}", e);
+            Self::default()
+        })
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        let json = serde_json::to_string_pretty(self).map_err(|e| e.to_string())?;
+        storage::save(STORAGE_KEY, &json)
+    }
+
+    pub fn get(&self, level_id: &str) -> Option<&LevelRecord> {
+        self.levels.get(level_id)
+    }
+
+    /// Records a completed run, keeping the best of each measure.
+    /// Returns true if the run set any new best.
+    pub fn record(&mut self, level_id: &str, score: i32, keystrokes: u32, time: f32) -> bool {
+        let Some(record) = self.levels.get_mut(level_id) else {
+            self.levels.insert(
+                level_id.to_string(),
+                LevelRecord {
+                    completions: 1,
+                    best_score: score,
+                    best_keystrokes: keystrokes,
+                    best_time: time,
+                },
+            );
+            return true;
+        };
+        let improved =
+            score > record.best_score || keystrokes < record.best_keystrokes || time < record.best_time;
+        record.completions += 1;
+        record.best_score = record.best_score.max(score);
+        record.best_keystrokes = record.best_keystrokes.min(keystrokes);
+        record.best_time = record.best_time.min(time);
+        improved
+    }
+
+    /// One star for finishing, one for matching par keystrokes and one for
+    /// matching par time.
+    pub fn stars(&self, level: &Level) -> u8 {
+        let Some(record) = self.get(&level.id) else {
+            return 0;
+        };
+        1 + u8::from(record.best_keystrokes <= level.par_keystrokes)
+            + u8::from(record.best_time <= level.par_time)
+    }
+
+    /// Campaign rule: the first level is open, and finishing a level opens
+    /// the next. `campaign` holds level ids in order, with None for a level
+    /// that failed to load; broken levels are skipped over.
+    pub fn is_unlocked(&self, campaign: &[Option<&str>], index: usize) -> bool {
+        let completed = |id: &str| self.levels.contains_key(id);
+        let previous = campaign[..index].iter().rev().flatten().next();
+        campaign[index].is_some_and(completed) || previous.is_none_or(|id| completed(id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_keeps_bests() {
+        let mut progress = Progress::default();
+        assert!(progress.record("a", 900, 12, 8.0));
+        assert!(!progress.record("a", 800, 14, 9.0));
+        assert!(progress.record("a", 850, 10, 9.5));
+        assert_eq!(
+            progress.get("a"),
+            Some(&LevelRecord {
+                completions: 3,
+                best_score: 900,
+                best_keystrokes: 10,
+                best_time: 8.0,
+            })
+        );
+
+        let json = serde_json::to_string(&progress).unwrap();
+        assert_eq!(serde_json::from_str::<Progress>(&json).unwrap(), progress);
+    }
+
+    #[test]
+    fn test_unlocking() {
+        let mut progress = Progress::default();
+        let campaign = [Some("a"), Some("b"), None, Some("d"), Some("e")];
+        assert!(progress.is_unlocked(&campaign, 0));
+        assert!(!progress.is_unlocked(&campaign, 1));
+
+        progress.record("a", 1000, 1, 1.0);
+        assert!(progress.is_unlocked(&campaign, 1));
+        assert!(!progress.is_unlocked(&campaign, 2));
+
+        // A level that failed to load is skipped over
+        assert!(!progress.is_unlocked(&campaign, 3));
+        progress.record("b", 1000, 1, 1.0);
+        assert!(progress.is_unlocked(&campaign, 2));
+        assert!(progress.is_unlocked(&campaign, 3));
+        assert!(!progress.is_unlocked(&campaign, 4));
+    }
+}
@@ -1,14 +1,25 @@
-use crate::game::{GameState, GameStatus, InputMode};
+use crate::daily::{DailyChallenge, DailyHistory, Date};
+use crate::editor::{Analysis, Editor, EditorMode};
+use crate::game::{GameState, GameStatus, InputMode, Message};
 use crate::input::VimCommand;
+use crate::level::{Goal, KeyPolicy, Level, Position};
+use crate::solver::SolveError;
+use crate::viewport::{LineNumbers, Viewport};
 use macroquad::prelude::*;
 
+/// Height of the bar above the level, and of the status lines below it.
+const TOP_BAR: f32 = 50.0;
+const BOTTOM_BAR: f32 = 100.0;
+
+/// Width of the line number gutter, when it is shown.
+const GUTTER_WIDTH: f32 = 44.0;
+
 pub struct Resources {
     pub player_texture: Option<Texture2D>,
     pub wall_texture: Option<Texture2D>,
     pub floor_texture: Option<Texture2D>,
     pub water_texture: Option<Texture2D>,
     pub goal_texture: Option<Texture2D>,
-    pub font: Option<Font>,
 }
 
 impl Resources {
@@ -24,23 +35,74 @@ impl Resources {
             floor_texture: load_texture_safe("assets/textures/floor.png").await,
             water_texture: load_texture_safe("assets/textures/water.png").await,
             goal_texture: load_texture_safe("assets/textures/goal.png").await,
-            font: None,
         }
     }
 }
 
-pub fn draw_game(state: &GameState, resources: &Resources, cell_size: f32) {
-    let level = &state.current_level;
+fn gutter_width(numbers: LineNumbers) -> f32 {
+    if numbers == LineNumbers::Off {
+        0.0
+    } else {
+        GUTTER_WIDTH
+    }
+}
+
+/// Rows and columns of cells that fit between the top bar and the status
+/// lines, right of the gutter.
+pub fn visible_cells(cell_size: f32, numbers: LineNumbers) -> (usize, usize) {
+    let rows = (screen_height() - TOP_BAR - BOTTOM_BAR) / cell_size;
+    let cols = (screen_width() - gutter_width(numbers)) / cell_size;
+    (rows.max(1.0) as usize, cols.max(1.0) as usize)
+}
+
+/// Where the cells in `view` are drawn.
+struct Grid<'a> {
+    view: &'a Viewport,
+    cell_size: f32,
+    /// Left edge of the first visible column.
+    left: f32,
+}
+
+impl Grid<'_> {
+    fn origin(&self, pos: Position) -> (f32, f32) {
+        (
+            self.left + (pos.x - self.view.left) as f32 * self.cell_size,
+            (pos.y - self.view.top) as f32 * self.cell_size + TOP_BAR,
+        )
+    }
+}
+
+pub fn draw_game(state: &GameState, resources: &Resources, cell_size: f32, numbers: LineNumbers) {
+    let grid = Grid {
+        view: &state.viewport,
+        cell_size,
+        left: gutter_width(numbers),
+    };
+    draw_layout(&state.current_level, &grid, resources, state.time_elapsed);
+    draw_goal_diff(&state.current_level, &grid);
+    draw_search(state, &grid);
+    draw_marks(state, &grid);
+    draw_gutter(&state.current_level, &grid, numbers, state.player_pos.y);
 
-    // Draw Level
-    for (y, row) in level.layout.iter().enumerate() {
-        for (x, char) in row.chars().enumerate() {
-            let screen_x = x as f32 * cell_size;
-            let screen_y = y as f32 * cell_size + 50.0; // Offset for UI
+    // Draw Player
+    let (player_x, player_y) = grid.origin(state.player_pos);
+    draw_player(player_x, player_y, cell_size, resources, state.time_elapsed);
+
+    // Draw UI
+    draw_ui(state);
+}
+
+/// Draws the level's tiles on screen below the top UI bar.
+fn draw_layout(level: &Level, grid: &Grid, resources: &Resources, time: f32) {
+    let (view, cell_size) = (grid.view, grid.cell_size);
+    let rows = level.layout.iter().enumerate().skip(view.top);
+    for (y, row) in rows.take(view.rows) {
+        for (x, char) in row.chars().enumerate().skip(view.left).take(view.cols) {
+            let (screen_x, screen_y) = grid.origin(Position { x, y });
 
             match char {
                 '#' => draw_wall(screen_x, screen_y, cell_size, resources),
-                '~' => draw_water(screen_x, screen_y, cell_size, resources, state.time_elapsed),
+                '~' => draw_water(screen_x, screen_y, cell_size, resources, time),
                 '.' | 'S' | 'E' => draw_floor(screen_x, screen_y, cell_size, resources),
                 _ => draw_floor(screen_x, screen_y, cell_size, resources), // Default to floor for text
             }
@@ -86,14 +148,379 @@ pub fn draw_game(state: &GameState, resources: &Resources, cell_size: f32) {
             }
         }
     }
+}
+
+/// Outlines each cell that still differs from a text goal, with the wanted
+/// character in its corner.
+fn draw_goal_diff(level: &Level, grid: &Grid) {
+    let Some(goal) = level.goal.layout() else {
+        return;
+    };
+    let cell_size = grid.cell_size;
+    for pos in level.goal.differences(&level.layout) {
+        if !grid.view.contains(pos) {
+            continue;
+        }
+        let (screen_x, screen_y) = grid.origin(pos);
+        draw_rectangle(
+            screen_x,
+            screen_y,
+            cell_size,
+            cell_size,
+            Color::new(1.0, 0.4, 0.0, 0.25),
+        );
+        draw_rectangle_lines(screen_x, screen_y, cell_size, cell_size, 2.0, ORANGE);
+        let want = goal[pos.y].chars().nth(pos.x).unwrap_or(' ');
+        draw_text(
+            &want.to_string(),
+            screen_x + 3.0,
+            screen_y + cell_size * 0.3,
+            cell_size * 0.35,
+            ORANGE,
+        );
+    }
+}
 
-    // Draw Player
-    let player_x = state.player_pos.x as f32 * cell_size;
-    let player_y = state.player_pos.y as f32 * cell_size + 50.0;
-    draw_player(player_x, player_y, cell_size, resources, state.time_elapsed);
+/// hlsearch and incsearch: every match of the search is tinted, and the
+/// cell the search being typed would land on is outlined.
+fn draw_search(state: &GameState, grid: &Grid) {
+    let cell_size = grid.cell_size;
+    for (start, len) in state.search_highlights() {
+        for x in start.x..start.x + len {
+            let pos = Position { x, y: start.y };
+            if grid.view.contains(pos) {
+                let (screen_x, screen_y) = grid.origin(pos);
+                draw_rectangle(
+                    screen_x,
+                    screen_y,
+                    cell_size,
+                    cell_size,
+                    Color::new(1.0, 0.9, 0.0, 0.3),
+                );
+            }
+        }
+    }
+    if let Some(pos) = state.search_preview()
+        && grid.view.contains(pos)
+    {
+        let (screen_x, screen_y) = grid.origin(pos);
+        draw_rectangle_lines(screen_x, screen_y, cell_size, cell_size, 3.0, YELLOW);
+    }
+}
 
-    // Draw UI
-    draw_ui(state);
+/// Each mark's letter in the bottom-right corner of its cell.
+fn draw_marks(state: &GameState, grid: &Grid) {
+    let cell_size = grid.cell_size;
+    for (name, pos) in state.marks.iter() {
+        if !grid.view.contains(pos) {
+            continue;
+        }
+        let (screen_x, screen_y) = grid.origin(pos);
+        draw_text(
+            &name.to_string(),
+            screen_x + cell_size * 0.72,
+            screen_y + cell_size - 4.0,
+            cell_size * 0.35,
+            SKYBLUE,
+        );
+    }
+}
+
+/// Numbers the rows on screen left of the level, with the cursor's row
+/// highlighted.
+fn draw_gutter(level: &Level, grid: &Grid, numbers: LineNumbers, cursor_y: usize) {
+    let last = level.height().min(grid.view.top + grid.view.rows);
+    for y in grid.view.top..last {
+        let Some(label) = numbers.label(y, cursor_y) else {
+            return;
+        };
+        let (_, screen_y) = grid.origin(Position { x: grid.view.left, y });
+        let dims = measure_text(&label, None, 20, 1.0);
+        let color = if y == cursor_y { YELLOW } else { DARKGRAY };
+        draw_text(
+            &label,
+            grid.left - dims.width - 8.0,
+            screen_y + (grid.cell_size + dims.height) / 2.0,
+            20.0,
+            color,
+        );
+    }
+}
+
+/// Shows a partially typed command (count, prefix) in the bottom-right
+/// corner, like Vim's showcmd.
+pub fn draw_pending_keys(keys: &str) {
+    if !keys.is_empty() {
+        let dims = measure_text(keys, None, 30, 1.0);
+        draw_text(
+            keys,
+            screen_width() - dims.width - 20.0,
+            screen_height() - 40.0,
+            30.0,
+            YELLOW,
+        );
+    }
+}
+
+/// The level editor: the layout with its cursor, the level's settings and
+/// the solver's verdict below it, and a Vim-style status line.
+pub fn draw_editor(editor: &Editor, resources: &Resources, cell_size: f32) {
+    let level = &editor.level;
+    let grid = Grid {
+        view: &Viewport::new(level.height(), level.width()),
+        cell_size,
+        left: 0.0,
+    };
+    draw_layout(level, &grid, resources, get_time() as f32);
+    draw_goal_diff(level, &grid);
+
+    let cursor_color = match editor.mode {
+        EditorMode::Insert | EditorMode::Replace => GREEN,
+        _ => YELLOW,
+    };
+    draw_rectangle_lines(
+        editor.cursor.x as f32 * cell_size,
+        editor.cursor.y as f32 * cell_size + 50.0,
+        cell_size,
+        cell_size,
+        3.0,
+        cursor_color,
+    );
+
+    let modified = if editor.modified { " [+]" } else { "" };
+    draw_text(
+        &format!("EDITOR: {} ({}){}", level.name, level.id, modified),
+        10.0,
+        30.0,
+        30.0,
+        WHITE,
+    );
+    let file = editor.path.as_deref().unwrap_or("(new level)");
+    let dims = measure_text(file, None, 20, 1.0);
+    draw_text(file, screen_width() - dims.width - 10.0, 30.0, 20.0, GRAY);
+
+    let list = |keys: &[String]| {
+        if keys.is_empty() {
+            "all".to_string()
+        } else {
+            keys.join(" ")
+        }
+    };
+    let goal = match level.goal {
+        Goal::Reach => "reach E",
+        Goal::Text { .. } => "text",
+        Goal::TextThenReach { .. } => "text, then reach E",
+    };
+    let mut lines = vec![
+        (
+            format!(
+                "{}x{} | Goal: {} | Par: {} keys, {:.0}s",
+                level.width(),
+                level.height(),
+                goal,
+                level.par_keystrokes,
+                level.par_time
+            ),
+            LIGHTGRAY,
+        ),
+        (
+            format!(
+                "Allowed: {} | Trained: {}",
+                list(&level.allowed_keys),
+                level.trained_commands.join(" ")
+            ),
+            LIGHTGRAY,
+        ),
+    ];
+    match &editor.analysis {
+        Analysis::Pending => lines.push(("Solving...".to_string(), GRAY)),
+        Analysis::Invalid(errors) => {
+            lines.extend(errors.iter().map(|e| (e.to_string(), RED)));
+        }
+        Analysis::Unsolved(SolveError::Unsolvable) => {
+            lines.push(("No solution with the allowed keys".to_string(), RED));
+        }
+        Analysis::Unsolved(SolveError::NodeLimit(nodes)) => {
+            lines.push((format!("Gave up after exploring {} states", nodes), RED));
+        }
+        Analysis::Solved(solution) => {
+            let keys: Vec<String> = solution.commands.iter().map(|c| c.to_keys()).collect();
+            lines.push((
+                format!("Solver ({} keys): {}", solution.keystrokes, keys.join(" ")),
+                GREEN,
+            ));
+            if solution.keystrokes != level.par_keystrokes {
+                lines.push((
+                    format!("Suggested par: {} (:par to use it)", solution.keystrokes),
+                    YELLOW,
+                ));
+            }
+        }
+    }
+    let top = level.height() as f32 * cell_size + 80.0;
+    for (i, (line, color)) in lines.iter().enumerate() {
+        draw_text(line, 10.0, top + i as f32 * 26.0, 22.0, *color);
+    }
+
+    draw_text(
+        "hjkl move | r{c} paint | i type | x clear | S/E start/end | o/O/D rows | >/< width | u undo",
+        10.0,
+        screen_height() - 55.0,
+        20.0,
+        GRAY,
+    );
+    draw_text(
+        ":name :id :desc :tutorial :allow :train :policy :par [N] :partime N :goal | :w [path] :q",
+        10.0,
+        screen_height() - 35.0,
+        20.0,
+        GRAY,
+    );
+    let status = match &editor.mode {
+        EditorMode::Command(line) => format!(":{}", line),
+        EditorMode::Insert => "-- INSERT --".to_string(),
+        EditorMode::Replace => "r".to_string(),
+        EditorMode::Normal => editor.message.clone(),
+    };
+    draw_text(&status, 10.0, screen_height() - 10.0, 24.0, WHITE);
+}
+
+/// The daily challenge: today's level and budget, streaks, and a calendar
+/// of `month` marking each day's result.
+pub fn draw_daily(
+    today: Date,
+    month: Date,
+    history: &DailyHistory,
+    challenge: Option<&DailyChallenge>,
+    building: bool,
+) {
+    draw_text("DAILY CHALLENGE", 100.0, 100.0, 60.0, GREEN);
+    draw_text(&today.key(), 100.0, 140.0, 30.0, WHITE);
+
+    match challenge {
+        Some(challenge) => {
+            let remix = if challenge.mirrored {
+                "mirrored, new words"
+            } else {
+                "new words"
+            };
+            draw_text(
+                &format!(
+                    "{} ({}) | Budget: {} keystrokes",
+                    challenge.source_name,
+                    remix,
+                    challenge.budget()
+                ),
+                100.0,
+                180.0,
+                25.0,
+                LIGHTGRAY,
+            );
+        }
+        None if building => {
+            draw_text("Making today's challenge...", 100.0, 180.0, 25.0, GRAY);
+        }
+        None => {
+            draw_text("No challenge could be made today.", 100.0, 180.0, 25.0, RED);
+        }
+    }
+    let today_line = match history.get(today) {
+        Some(result) => format!(
+            "Today: {} keystrokes, {:.1}s, {} pts{}",
+            result.keystrokes,
+            result.time,
+            result.score,
+            if result.within_budget() {
+                ""
+            } else {
+                " (over budget)"
+            }
+        ),
+        None => "Today: not played yet".to_string(),
+    };
+    draw_text(&today_line, 100.0, 215.0, 25.0, WHITE);
+    draw_text(
+        &format!(
+            "Streak: {} days | Best streak: {} days",
+            history.streak(today),
+            history.best_streak()
+        ),
+        100.0,
+        250.0,
+        25.0,
+        GOLD,
+    );
+
+    // Calendar: a week per row, Monday first
+    const MONTHS: [&str; 12] = [
+        "January", "February", "March", "April", "May", "June", "July", "August",
+        "September", "October", "November", "December",
+    ];
+    let cell = 50.0;
+    let left = 100.0;
+    let top = 330.0;
+    draw_text(
+        &format!("{} {}", MONTHS[month.month as usize - 1], month.year),
+        left,
+        300.0,
+        30.0,
+        WHITE,
+    );
+    for (i, name) in ["Mo", "Tu", "We", "Th", "Fr", "Sa", "Su"].iter().enumerate() {
+        draw_text(name, left + i as f32 * cell + 12.0, top, 20.0, GRAY);
+    }
+    let first_weekday = month.weekday();
+    for day in 1..=month.days_in_month() {
+        let date = Date { day, ..month };
+        let slot = first_weekday + day - 1;
+        let x = left + (slot % 7) as f32 * cell;
+        let y = top + 10.0 + (slot / 7) as f32 * cell;
+        let fill = match history.get(date) {
+            Some(result) if result.within_budget() => Some(DARKGREEN),
+            Some(_) => Some(Color::new(0.6, 0.45, 0.0, 1.0)),
+            None => None,
+        };
+        if let Some(fill) = fill {
+            draw_rectangle(x + 2.0, y + 2.0, cell - 4.0, cell - 4.0, fill);
+        }
+        if date == today {
+            draw_rectangle_lines(x + 2.0, y + 2.0, cell - 4.0, cell - 4.0, 3.0, YELLOW);
+        }
+        let color = if date > today { DARKGRAY } else { WHITE };
+        draw_text(&day.to_string(), x + 12.0, y + 32.0, 24.0, color);
+    }
+
+    draw_text(
+        "Green: within budget | Amber: over budget",
+        left,
+        top + 10.0 + 6.0 * cell + 30.0,
+        20.0,
+        GRAY,
+    );
+    draw_text(
+        "Enter to play today's challenge | h/l for other months | Esc for the menu",
+        100.0,
+        screen_height() - 50.0,
+        20.0,
+        GRAY,
+    );
+}
+
+/// Menu panel explaining why a level could not be loaded.
+pub fn draw_load_errors(path: &str, errors: &[String]) {
+    draw_text("VIM GAME", 100.0, 100.0, 60.0, GREEN);
+    draw_text(&format!("Could not load {}:", path), 100.0, 180.0, 30.0, RED);
+    for (i, error) in errors.iter().enumerate() {
+        let y = 230.0 + i as f32 * 30.0;
+        draw_text(error, 120.0, y, 22.0, WHITE);
+    }
+    draw_text(
+        "Press Enter or Esc to return to the menu",
+        100.0,
+        screen_height() - 50.0,
+        20.0,
+        GRAY,
+    );
 }
 
 fn draw_platform(x: f32, y: f32, size: f32, left: bool, right: bool) {
@@ -383,6 +810,38 @@ fn draw_ui(state: &GameState) {
             RED,
         );
     }
+
+    // Goal, undo and register status sit bottom-right, above the pending keys
+    let mut history = Vec::new();
+    if state.practice_mode {
+        history.push("PRACTICE".to_string());
+    }
+    if state.current_level.goal.layout().is_some() {
+        let level = &state.current_level;
+        let left = level.goal.differences(&level.layout).len();
+        history.push(format!("Cells to fix: {}", left));
+    }
+    let height = state.current_level.height();
+    if height > state.viewport.rows {
+        history.push(state.viewport.ruler(height));
+    }
+    if state.undos > 0 {
+        history.push(format!("Undos: {}", state.undos));
+    }
+    if let Some(register) = &state.register {
+        history.push(format!("Register: \"{}\"", register.text.join("\\n")));
+    }
+    if !history.is_empty() {
+        let text = history.join(" | ");
+        let dims = measure_text(&text, None, 25, 1.0);
+        draw_text(
+            &text,
+            screen_width() - dims.width - 20.0,
+            screen_height() - 70.0,
+            25.0,
+            SKYBLUE,
+        );
+    }
     if state.is_auto_playing {
         let box_width = 400.0;
         let box_height = 100.0;
@@ -412,6 +871,27 @@ fn draw_ui(state: &GameState) {
         }
     }
 
+    if let Some(cmd) = &state.blocked_command {
+        let text = match state.current_level.key_policy {
+            KeyPolicy::Reject => format!("Blocked: {} is not allowed here", cmd.to_display_string()),
+            KeyPolicy::Penalize => {
+                format!("Penalty: {} is not allowed here", cmd.to_display_string())
+            }
+        };
+        draw_text(&text, 10.0, screen_height() - 70.0, 25.0, RED);
+    }
+
+    // Replies to ex commands sit where the command line was, or above it
+    // while the next one is typed (Tab's list of matches)
+    if let Some(message) = &state.message {
+        let (text, color) = match message {
+            Message::Info(text) => (text, WHITE),
+            Message::Error(text) => (text, RED),
+        };
+        let y = if state.input_mode.takes_text() { 70.0 } else { 40.0 };
+        draw_text(text, 10.0, screen_height() - y, 25.0, color);
+    }
+
     // Draw Input Mode Status
     match &state.input_mode {
         InputMode::WaitingForChar(cmd) => {
@@ -422,8 +902,9 @@ fn draw_ui(state: &GameState) {
                 VimCommand::StartTillPrev => "T",
                 _ => "?",
             };
+            let count = state.pending_count.map(|n| n.to_string()).unwrap_or_default();
             draw_text(
-                &format!("Waiting for char: {}", cmd_name),
+                &format!("Waiting for char: {}{}", count, cmd_name),
                 10.0,
                 screen_height() - 40.0,
                 30.0,
@@ -450,11 +931,32 @@ fn draw_ui(state: &GameState) {
                 );
             }
         }
+        InputMode::OperatorPending => {
+            if let Some(pending) = &state.pending_operator {
+                let count = pending.count.map(|n| n.to_string()).unwrap_or_default();
+                let object = pending.object.map(String::from).unwrap_or_default();
+                draw_text(
+                    &format!(
+                        "Operator pending: {}{}{}",
+                        count,
+                        pending.operator.key(),
+                        object
+                    ),
+                    10.0,
+                    screen_height() - 40.0,
+                    30.0,
+                    YELLOW,
+                );
+            }
+        }
+        InputMode::Insert(_) => {
+            draw_text("-- INSERT --", 10.0, screen_height() - 40.0, 30.0, YELLOW);
+        }
         _ => {}
     }
 
     draw_text(
-        "Press ESC to Menu | F1 for Help | Shift+P to Solve",
+        "ESC Menu | F1 Help | F2 Practice | F3 Solve | F4 Replay | F5 Numbers | u/Ctrl-r Undo/Redo",
         10.0,
         screen_height() - 10.0,
         20.0,
@@ -466,9 +968,19 @@ fn draw_ui(state: &GameState) {
     }
 
     if let GameStatus::LevelComplete = state.status {
-        draw_overlay("LEVEL COMPLETE!", &format!("Score: {}", state.score), GOLD);
+        draw_overlay(
+            "LEVEL COMPLETE!",
+            &format!("Score: {}", state.score),
+            "Press ENTER for Next Level, ESC to Menu",
+            GOLD,
+        );
     } else if let GameStatus::GameOver = state.status {
-        draw_overlay("GAME OVER", "You fell into the void!", RED);
+        let help = if state.practice_mode {
+            "Press u to undo, ESC to return"
+        } else {
+            "Press ESC to return (F2 enables practice undo)"
+        };
+        draw_overlay("GAME OVER", "You fell into the void!", help, RED);
     }
 }
 
@@ -512,9 +1024,18 @@ fn draw_help_overlay(state: &GameState) {
         draw_text(line, 80.0, 220.0 + i as f32 * 30.0, 30.0, WHITE);
     }
 
+    let mut start_y = 220.0 + lines.len() as f32 * 30.0 + 40.0;
+
+    // Draw allowed keys
+    if !state.current_level.allowed_keys.is_empty() {
+        draw_text("Allowed Keys:", 80.0, start_y, 30.0, YELLOW);
+        let keys = state.current_level.allowed_keys.join(" ");
+        draw_text(&keys, 300.0, start_y, 30.0, WHITE);
+        start_y += 50.0;
+    }
+
     // Draw trained commands
     if !state.current_level.trained_commands.is_empty() {
-        let start_y = 220.0 + lines.len() as f32 * 30.0 + 40.0;
         draw_text("Trained Commands:", 80.0, start_y, 30.0, YELLOW);
         let cmds = state.current_level.trained_commands.join(", ");
         draw_text(&cmds, 80.0, start_y + 40.0, 30.0, WHITE);
@@ -536,7 +1057,7 @@ fn draw_help_overlay(state: &GameState) {
     );
 }
 
-fn draw_overlay(title: &str, subtitle: &str, color: Color) {
+fn draw_overlay(title: &str, subtitle: &str, help: &str, color: Color) {
     draw_rectangle(
         50.0,
         200.0,
@@ -564,11 +1085,6 @@ fn draw_overlay(title: &str, subtitle: &str, color: Color) {
         WHITE,
     );
 
-    let help = if title == "LEVEL COMPLETE!" {
-        "Press ENTER for Next Level, ESC to Menu"
-    } else {
-        "Press ESC to return"
-    };
     let help_dims = measure_text(help, None, 30, 1.0);
     draw_text(
         help,
@@ -0,0 +1,115 @@
+//! Recorded runs: every resolved command with the time it was played, saved
+//! per level so a run can be watched again at the pace it was played.
+
+use crate::input::VimCommand;
+use crate::level::Level;
+use crate::pattern::CaseOptions;
+use crate::storage;
+use serde::{Deserialize, Serialize};
+
+/// Seconds between commands when auto-playing a solver solution.
+pub const AUTO_PLAY_TICK: f32 = 0.8;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReplayStep {
+    /// Seconds since the level started.
+    pub time: f32,
+    pub command: VimCommand,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Replay {
+    pub level_id: String,
+    /// `Level::fingerprint` of the level as it was played.
+    pub level_hash: u64,
+    pub steps: Vec<ReplayStep>,
+    pub keystrokes: u32,
+    pub time: f32,
+    pub score: i32,
+    /// `ignorecase`/`smartcase` as they were, since they change where searches land.
+    #[serde(default)]
+    pub case_options: CaseOptions,
+}
+
+impl Replay {
+    fn key(level_id: &str) -> String {
+        format!("replays/{}", level_id)
+    }
+
+    /// Saves this run as the level's replay, replacing any older one.
+    pub fn save(&self) -> Result<(), String> {
+        let json = serde_json::to_string_pretty(self).map_err(|e| e.to_string())?;
+        storage::save(&Self::key(&self.level_id), &json)
+    }
+
+    /// Loads the saved replay for `level`, refusing one recorded on a
+    /// different version of it.
+    pub fn load(level: &Level) -> Result<Replay, String> {
+        let json = storage::load(&Self::key(&level.id))
+            .ok_or_else(|| format!("no replay saved for {}", level.id))?;
+        let replay: Replay = serde_json::from_str(&json).map_err(|e| e.to_string())?;
+        if replay.level_hash != level.fingerprint() {
+            return Err(format!(
+                "the replay for {} was recorded on a different version of the level",
+                level.id
+            ));
+        }
+        Ok(replay)
+    }
+}
+
+/// Spaces commands a fixed `tick` apart, for auto-playing a solution.
+pub fn paced(commands: Vec<VimCommand>, tick: f32) -> Vec<ReplayStep> {
+    commands
+        .into_iter()
+        .enumerate()
+        .map(|(i, command)| ReplayStep {
+            time: (i + 1) as f32 * tick,
+            command,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::Operator;
+
+    #[test]
+    fn test_replay_round_trips_through_json() {
+        let replay = Replay {
+            level_id: "level_01".to_string(),
+            level_hash: u64::MAX,
+            steps: vec![
+                ReplayStep {
+                    time: 0.5,
+                    command: VimCommand::Counted(3, Box::new(VimCommand::MoveWordForward)),
+                },
+                ReplayStep {
+                    time: 1.25,
+                    command: VimCommand::Operate(
+                        Operator::Delete,
+                        Box::new(VimCommand::SearchForward("x".to_string())),
+                    ),
+                },
+            ],
+            keystrokes: 6,
+            time: 1.25,
+            score: 1000,
+            case_options: CaseOptions {
+                ignore_case: true,
+                smart_case: false,
+            },
+        };
+        let json = serde_json::to_string(&replay).unwrap();
+        assert_eq!(serde_json::from_str::<Replay>(&json).unwrap(), replay);
+    }
+
+    #[test]
+    fn test_paced() {
+        let steps = paced(vec![VimCommand::MoveLeft, VimCommand::MoveRight], 0.5);
+        assert_eq!(steps[0].time, 0.5);
+        assert_eq!(steps[1].time, 1.0);
+        assert_eq!(steps[1].command, VimCommand::MoveRight);
+    }
+}
@@ -1,31 +1,71 @@
-use crate::game::{GameState, GameStatus};
-use crate::input::VimCommand;
+use crate::game::{GameState, GameStatus, Register};
+use crate::input::{Operator, TextObject, VimCommand};
 use crate::level::{Level, Position};
+use crate::marks::{JumpList, Marks};
+use crate::pattern;
+use crate::viewport::Viewport;
 use std::cmp::Ordering;
-use std::collections::{BinaryHeap, HashSet};
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::fmt;
+
+// Word, paragraph and bracket motions are tried with counts up to this value
+const MAX_WORD_COUNT: u32 = 9;
+
+// Longest search query the solver will type. Anything longer costs more
+// keystrokes than walking in almost every layout.
+const MAX_QUERY_LEN: usize = 10;
+
+// States the solver explores before giving up.
+const MAX_NODES: usize = 500_000;
+
+/// States to explore per frame when a search is spread over frames. A state
+/// takes up to a couple of milliseconds, in levels with many commands.
+pub const FRAME_BUDGET: usize = 20;
+
+// Marks the solver sets at once. Which letter a mark has doesn't matter,
+// so it only ever uses `a`, and moves it rather than setting a second one.
+const MAX_MARKS: usize = 1;
 
 #[derive(Clone, Eq, PartialEq, Hash)]
 struct State {
     x: usize,
     y: usize,
     layout: Vec<String>,
+    // Registers that make ; , n N meaningful
+    last_find: Option<VimCommand>,
+    last_search: Option<(String, bool)>,
+    // What p and P would put
+    register: Option<Register>,
+    // Where H, M, L and the scroll commands act
+    viewport: Viewport,
+    marks: Marks,
+    // Only kept in levels that allow Ctrl-o or Ctrl-i
+    jumps: JumpList,
 }
 
 #[derive(Clone, Eq, PartialEq)]
 struct Node {
     state: State,
     path: Vec<VimCommand>,
-    cost: usize,      // g(n)
+    cost: usize,      // g(n), in keystrokes
     heuristic: usize, // h(n)
+    counts: usize,    // Counted commands in the path
+    seq: usize,       // Generation order, to break ties deterministically
 }
 
 // Priority is based on f(n) = cost + heuristic.
 // BinaryHeap is max-heap, so we need to reverse the ordering to get min-heap behavior.
+// Ties prefer fewer commands, then fewer counts (fE over 4l), then the
+// command generated first (e.g. 4l over 6l).
 impl Ord for Node {
     fn cmp(&self, other: &Self) -> Ordering {
         let self_f = self.cost + self.heuristic;
         let other_f = other.cost + other.heuristic;
-        other_f.cmp(&self_f) // Reverse for min-heap
+        other_f
+            .cmp(&self_f) // Reverse for min-heap
+            .then(other.path.len().cmp(&self.path.len()))
+            .then(other.counts.cmp(&self.counts))
+            .then(other.seq.cmp(&self.seq))
     }
 }
 
@@ -35,49 +75,179 @@ impl PartialOrd for Node {
     }
 }
 
+/// The way through a level with the fewest keystrokes, among the commands
+/// the solver tries. It leaves out counts over 9 for word, paragraph,
+/// sentence, bracket and jumplist motions and for searches, counted `;`
+/// and `,`, counted searches for more than one char, and a counted `x`
+/// over a cell the goal keeps, so a cheaper way may exist that uses those.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Solution {
+    pub commands: Vec<VimCommand>,
+    pub keystrokes: u32,
+}
+
+/// Why the solver came back without a solution.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SolveError {
+    /// Every reachable state was tried: the allowed keys can't do it.
+    Unsolvable,
+    /// The search gave up after exploring this many states, so a solution
+    /// may still exist.
+    NodeLimit(usize),
+}
+
+impl fmt::Display for SolveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SolveError::Unsolvable => write!(f, "no solution with the allowed keys"),
+            SolveError::NodeLimit(nodes) => {
+                write!(f, "gave up after exploring {} states", nodes)
+            }
+        }
+    }
+}
+
+/// Every substring (up to `MAX_QUERY_LEN`) of a layout, escaped as a search
+/// pattern, with the cells where it starts in reading order. Used to find
+/// the shortest query that lands on a given cell.
+struct QueryIndex {
+    width: usize,
+    matches: Vec<(String, Vec<usize>)>,
+}
+
+impl QueryIndex {
+    fn new(layout: &[String], width: usize) -> Self {
+        let mut by_query: HashMap<String, Vec<usize>> = HashMap::new();
+        for (y, row) in layout.iter().enumerate() {
+            let chars: Vec<char> = row.chars().collect();
+            let is_word = |x: usize| chars.get(x).is_some_and(|&c| c.is_alphanumeric() || c == '_');
+            // `/^` lands on the next row's first cell
+            by_query.entry("^".to_string()).or_default().push(y * width);
+            for x in 0..chars.len() {
+                let word_start = is_word(x) && (x == 0 || !is_word(x - 1));
+                for len in 1..=MAX_QUERY_LEN.min(chars.len() - x) {
+                    let text: String = chars[x..x + len].iter().collect();
+                    // Queries are patterns, so magic chars need a backslash
+                    let text = pattern::escape(&text);
+                    // Anchors pin the text to a row's start or end, or a word's start
+                    let mut queries = vec![text.clone()];
+                    if x == 0 {
+                        queries.push(format!("^{}", text));
+                    }
+                    if x + len == chars.len() {
+                        queries.push(format!("{}$", text));
+                    }
+                    if word_start {
+                        queries.push(format!("\\<{}", text));
+                    }
+                    for query in queries {
+                        by_query.entry(query).or_default().push(y * width + x);
+                    }
+                }
+            }
+        }
+        let mut matches: Vec<(String, Vec<usize>)> = by_query.into_iter().collect();
+        // Sorted so that ties between equally short queries are deterministic
+        matches.sort_by(|a, b| {
+            (a.0.chars().count().cmp(&b.0.chars().count())).then(a.0.cmp(&b.0))
+        });
+        for (_, cells) in &mut matches {
+            cells.sort_unstable();
+        }
+        Self { width, matches }
+    }
+
+    fn single_chars(&self) -> impl Iterator<Item = &String> {
+        self.matches
+            .iter()
+            .map(|(query, _)| query)
+            .take_while(|query| query.chars().count() == 1)
+    }
+
+    /// The shortest query for every cell a search from (x, y) can land on.
+    fn queries_from(&self, x: usize, y: usize, forward: bool) -> Vec<(String, Position)> {
+        let here = y * self.width + x;
+        let mut best: HashMap<usize, &str> = HashMap::new();
+        for (query, cells) in &self.matches {
+            // Searches wrap around and never match the cursor's own cell
+            let landing = if forward {
+                let i = cells.partition_point(|&c| c <= here);
+                cells.get(i).or_else(|| cells.first())
+            } else {
+                let i = cells.partition_point(|&c| c < here);
+                if i > 0 { cells.get(i - 1) } else { cells.last() }
+            };
+            if let Some(&cell) = landing
+                && cell != here
+            {
+                best.entry(cell).or_insert(query);
+            }
+        }
+        best.into_iter()
+            .map(|(cell, query)| {
+                let pos = Position {
+                    x: cell % self.width,
+                    y: cell / self.width,
+                };
+                (query.to_string(), pos)
+            })
+            .collect()
+    }
+}
+
+#[derive(Clone)]
 pub struct Solver {
     level: Level,
-    interesting_words: Vec<String>,
+    // Rows and columns on screen; None shows the whole level
+    screen: Option<(usize, usize)>,
+    max_nodes: usize,
 }
 
 impl Solver {
     pub fn new(level: Level) -> Self {
-        let interesting_words = Self::extract_words(&level.layout);
         Self {
             level,
-            interesting_words,
+            screen: None,
+            max_nodes: MAX_NODES,
         }
     }
 
-    fn extract_words(layout: &[String]) -> Vec<String> {
-        let mut words = HashSet::new();
-        for line in layout {
-            let mut current_word = String::new();
-            for c in line.chars() {
-                if c.is_alphanumeric() || c == '_' {
-                    current_word.push(c);
-                } else {
-                    if !current_word.is_empty() {
-                        words.insert(current_word.clone());
-                        current_word.clear();
-                    }
-                }
-            }
-            if !current_word.is_empty() {
-                words.insert(current_word);
+    /// Solves as seen on a screen of `rows` x `cols` cells, which changes
+    /// where H, M, L and the scroll commands go.
+    pub fn with_screen(mut self, rows: usize, cols: usize) -> Self {
+        self.screen = Some((rows, cols));
+        self
+    }
+
+    /// Finds a solution with the fewest keystrokes, among the commands the
+    /// solver tries (see `Solution`).
+    pub fn solve(&self) -> Result<Solution, SolveError> {
+        let mut search = self.clone().search();
+        loop {
+            if let Some(result) = search.step(usize::MAX) {
+                return result;
             }
         }
-        // Filter out "terrain" words (e.g. "XXXXX")
-        words.into_iter()
-            .filter(|w| !w.chars().all(|c| c == 'X' || c == '.' || c == '#'))
-            .collect()
     }
 
-    pub fn solve(&self) -> Option<Vec<VimCommand>> {
+    /// Starts a search that can be run a few states at a time, so a caller
+    /// with a frame to draw can spread it out.
+    pub fn search(self) -> Search {
+        let (rows, cols) = self
+            .screen
+            .unwrap_or((self.level.height(), self.level.width()));
+        let mut viewport = Viewport::new(rows, cols);
+        viewport.follow(self.level.start_pos);
         let start_state = State {
             x: self.level.start_pos.x,
             y: self.level.start_pos.y,
             layout: self.level.layout.clone(),
+            last_find: None,
+            last_search: None,
+            register: None,
+            viewport,
+            marks: Marks::default(),
+            jumps: JumpList::default(),
         };
 
         let start_node = Node {
@@ -85,134 +255,473 @@ impl Solver {
             path: vec![],
             cost: 0,
             heuristic: self.heuristic(&start_state),
+            counts: 0,
+            seq: 0,
         };
 
         let mut queue = BinaryHeap::new();
         queue.push(start_node);
 
-        let mut visited = HashSet::new();
-        visited.insert(start_state);
+        let level_hash = self.level.fingerprint();
+        let gate = GameState::with_level_hash(self.level.clone(), level_hash);
+        // Marks and the jumplist tell apart states that are otherwise the
+        // same, so they are only used when allowed_keys lists them
+        let listed = |command: VimCommand| gate.allowed_commands.contains(&command);
+        let uses_marks = listed(VimCommand::StartSetMark);
+        let uses_jumps = listed(VimCommand::JumpOlder) || listed(VimCommand::JumpNewer);
 
-        let max_nodes = 500_000; // Safety break
-        let mut nodes_explored = 0;
+        Search {
+            width: self.level.width() + 1,
+            solver: self,
+            queue,
+            visited: HashSet::new(),
+            gate,
+            level_hash,
+            uses_marks,
+            uses_jumps,
+            query_indexes: HashMap::new(),
+            seq: 0,
+            nodes_explored: 0,
+        }
+    }
+
+    fn is_goal(&self, state: &State) -> bool {
+        let at_target = state.x == self.level.target_pos.x && state.y == self.level.target_pos.y;
+        self.level.goal.is_met(at_target, &state.layout)
+    }
 
-        while let Some(node) = queue.pop() {
-            nodes_explored += 1;
-            if nodes_explored > max_nodes {
-                println!("Solver hit max_nodes limit: {}", max_nodes);
-                break;
+    /// The goal text for the run of wrong cells starting at column `x` of
+    /// the cursor's row, or None if that cell is already right.
+    fn goal_run(&self, state: &State, x: usize) -> Option<String> {
+        let goal_row: Vec<char> = self.level.goal.layout()?.get(state.y)?.chars().collect();
+        let row: Vec<char> = state.layout.get(state.y)?.chars().collect();
+        let typed: String = (x..row.len().min(goal_row.len()))
+            .take_while(|&x| row[x] != goal_row[x])
+            .map(|x| goal_row[x])
+            .collect();
+        (!typed.is_empty()).then_some(typed)
+    }
+
+    // Any command can teleport (search, H/L, ...) and one edit can fix many
+    // cells, so neither distance nor wrong cells bound the keystrokes left.
+    // The only admissible estimate is "at least one more keystroke", which
+    // makes this Dijkstra's search in all but name. That is deliberate: a
+    // tighter bound would need every command's reach from the state, which
+    // costs as much as expanding it.
+    fn heuristic(&self, state: &State) -> usize {
+        if self.is_goal(state) { 0 } else { 1 }
+    }
+}
+
+/// A search in progress, started by `Solver::search`.
+pub struct Search {
+    solver: Solver,
+    queue: BinaryHeap<Node>,
+    // States are closed when popped, not when pushed: commands cost
+    // different numbers of keystrokes, so the first path to reach a
+    // state isn't necessarily the cheapest.
+    visited: HashSet<State>,
+    // Used only to ask which commands the level's allowed_keys permit
+    gate: GameState,
+    // Fingerprinting serializes the level, far too slow to do per state
+    level_hash: u64,
+    uses_marks: bool,
+    uses_jumps: bool,
+    width: usize,
+    // Search queries depend on the text, which `x` can change
+    query_indexes: HashMap<Vec<String>, QueryIndex>,
+    seq: usize,
+    nodes_explored: usize,
+}
+
+impl Search {
+    /// Explores up to `budget` more states. None while the search goes on;
+    /// once it returns the outcome, the search is over.
+    pub fn step(&mut self, budget: usize) -> Option<Result<Solution, SolveError>> {
+        for _ in 0..budget {
+            let Some(node) = self.queue.pop() else {
+                return Some(Err(SolveError::Unsolvable));
+            };
+            self.nodes_explored += 1;
+            if self.nodes_explored > self.solver.max_nodes {
+                return Some(Err(SolveError::NodeLimit(self.solver.max_nodes)));
             }
 
-            if node.state.x == self.level.target_pos.x && node.state.y == self.level.target_pos.y {
-                return Some(node.path);
+            if self.solver.is_goal(&node.state) {
+                return Some(Ok(Solution {
+                    commands: node.path,
+                    keystrokes: node.cost as u32,
+                }));
             }
 
-            // Generate commands
-            let mut commands = vec![
-                VimCommand::MoveLeft,
-                VimCommand::MoveRight,
-                VimCommand::MoveUp,
-                VimCommand::MoveDown,
-                VimCommand::MoveWordForward,
-                VimCommand::MoveWordBack,
-                VimCommand::MoveWordEnd,
-                VimCommand::MoveLineStart,
-                VimCommand::MoveLineEnd,
-                VimCommand::MoveScreenTop,
-                VimCommand::MoveScreenMiddle,
-                VimCommand::MoveScreenBottom,
-                VimCommand::MoveParagraphForward,
-                VimCommand::MoveParagraphBack,
-            ];
-
-            // Add DeleteChar if applicable
-            let current_char = node
-                .state
-                .layout
-                .get(node.state.y)
-                .and_then(|row| row.chars().nth(node.state.x))
-                .unwrap_or(' ');
-            if current_char != '.'
-                && current_char != ' '
-                && current_char != 'S'
-                && current_char != 'E'
-            {
-                commands.push(VimCommand::DeleteChar);
+            if self.visited.insert(node.state.clone()) {
+                self.expand(node);
             }
+        }
+        None
+    }
 
-            // Add Find/Till commands
-            if let Some(row) = node.state.layout.get(node.state.y) {
-                let mut unique_chars = HashSet::new();
-                for c in row.chars() {
-                    if c != ' ' && c != '#' {
-                        unique_chars.insert(c);
-                    }
-                }
-                for c in unique_chars {
-                    commands.push(VimCommand::FindNextChar(c));
-                    commands.push(VimCommand::FindPrevChar(c));
-                    commands.push(VimCommand::TillNextChar(c));
-                    commands.push(VimCommand::TillPrevChar(c));
+    /// Queues every state one step away from `node`.
+    fn expand(&mut self, node: Node) {
+        // Generate commands
+        let mut commands = vec![
+            VimCommand::MoveLeft,
+            VimCommand::MoveRight,
+            VimCommand::MoveUp,
+            VimCommand::MoveDown,
+            VimCommand::MoveWordForward,
+            VimCommand::MoveWordBack,
+            VimCommand::MoveWordEnd,
+            VimCommand::MoveWordEndBack,
+            VimCommand::MoveBigWordForward,
+            VimCommand::MoveBigWordBack,
+            VimCommand::MoveBigWordEnd,
+            VimCommand::MoveBigWordEndBack,
+            VimCommand::MoveLineStart,
+            VimCommand::MoveLineEnd,
+            VimCommand::MoveFirstNonBlank,
+            VimCommand::MoveLastNonBlank,
+            VimCommand::MoveLineFirstNonBlank,
+            VimCommand::MoveNextLineStart,
+            VimCommand::MovePrevLineStart,
+            VimCommand::MoveScreenTop,
+            VimCommand::MoveScreenMiddle,
+            VimCommand::MoveScreenBottom,
+            VimCommand::MoveParagraphForward,
+            VimCommand::MoveParagraphBack,
+            VimCommand::MoveSentenceForward,
+            VimCommand::MoveSentenceBack,
+            VimCommand::MoveSectionForward,
+            VimCommand::MoveSectionBack,
+            VimCommand::MoveSectionEndForward,
+            VimCommand::MoveSectionEndBack,
+            VimCommand::MatchPair,
+            VimCommand::PrevUnmatchedParen,
+            VimCommand::PrevUnmatchedBrace,
+            VimCommand::NextUnmatchedParen,
+            VimCommand::NextUnmatchedBrace,
+            VimCommand::GotoFirstLine,
+            VimCommand::GotoLastLine,
+            VimCommand::SearchWordForward,
+            VimCommand::SearchWordBackward,
+            VimCommand::SearchTextForward,
+            VimCommand::SearchTextBackward,
+        ];
+
+        if node.state.last_find.is_some() {
+            commands.push(VimCommand::RepeatFind);
+            commands.push(VimCommand::RepeatFindReverse);
+        }
+        if node.state.last_search.is_some() {
+            for repeat in [VimCommand::NextMatch, VimCommand::PrevMatch] {
+                for count in 2..=MAX_WORD_COUNT {
+                    commands.push(VimCommand::Counted(count, Box::new(repeat.clone())));
                 }
+                commands.push(repeat);
             }
+        }
 
-            // Add Search commands
-            for word in &self.interesting_words {
-                commands.push(VimCommand::SearchForward(word.clone()));
+        // Add counted versions of the repeatable motions (e.g. 5j, 3w)
+        let max_count = self.solver.level.width().max(self.solver.level.height()) as u32;
+        for motion in [
+            VimCommand::MoveLeft,
+            VimCommand::MoveRight,
+            VimCommand::MoveUp,
+            VimCommand::MoveDown,
+        ] {
+            for count in 2..=max_count {
+                commands.push(VimCommand::Counted(count, Box::new(motion.clone())));
+            }
+        }
+        // Line starts and ends can go as far as the last row
+        for motion in [
+            VimCommand::MoveLastNonBlank,
+            VimCommand::MoveLineFirstNonBlank,
+            VimCommand::MoveNextLineStart,
+            VimCommand::MovePrevLineStart,
+        ] {
+            for count in 2..=self.solver.level.height() as u32 {
+                commands.push(VimCommand::Counted(count, Box::new(motion.clone())));
             }
+        }
+        for motion in [
+            VimCommand::MoveWordForward,
+            VimCommand::MoveWordBack,
+            VimCommand::MoveWordEnd,
+            VimCommand::MoveWordEndBack,
+            VimCommand::MoveBigWordForward,
+            VimCommand::MoveBigWordBack,
+            VimCommand::MoveBigWordEnd,
+            VimCommand::MoveBigWordEndBack,
+            VimCommand::MoveParagraphForward,
+            VimCommand::MoveParagraphBack,
+            VimCommand::MoveSentenceForward,
+            VimCommand::MoveSentenceBack,
+            VimCommand::MoveSectionForward,
+            VimCommand::MoveSectionBack,
+            VimCommand::MoveSectionEndForward,
+            VimCommand::MoveSectionEndBack,
+            VimCommand::PrevUnmatchedParen,
+            VimCommand::PrevUnmatchedBrace,
+            VimCommand::NextUnmatchedParen,
+            VimCommand::NextUnmatchedBrace,
+        ] {
+            for count in 2..=MAX_WORD_COUNT {
+                commands.push(VimCommand::Counted(count, Box::new(motion.clone())));
+            }
+        }
 
-            for cmd in commands {
-                // Simulate
-                let mut temp_level = self.level.clone();
-                temp_level.layout = node.state.layout.clone();
+        // Back to a mark, exactly or to its line
+        for (name, _) in node.state.marks.iter() {
+            commands.push(VimCommand::GotoMarkLine(name));
+            commands.push(VimCommand::GotoMark(name));
+        }
 
-                let mut temp_game = GameState::new(temp_level);
-                temp_game.player_pos = Position {
-                    x: node.state.x,
-                    y: node.state.y,
-                };
+        // Every line by number: {N}G, and :{N} for levels without G
+        for line in 1..=self.solver.level.height() as u32 {
+            commands.push(VimCommand::Counted(line, Box::new(VimCommand::GotoLastLine)));
+            commands.push(VimCommand::GotoLine(line));
+        }
 
-                temp_game.handle_command(cmd.clone());
+        // Add DeleteChar, with a count reaching each cell it would change
+        // before the next wall (a counted x stops there). With a goal
+        // layout, a count stops short of the first cell the goal keeps.
+        if let Some(row) = node.state.layout.get(node.state.y) {
+            let goal_row: Option<Vec<char>> = self
+                .solver
+                .level
+                .goal
+                .layout()
+                .and_then(|goal| goal.get(node.state.y))
+                .map(|row| row.chars().collect());
+            let run = row.chars().enumerate().skip(node.state.x).take_while(|&(_, c)| c != '#');
+            for (i, (x, c)) in run.enumerate() {
+                if matches!(c, '.' | ' ' | 'S' | 'E') {
+                    continue;
+                }
+                let kept = goal_row.as_ref().is_some_and(|goal| goal.get(x) != Some(&'.'));
+                if i == 0 {
+                    commands.push(VimCommand::DeleteChar);
+                } else if !kept {
+                    let count = i as u32 + 1;
+                    commands.push(VimCommand::Counted(count, Box::new(VimCommand::DeleteChar)));
+                }
+                if kept {
+                    break;
+                }
+            }
+        }
 
-                if let GameStatus::GameOver = temp_game.status {
-                    continue; // Died
+        // Add Find/Till commands, with a count for each repeated char
+        if let Some(row) = node.state.layout.get(node.state.y) {
+            let mut occurrences: HashMap<char, u32> = HashMap::new();
+            for c in row.chars() {
+                if c != ' ' && c != '#' {
+                    *occurrences.entry(c).or_default() += 1;
+                }
+            }
+            for (c, max) in occurrences {
+                for find in [
+                    VimCommand::FindNextChar(c),
+                    VimCommand::FindPrevChar(c),
+                    VimCommand::TillNextChar(c),
+                    VimCommand::TillPrevChar(c),
+                ] {
+                    for count in 2..=max {
+                        commands.push(VimCommand::Counted(count, Box::new(find.clone())));
+                    }
+                    commands.push(find);
                 }
+            }
+        }
 
-                let new_state = State {
-                    x: temp_game.player_pos.x,
-                    y: temp_game.player_pos.y,
-                    layout: temp_game.current_level.layout.clone(),
-                };
+        // Add operators over every uncounted motion, line and text object.
+        // `c` is left out: whatever it types, `x` or `d` plus a put can too.
+        for operator in [Operator::Delete, Operator::Yank] {
+            if !self.gate.is_command_allowed(&VimCommand::StartOperator(operator)) {
+                continue;
+            }
+            let mut motions: Vec<VimCommand> = commands
+                .iter()
+                .filter(|cmd| {
+                    !matches!(
+                        cmd,
+                        VimCommand::Counted(..)
+                            | VimCommand::DeleteChar
+                            | VimCommand::GotoLine(_)
+                            | VimCommand::SearchForward(_)
+                            | VimCommand::SearchBackward(_)
+                    )
+                })
+                .cloned()
+                .collect();
+            motions.push(VimCommand::StartOperator(operator));
+            for object in [
+                TextObject::InnerWord,
+                TextObject::AWord,
+                TextObject::InnerBigWord,
+                TextObject::ABigWord,
+            ] {
+                motions.push(VimCommand::TextObject(object));
+            }
+            for motion in motions {
+                commands.push(VimCommand::Operate(operator, Box::new(motion)));
+            }
+        }
+        // Scrolling isn't a motion, so it comes after the operators
+        commands.extend([
+            VimCommand::ScrollLineDown,
+            VimCommand::ScrollLineUp,
+            VimCommand::ScrollHalfPageDown,
+            VimCommand::ScrollHalfPageUp,
+            VimCommand::ScrollPageDown,
+            VimCommand::ScrollPageUp,
+            VimCommand::ScrollCursorTop,
+            VimCommand::ScrollCursorCenter,
+            VimCommand::ScrollCursorBottom,
+        ]);
 
-                if !visited.contains(&new_state) {
-                    visited.insert(new_state.clone());
-                    let mut new_path = node.path.clone();
-                    new_path.push(cmd);
-
-                    let new_node = Node {
-                        state: new_state.clone(),
-                        path: new_path,
-                        cost: node.cost + 1,
-                        heuristic: self.heuristic(&new_state),
-                    };
-                    queue.push(new_node);
+        if node.state.register.is_some() {
+            commands.push(VimCommand::PutAfter);
+            commands.push(VimCommand::PutBefore);
+        }
+
+        if self.uses_marks {
+            // Move a mark already set, or set the next letter
+            let unused = ('a'..='z').find(|&name| node.state.marks.get(name).is_none());
+            let mut names: Vec<char> = node.state.marks.iter().map(|(name, _)| name).collect();
+            if node.state.marks.len() < MAX_MARKS {
+                names.extend(unused);
+            }
+            commands.extend(names.into_iter().map(VimCommand::SetMark));
+        }
+        if self.uses_jumps {
+            for jump in [VimCommand::JumpOlder, VimCommand::JumpNewer] {
+                for count in 2..=node.state.jumps.len().min(MAX_WORD_COUNT as usize) {
+                    commands.push(VimCommand::Counted(count as u32, Box::new(jump.clone())));
                 }
+                commands.push(jump);
             }
         }
 
-        None
-    }
+        // Add Search commands: the shortest query for each landing cell
+        let index = self.query_indexes
+            .entry(node.state.layout.clone())
+            .or_insert_with(|| QueryIndex::new(&node.state.layout, self.width));
+        for (query, _) in index.queries_from(node.state.x, node.state.y, true) {
+            commands.push(VimCommand::SearchForward(query));
+        }
+        for (query, _) in index.queries_from(node.state.x, node.state.y, false) {
+            commands.push(VimCommand::SearchBackward(query));
+        }
+        // A counted one-char search ("3/d", 4 keys) beats any uncounted
+        // query of three chars or more, so those are worth trying too
+        for query in index.single_chars() {
+            for count in 2..=MAX_WORD_COUNT {
+                commands.push(VimCommand::Counted(
+                    count,
+                    Box::new(VimCommand::SearchForward(query.clone())),
+                ));
+                commands.push(VimCommand::Counted(
+                    count,
+                    Box::new(VimCommand::SearchBackward(query.clone())),
+                ));
+            }
+        }
 
-    fn heuristic(&self, state: &State) -> usize {
-        let dx = (state.x as isize - self.level.target_pos.x as isize).abs();
-        let dy = (state.y as isize - self.level.target_pos.y as isize).abs();
-        (dx + dy) as usize
+        // Only use keys the level allows
+        commands.retain(|cmd| self.gate.is_command_allowed(cmd));
+        let mut steps: Vec<Vec<VimCommand>> = commands.into_iter().map(|cmd| vec![cmd]).collect();
+
+        // Text goals: type the goal over the wrong cells at (i) or after (a) the cursor
+        for (insert, x) in [
+            (VimCommand::InsertMode, node.state.x),
+            (VimCommand::Append, node.state.x + 1),
+        ] {
+            if self.gate.is_command_allowed(&insert)
+                && let Some(typed) = self.solver.goal_run(&node.state, x)
+            {
+                let mut step = vec![insert];
+                step.extend(typed.chars().map(VimCommand::InsertChar));
+                step.push(VimCommand::Escape);
+                steps.push(step);
+            }
+        }
+
+        for step in steps {
+            // Simulate
+            let mut temp_level = self.solver.level.clone();
+            temp_level.layout = node.state.layout.clone();
+
+            let mut temp_game = GameState::with_level_hash(temp_level, self.level_hash);
+            temp_game.player_pos = Position {
+                x: node.state.x,
+                y: node.state.y,
+            };
+            temp_game.last_find_command = node.state.last_find.clone();
+            if let Some((query, forward)) = &node.state.last_search {
+                temp_game.last_search_query = Some(query.clone());
+                temp_game.search_direction_forward = *forward;
+            }
+            temp_game.register = node.state.register.clone();
+            temp_game.viewport = node.state.viewport;
+            temp_game.marks = node.state.marks.clone();
+            temp_game.jumps = node.state.jumps.clone();
+
+            for cmd in &step {
+                temp_game.handle_command(cmd.clone());
+            }
+
+            if let GameStatus::GameOver = temp_game.status {
+                continue; // Died
+            }
+
+            let new_state = State {
+                x: temp_game.player_pos.x,
+                y: temp_game.player_pos.y,
+                layout: temp_game.current_level.layout,
+                last_find: temp_game.last_find_command,
+                last_search: temp_game
+                    .last_search_query
+                    .map(|query| (query, temp_game.search_direction_forward)),
+                register: temp_game.register,
+                viewport: temp_game.viewport,
+                marks: temp_game.marks,
+                jumps: if self.uses_jumps {
+                    temp_game.jumps
+                } else {
+                    JumpList::default()
+                },
+            };
+
+            if !self.visited.contains(&new_state) {
+                let cost: usize = step.iter().map(|cmd| cmd.keystrokes() as usize).sum();
+                let counts = step
+                    .iter()
+                    .filter(|cmd| matches!(cmd, VimCommand::Counted(..)))
+                    .count();
+                let mut new_path = node.path.clone();
+                new_path.extend(step);
+                self.seq += 1;
+
+                let new_node = Node {
+                    seq: self.seq,
+                    heuristic: self.solver.heuristic(&new_state),
+                    state: new_state,
+                    cost: node.cost + cost,
+                    counts: node.counts + counts,
+                    path: new_path,
+                };
+                self.queue.push(new_node);
+            }
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::level::{Goal, KeyPolicy};
 
     #[test]
     fn test_solve_simple_level() {
@@ -223,7 +732,10 @@ mod tests {
             layout: vec!["S...E".to_string()],
             start_pos: Position { x: 0, y: 0 },
             target_pos: Position { x: 4, y: 0 },
+            goal: Goal::Reach,
             allowed_keys: vec![],
+            key_policy: KeyPolicy::Reject,
+            undo_penalty: 0,
             trained_commands: vec![],
             tutorial_text: String::new(),
             par_time: 10.0,
@@ -233,10 +745,39 @@ mod tests {
         let solver = Solver::new(level);
         let solution = solver.solve();
 
-        assert!(solution.is_some());
-        let path = solution.unwrap();
+        assert!(solution.is_ok());
+        let path = solution.unwrap().commands;
         // Should be some moves to the right
-        assert!(path.len() > 0);
+        assert!(!path.is_empty());
+    }
+
+    #[test]
+    fn test_node_limit_is_not_unsolvable() {
+        let mut level = Level {
+            id: "test".to_string(),
+            name: "Test".to_string(),
+            description: "Test".to_string(),
+            layout: vec!["S...#E".to_string()],
+            start_pos: Position { x: 0, y: 0 },
+            target_pos: Position { x: 5, y: 0 },
+            goal: Goal::Reach,
+            allowed_keys: vec!["h".to_string(), "l".to_string()],
+            key_policy: KeyPolicy::Reject,
+            undo_penalty: 0,
+            trained_commands: vec![],
+            tutorial_text: String::new(),
+            par_time: 10.0,
+            par_keystrokes: 5,
+        };
+        assert_eq!(
+            Solver::new(level.clone()).solve(),
+            Err(SolveError::Unsolvable)
+        );
+
+        level.layout = vec!["S....E".to_string()];
+        let mut solver = Solver::new(level);
+        solver.max_nodes = 1;
+        assert_eq!(solver.solve(), Err(SolveError::NodeLimit(1)));
     }
 
     #[test]
@@ -248,7 +789,10 @@ mod tests {
             layout: vec!["S a E b".to_string()],
             start_pos: Position { x: 0, y: 0 },
             target_pos: Position { x: 4, y: 0 },
+            goal: Goal::Reach,
             allowed_keys: vec![],
+            key_policy: KeyPolicy::Reject,
+            undo_penalty: 0,
             trained_commands: vec![],
             tutorial_text: String::new(),
             par_time: 10.0,
@@ -258,8 +802,8 @@ mod tests {
         let solver = Solver::new(level);
         let solution = solver.solve();
 
-        assert!(solution.is_some());
-        let path = solution.unwrap();
+        assert!(solution.is_ok());
+        let path = solution.unwrap().commands;
         // Check if path contains FindNextChar('E')
         let has_find = path.iter().any(|cmd| matches!(cmd, VimCommand::FindNextChar('E')));
         assert!(has_find, "Solution should use FindNextChar('E')");
@@ -277,7 +821,10 @@ mod tests {
             ],
             start_pos: Position { x: 0, y: 0 },
             target_pos: Position { x: 4, y: 1 }, // E is at 4 on line 1
+            goal: Goal::Reach,
             allowed_keys: vec![],
+            key_policy: KeyPolicy::Reject,
+            undo_penalty: 0,
             trained_commands: vec![],
             tutorial_text: String::new(),
             par_time: 10.0,
@@ -287,10 +834,322 @@ mod tests {
         let solver = Solver::new(level);
         let solution = solver.solve();
 
-        assert!(solution.is_some());
-        let path = solution.unwrap();
+        assert!(solution.is_ok());
+        let solution = solution.unwrap();
+        // Searching costs its Enter too: `/E<CR>` is three keystrokes, and
+        // `+` lands on E in one
+        assert_eq!(solution.keystrokes, 1);
+        let has_search = solution
+            .commands
+            .iter()
+            .any(|cmd| matches!(cmd, VimCommand::SearchForward(_)));
+        assert!(!has_search, "Solution shouldn't use SearchForward");
+    }
+
+    #[test]
+    fn test_solve_with_find_over_walking() {
+        let level = Level {
+            id: "find_test".to_string(),
+            name: "Find Test".to_string(),
+            description: "Test".to_string(),
+            layout: vec!["S a b c d E f".to_string()],
+            start_pos: Position { x: 0, y: 0 },
+            target_pos: Position { x: 10, y: 0 },
+            goal: Goal::Reach,
+            // Word motions would tie with 'fE'
+            allowed_keys: vec!["l".to_string(), "f".to_string()],
+            key_policy: KeyPolicy::Reject,
+            undo_penalty: 0,
+            trained_commands: vec![],
+            tutorial_text: String::new(),
+            par_time: 10.0,
+            par_keystrokes: 2,
+        };
+
+        let solver = Solver::new(level);
+        let solution = solver.solve();
+
+        assert!(solution.is_ok());
+        let path = solution.unwrap().commands;
+        // Check if path contains FindNextChar('E')
+        let has_find = path.iter().any(|cmd| matches!(cmd, VimCommand::FindNextChar('E')));
+        assert!(has_find, "Solution should use FindNextChar('E')");
+    }
+
+    #[test]
+    fn test_solve_with_search_over_walking() {
+        let level = Level {
+            id: "search_test".to_string(),
+            name: "Search Test".to_string(),
+            description: "Test".to_string(),
+            layout: vec![
+                "S ... target ...".to_string(),
+                "... ... ... ... E".to_string()
+            ],
+            start_pos: Position { x: 0, y: 0 },
+            target_pos: Position { x: 16, y: 1 }, // E is at 16 on line 1
+            goal: Goal::Reach,
+            // Only walking competes with '/E' (j16l is four keystrokes)
+            allowed_keys: vec!["j".to_string(), "l".to_string(), "/".to_string()],
+            key_policy: KeyPolicy::Reject,
+            undo_penalty: 0,
+            trained_commands: vec![],
+            tutorial_text: String::new(),
+            par_time: 10.0,
+            par_keystrokes: 2,
+        };
+
+        let solver = Solver::new(level);
+        let solution = solver.solve();
+
+        assert!(solution.is_ok());
+        let path = solution.unwrap().commands;
         // Check if path contains SearchForward
         let has_search = path.iter().any(|cmd| matches!(cmd, VimCommand::SearchForward(_)));
         assert!(has_search, "Solution should use SearchForward");
     }
+
+    #[test]
+    fn test_solver_respects_allowed_keys() {
+        let level = Level {
+            id: "allowed_test".to_string(),
+            name: "Allowed Test".to_string(),
+            description: "Test".to_string(),
+            layout: vec!["S a E b".to_string()],
+            start_pos: Position { x: 0, y: 0 },
+            target_pos: Position { x: 4, y: 0 },
+            goal: Goal::Reach,
+            allowed_keys: vec!["l".to_string()],
+            key_policy: KeyPolicy::Reject,
+            undo_penalty: 0,
+            trained_commands: vec![],
+            tutorial_text: String::new(),
+            par_time: 10.0,
+            par_keystrokes: 4,
+        };
+
+        let solver = Solver::new(level);
+        let path = solver.solve().expect("Level should be solvable with l").commands;
+        assert_eq!(
+            path,
+            vec![VimCommand::Counted(4, Box::new(VimCommand::MoveRight))]
+        );
+    }
+
+    #[test]
+    fn test_solve_with_count() {
+        let level = Level {
+            id: "count_test".to_string(),
+            name: "Count Test".to_string(),
+            description: "Test".to_string(),
+            layout: vec!["S.........E".to_string()],
+            start_pos: Position { x: 0, y: 0 },
+            target_pos: Position { x: 10, y: 0 },
+            goal: Goal::Reach,
+            allowed_keys: vec!["l".to_string()],
+            key_policy: KeyPolicy::Reject,
+            undo_penalty: 0,
+            trained_commands: vec![],
+            tutorial_text: String::new(),
+            par_time: 10.0,
+            par_keystrokes: 3,
+        };
+
+        let solver = Solver::new(level);
+        let solution = solver.solve().expect("Level should be solvable");
+        assert_eq!(solution.keystrokes, 3, "10l is three keystrokes");
+    }
+
+    #[test]
+    fn test_solution_is_keystroke_optimal() {
+        let level = Level {
+            id: "cost_test".to_string(),
+            name: "Cost Test".to_string(),
+            description: "Test".to_string(),
+            layout: vec!["S door door door".to_string()],
+            start_pos: Position { x: 0, y: 0 },
+            target_pos: Position { x: 12, y: 0 },
+            goal: Goal::Reach,
+            allowed_keys: vec!["/".to_string(), "n".to_string()],
+            key_policy: KeyPolicy::Reject,
+            undo_penalty: 0,
+            trained_commands: vec![],
+            tutorial_text: String::new(),
+            par_time: 10.0,
+            par_keystrokes: 4,
+        };
+
+        // "/door<CR>nn" is 8 keys and "/d<CR>nn" is 5, but "3/d<CR>" is 4
+        let solution = Solver::new(level.clone()).solve().expect("Level should be solvable");
+        assert_eq!(solution.keystrokes, 4);
+        assert_eq!(
+            solution.keystrokes,
+            solution.commands.iter().map(|c| c.keystrokes()).sum::<u32>()
+        );
+
+        // Replaying the solution counts the same keystrokes
+        let mut game = GameState::new(level);
+        for cmd in solution.commands {
+            game.handle_command(cmd);
+        }
+        assert!(matches!(game.status, GameStatus::LevelComplete));
+        assert_eq!(game.keystrokes, 4);
+    }
+
+    #[test]
+    fn test_solve_on_a_small_screen() {
+        let mut layout = vec!["...".to_string(); 12];
+        layout[0] = ".S.".to_string();
+        layout[11] = ".E.".to_string();
+        let level = Level {
+            id: "screen_test".to_string(),
+            name: "Screen Test".to_string(),
+            description: "Test".to_string(),
+            layout,
+            start_pos: Position { x: 1, y: 0 },
+            target_pos: Position { x: 1, y: 11 },
+            goal: Goal::Reach,
+            allowed_keys: vec!["L".to_string(), "<C-f>".to_string()],
+            key_policy: KeyPolicy::Reject,
+            undo_penalty: 0,
+            trained_commands: vec![],
+            tutorial_text: String::new(),
+            par_time: 10.0,
+            par_keystrokes: 1,
+        };
+
+        // With the whole level on screen, L goes straight to the bottom
+        let full = Solver::new(level.clone()).solve().unwrap();
+        assert_eq!(full.keystrokes, 1);
+
+        // Four rows at a time, the view has to be paged down first
+        let solution = Solver::new(level.clone()).with_screen(4, 3).solve().unwrap();
+        assert!(solution.keystrokes > 1);
+        let mut game = GameState::new(level);
+        game.resize_viewport(4, 3);
+        for cmd in solution.commands {
+            game.handle_command(cmd);
+        }
+        assert!(matches!(game.status, GameStatus::LevelComplete));
+    }
+
+    #[test]
+    fn test_solve_with_line_numbers() {
+        let mut level = Level {
+            id: "line_test".to_string(),
+            name: "Line Test".to_string(),
+            description: "Test".to_string(),
+            layout: ["S", "~", "~", "E", "~", "."]
+                .iter()
+                .map(|row| row.to_string())
+                .collect(),
+            start_pos: Position { x: 0, y: 0 },
+            target_pos: Position { x: 0, y: 3 },
+            goal: Goal::Reach,
+            allowed_keys: vec!["j".to_string(), "G".to_string(), ":".to_string()],
+            key_policy: KeyPolicy::Reject,
+            undo_penalty: 0,
+            trained_commands: vec![],
+            tutorial_text: String::new(),
+            par_time: 10.0,
+            par_keystrokes: 2,
+        };
+
+        // 4G jumps the water; :4<CR> would too, but costs a key more
+        let solution = Solver::new(level.clone()).solve().unwrap();
+        assert_eq!(
+            solution.commands,
+            vec![VimCommand::Counted(4, Box::new(VimCommand::GotoLastLine))]
+        );
+        level.allowed_keys = vec![":".to_string()];
+        let solution = Solver::new(level).solve().unwrap();
+        assert_eq!(solution.commands, vec![VimCommand::GotoLine(4)]);
+    }
+
+    #[test]
+    fn test_solve_text_goal() {
+        let level = Level {
+            id: "text_test".to_string(),
+            name: "Text Test".to_string(),
+            description: "Test".to_string(),
+            layout: vec!["#S.~~~.E#".to_string()],
+            start_pos: Position { x: 1, y: 0 },
+            target_pos: Position { x: 7, y: 0 },
+            goal: Goal::TextThenReach {
+                layout: vec!["#S.ok~.E#".to_string()],
+            },
+            allowed_keys: vec!["l".to_string(), "a".to_string(), "f".to_string()],
+            key_policy: KeyPolicy::Reject,
+            undo_penalty: 0,
+            trained_commands: vec![],
+            tutorial_text: String::new(),
+            par_time: 10.0,
+            par_keystrokes: 7,
+        };
+
+        let solution = Solver::new(level).solve().expect("Level should be solvable");
+        let keys: Vec<String> = solution.commands.iter().map(|c| c.to_keys()).collect();
+        // Stepping onto the water drowns, so the typing starts with 'a'
+        assert_eq!(keys.join(" "), "l a o k <Esc> fE");
+        assert_eq!(solution.keystrokes, 7);
+    }
+
+    #[test]
+    fn test_solve_with_counted_delete() {
+        let level = Level {
+            id: "delete_test".to_string(),
+            name: "Delete Test".to_string(),
+            description: "Test".to_string(),
+            layout: vec!["#S.XX.X#E".to_string()],
+            start_pos: Position { x: 1, y: 0 },
+            target_pos: Position { x: 8, y: 0 },
+            goal: Goal::Text {
+                layout: vec!["#S.....#E".to_string()],
+            },
+            allowed_keys: vec!["l".to_string(), "x".to_string()],
+            key_policy: KeyPolicy::Reject,
+            undo_penalty: 0,
+            trained_commands: vec![],
+            tutorial_text: String::new(),
+            par_time: 10.0,
+            par_keystrokes: 2,
+        };
+
+        let solution = Solver::new(level).solve().expect("Level should be solvable");
+        let keys: Vec<String> = solution.commands.iter().map(|c| c.to_keys()).collect();
+        // The count reaches past the floor between, but not the wall
+        assert_eq!(keys.join(" "), "6x");
+    }
+
+    #[test]
+    fn test_solve_with_marks_and_jumps() {
+        let mut level = Level {
+            id: "mark_test".to_string(),
+            name: "Mark Test".to_string(),
+            description: "Test".to_string(),
+            layout: vec!["S.E".to_string(), "~~~".to_string(), "*..".to_string()],
+            start_pos: Position { x: 0, y: 0 },
+            target_pos: Position { x: 2, y: 0 },
+            goal: Goal::TextThenReach {
+                layout: vec!["S.E".to_string(), "~~~".to_string(), "...".to_string()],
+            },
+            // '}' only goes down, over the water
+            allowed_keys: ["l", "}", "x", "m", "`"].map(String::from).to_vec(),
+            key_policy: KeyPolicy::Reject,
+            undo_penalty: 0,
+            trained_commands: vec![],
+            tutorial_text: String::new(),
+            par_time: 10.0,
+            par_keystrokes: 8,
+        };
+
+        let solution = Solver::new(level.clone()).solve().expect("Level should be solvable");
+        let keys: Vec<String> = solution.commands.iter().map(|c| c.to_keys()).collect();
+        assert_eq!(keys.join(" "), "ma } x `a 2l");
+
+        level.allowed_keys = ["l", "}", "x", "<C-o>"].map(String::from).to_vec();
+        let solution = Solver::new(level).solve().expect("Level should be solvable");
+        let keys: Vec<String> = solution.commands.iter().map(|c| c.to_keys()).collect();
+        assert_eq!(keys.join(" "), "} x <C-o> 2l");
+    }
 }
@@ -0,0 +1,76 @@
+//! Small key/value store for data that outlives a session: one JSON file
+//! per key on native, `localStorage` in the browser.
+//!
+//! Native files live under `$VIMGAME_DATA_DIR`, falling back to
+//! `~/.vimgame` (or `./.vimgame` when there is no home directory).
+
+/// Stores `value` under `key`. Keys may contain `/` to group entries.
+pub fn save(key: &str, value: &str) -> Result<(), String> {
+    backend::save(key, value)
+}
+
+/// Returns the value stored under `key`, if any.
+pub fn load(key: &str) -> Option<String> {
+    backend::load(key)
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+mod backend {
+    use std::fs;
+    use std::path::PathBuf;
+
+    fn data_dir() -> PathBuf {
+        if let Some(dir) = std::env::var_os("VIMGAME_DATA_DIR") {
+            return PathBuf::from(dir);
+        }
+        match std::env::var_os("HOME") {
+            Some(home) => PathBuf::from(home).join(".vimgame"),
+            None => PathBuf::from(".vimgame"),
+        }
+    }
+
+    fn path(key: &str) -> PathBuf {
+        data_dir().join(format!("{}.json", key))
+    }
+
+    pub fn save(key: &str, value: &str) -> Result<(), String> {
+        let path = path(key);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(|e| format!("{}: {}", parent.display(), e))?;
+        }
+        fs::write(&path, value).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    pub fn load(key: &str) -> Option<String> {
+        fs::read_to_string(path(key)).ok()
+    }
+}
+
+// Backed by the `vimgame_storage` plugin registered in assets/index.html.
+#[cfg(target_arch = "wasm32")]
+mod backend {
+    unsafe extern "C" {
+        fn vimgame_storage_set(key: *const u8, key_len: usize, value: *const u8, value_len: usize);
+        /// Byte length of the stored value, or -1 if there is none.
+        fn vimgame_storage_len(key: *const u8, key_len: usize) -> i32;
+        fn vimgame_storage_get(key: *const u8, key_len: usize, out: *mut u8);
+    }
+
+    /// Checked by the miniquad plugin loader against the JS plugin's version.
+    #[unsafe(no_mangle)]
+    pub extern "C" fn vimgame_storage_crate_version() -> u32 {
+        1
+    }
+
+    pub fn save(key: &str, value: &str) -> Result<(), String> {
+        unsafe { vimgame_storage_set(key.as_ptr(), key.len(), value.as_ptr(), value.len()) };
+        Ok(())
+    }
+
+    pub fn load(key: &str) -> Option<String> {
+        let len = unsafe { vimgame_storage_len(key.as_ptr(), key.len()) };
+        let mut bytes = vec![0u8; usize::try_from(len).ok()?];
+        unsafe { vimgame_storage_get(key.as_ptr(), key.len(), bytes.as_mut_ptr()) };
+        String::from_utf8(bytes).ok()
+    }
+}
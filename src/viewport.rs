@@ -0,0 +1,221 @@
+//! The window onto a level: which rows and columns fit on screen, and the
+//! rules Vim uses to scroll it.
+
+use crate::level::Position;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Viewport {
+    /// First visible row.
+    pub top: usize,
+    /// First visible column.
+    pub left: usize,
+    pub rows: usize,
+    pub cols: usize,
+}
+
+impl Viewport {
+    pub fn new(rows: usize, cols: usize) -> Self {
+        Self {
+            top: 0,
+            left: 0,
+            rows: rows.max(1),
+            cols: cols.max(1),
+        }
+    }
+
+    /// Last visible row.
+    pub fn bottom(&self) -> usize {
+        self.top + self.rows - 1
+    }
+
+    pub fn contains(&self, pos: Position) -> bool {
+        (self.top..self.top + self.rows).contains(&pos.y)
+            && (self.left..self.left + self.cols).contains(&pos.x)
+    }
+
+    /// Scrolls just enough to bring `pos` into view.
+    pub fn follow(&mut self, pos: Position) {
+        if pos.y < self.top {
+            self.top = pos.y;
+        } else if pos.y > self.bottom() {
+            self.top = pos.y + 1 - self.rows;
+        }
+        if pos.x < self.left {
+            self.left = pos.x;
+        } else if pos.x >= self.left + self.cols {
+            self.left = pos.x + 1 - self.cols;
+        }
+    }
+
+    /// Keeps the view from scrolling past the end of a `height` x `width` level.
+    pub fn clamp(&mut self, height: usize, width: usize) {
+        self.top = self.top.min(height.saturating_sub(self.rows));
+        self.left = self.left.min(width.saturating_sub(self.cols));
+    }
+
+    /// Scrolls by `delta` rows (positive is down) within a level of
+    /// `height` rows. Returns how many rows it actually moved.
+    pub fn scroll(&mut self, delta: isize, height: usize) -> isize {
+        let max_top = height.saturating_sub(self.rows);
+        let top = self.top.saturating_add_signed(delta).min(max_top);
+        let moved = top as isize - self.top as isize;
+        self.top = top;
+        moved
+    }
+
+    /// `zt`: puts row `y` at the top of the view.
+    pub fn place_top(&mut self, y: usize, height: usize) {
+        self.top = y;
+        self.clamp_rows(height);
+    }
+
+    /// `zz`: puts row `y` in the middle of the view.
+    pub fn place_center(&mut self, y: usize, height: usize) {
+        self.top = y.saturating_sub(self.rows / 2);
+        self.clamp_rows(height);
+    }
+
+    /// `zb`: puts row `y` at the bottom of the view.
+    pub fn place_bottom(&mut self, y: usize, height: usize) {
+        self.top = (y + 1).saturating_sub(self.rows);
+        self.clamp_rows(height);
+    }
+
+    /// Where the view is in a level of `height` rows, as Vim's ruler shows
+    /// it: `All`, `Top`, `Bot` or how far down it is in percent.
+    pub fn ruler(&self, height: usize) -> String {
+        if height <= self.rows {
+            "All".to_string()
+        } else if self.top == 0 {
+            "Top".to_string()
+        } else if self.bottom() + 1 >= height {
+            "Bot".to_string()
+        } else {
+            format!("{}%", self.top * 100 / (height - self.rows))
+        }
+    }
+
+    fn clamp_rows(&mut self, height: usize) {
+        self.top = self.top.min(height.saturating_sub(self.rows));
+    }
+}
+
+/// How the gutter numbers lines, like Vim's `number` and `relativenumber`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineNumbers {
+    Off,
+    Absolute,
+    Relative,
+    /// Relative, except the cursor's line shows its own number.
+    Hybrid,
+}
+
+impl LineNumbers {
+    pub fn next(self) -> Self {
+        match self {
+            LineNumbers::Off => LineNumbers::Absolute,
+            LineNumbers::Absolute => LineNumbers::Relative,
+            LineNumbers::Relative => LineNumbers::Hybrid,
+            LineNumbers::Hybrid => LineNumbers::Off,
+        }
+    }
+
+    /// From Vim's `number` and `relativenumber` options; both together
+    /// give the hybrid.
+    pub fn from_options(number: bool, relative: bool) -> Self {
+        match (number, relative) {
+            (false, false) => LineNumbers::Off,
+            (true, false) => LineNumbers::Absolute,
+            (false, true) => LineNumbers::Relative,
+            (true, true) => LineNumbers::Hybrid,
+        }
+    }
+
+    /// `(number, relativenumber)`
+    pub fn options(self) -> (bool, bool) {
+        match self {
+            LineNumbers::Off => (false, false),
+            LineNumbers::Absolute => (true, false),
+            LineNumbers::Relative => (false, true),
+            LineNumbers::Hybrid => (true, true),
+        }
+    }
+
+    /// The number shown beside row `y` with the cursor on row `cursor`.
+    /// Lines count from 1, as `:{N}` and `{N}G` do.
+    pub fn label(self, y: usize, cursor: usize) -> Option<String> {
+        match self {
+            LineNumbers::Off => None,
+            LineNumbers::Absolute => Some((y + 1).to_string()),
+            LineNumbers::Relative => Some(y.abs_diff(cursor).to_string()),
+            LineNumbers::Hybrid if y == cursor => Some((y + 1).to_string()),
+            LineNumbers::Hybrid => Some(y.abs_diff(cursor).to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_follow_and_scroll() {
+        let mut view = Viewport::new(5, 10);
+        view.follow(Position { x: 12, y: 7 });
+        assert_eq!((view.top, view.left), (3, 3));
+        assert_eq!(view.bottom(), 7);
+        assert!(view.contains(Position { x: 12, y: 7 }));
+        view.follow(Position { x: 0, y: 1 });
+        assert_eq!((view.top, view.left), (1, 0));
+
+        // A 20-row level can scroll down to row 15 at most
+        assert_eq!(view.scroll(10, 20), 10);
+        assert_eq!(view.scroll(10, 20), 4);
+        assert_eq!(view.top, 15);
+        assert_eq!(view.scroll(-20, 20), -15);
+
+        // Nothing scrolls when the whole level fits
+        assert_eq!(view.scroll(3, 4), 0);
+    }
+
+    #[test]
+    fn test_place() {
+        let mut view = Viewport::new(5, 10);
+        view.place_top(8, 20);
+        assert_eq!(view.top, 8);
+        view.place_center(8, 20);
+        assert_eq!(view.top, 6);
+        view.place_bottom(8, 20);
+        assert_eq!(view.top, 4);
+
+        // Never past either end of the level
+        view.place_top(18, 20);
+        assert_eq!(view.top, 15);
+        view.place_center(1, 20);
+        assert_eq!(view.top, 0);
+    }
+
+    #[test]
+    fn test_ruler() {
+        let mut view = Viewport::new(5, 10);
+        assert_eq!(view.ruler(5), "All");
+        assert_eq!(view.ruler(20), "Top");
+        view.scroll(6, 20);
+        assert_eq!(view.ruler(20), "40%");
+        view.scroll(20, 20);
+        assert_eq!(view.ruler(20), "Bot");
+    }
+
+    #[test]
+    fn test_line_numbers() {
+        assert_eq!(LineNumbers::Off.label(3, 5), None);
+        assert_eq!(LineNumbers::Absolute.label(3, 5).unwrap(), "4");
+        assert_eq!(LineNumbers::Relative.label(3, 5).unwrap(), "2");
+        assert_eq!(LineNumbers::Relative.label(5, 5).unwrap(), "0");
+        assert_eq!(LineNumbers::Hybrid.label(7, 5).unwrap(), "2");
+        assert_eq!(LineNumbers::Hybrid.label(5, 5).unwrap(), "6");
+        assert_eq!(LineNumbers::Hybrid.next(), LineNumbers::Off);
+        assert_eq!(LineNumbers::from_options(true, true), LineNumbers::Hybrid);
+        assert_eq!(LineNumbers::Relative.options(), (false, true));
+    }
+}